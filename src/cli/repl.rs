@@ -1,9 +1,12 @@
-use crate::storage::Kline;
+use crate::constants::keys::DEFAULT_PAGE_SIZE;
+use crate::storage::{ChannelFilter, Fields, KeyScan, Kline, StreamId};
 use crate::error::{Result};
 use base64::Engine as _;
 use base64::engine::general_purpose;
 use rustyline::{DefaultEditor, error::ReadlineError};
+use std::future::Future;
 use std::sync::Arc;
+use std::time::Duration;
 
 pub fn repl(db: Arc<Kline>) -> Result<()> {
     let mut rl = DefaultEditor::new().expect("Failed to initialize rustyline");
@@ -47,17 +50,313 @@ pub fn repl(db: Arc<Kline>) -> Result<()> {
                     println!("Error deleting key: {}", err);
                 }
             }
-            ["keys"] => {
-                match db.keys() {
-                    Ok(key_list) => {
-                        for key in key_list {
-                            match std::str::from_utf8(&key) {
-                                Ok(k) => println!("{}", k),
-                                Err(_) => println!("{}", general_purpose::STANDARD.encode(&key)),
-                            }
+            ["incr", key] => print_result(db.incr_by(key.as_bytes(), 1)),
+            ["incr", key, by] => match by.parse::<i64>() {
+                Ok(by) => print_result(db.incr_by(key.as_bytes(), by)),
+                Err(_) => match by.parse::<f64>() {
+                    Ok(by) => print_result(db.incr_by_float(key.as_bytes(), by)),
+                    Err(_) => println!("Error: increment must be a number"),
+                },
+            },
+            ["decr", key] => print_result(db.decr_by(key.as_bytes(), 1)),
+            ["decr", key, by] => match by.parse::<i64>() {
+                Ok(by) => print_result(db.decr_by(key.as_bytes(), by)),
+                Err(_) => println!("Error: decrement must be an integer"),
+            },
+            ["append", key, value] => print_result(db.append(key.as_bytes(), value.as_bytes())),
+            ["strlen", key] => print_result(db.strlen(key.as_bytes())),
+            ["getset", key, value] => {
+                match db.get_set(key.as_bytes().to_vec(), value.as_bytes().to_vec()) {
+                    Ok(Some(val)) => println!("{}", String::from_utf8_lossy(&val)),
+                    Ok(None) => println!("(null)"),
+                    Err(err) => println!("Error: {}", err),
+                }
+            }
+            ["getrange", key, range] => {
+                let bounds = range.split_once(' ')
+                    .and_then(|(start, end)| Some((start.parse::<i64>().ok()?, end.trim().parse::<i64>().ok()?)));
+                match bounds {
+                    Some((start, end)) => match db.get_range(key.as_bytes(), start, end) {
+                        Ok(Some(val)) => println!("{}", String::from_utf8_lossy(&val)),
+                        Ok(None) => println!("(null)"),
+                        Err(err) => println!("Error: {}", err),
+                    },
+                    None => println!("Usage: getrange <key> <start> <end>"),
+                }
+            }
+            ["setrange", key, rest] => {
+                match rest.split_once(' ').and_then(|(offset, value)| Some((offset.parse::<usize>().ok()?, value))) {
+                    Some((offset, value)) => print_result(db.set_range(key.as_bytes(), offset, value.as_bytes())),
+                    None => println!("Usage: setrange <key> <offset> <value>"),
+                }
+            }
+            ["rename", old, rest] => {
+                let args: Vec<&str> = rest.split_whitespace().collect();
+                let result = match args.as_slice() {
+                    [new] => Some(db.rename(old.as_bytes(), new.as_bytes(), false)),
+                    [new, "overwrite"] => Some(db.rename(old.as_bytes(), new.as_bytes(), true)),
+                    _ => None,
+                };
+                match result {
+                    Some(Ok(())) => println!("OK"),
+                    Some(Err(err)) => println!("Error: {}", err),
+                    None => println!("Usage: rename <old> <new> [overwrite]"),
+                }
+            }
+            ["copy", src, dst] => match db.copy(src.as_bytes(), dst.as_bytes()) {
+                Ok(()) => println!("OK"),
+                Err(err) => println!("Error: {}", err),
+            },
+            ["expire", key, secs] => match secs.parse::<u64>() {
+                Ok(secs) => match db.expire(key.as_bytes(), Duration::from_secs(secs)) {
+                    Ok(true) => println!("OK"),
+                    Ok(false) => println!("(null)"),
+                    Err(err) => println!("Error: {}", err),
+                },
+                Err(_) => println!("Error: TTL must be a whole number of seconds"),
+            },
+            ["ttl", key] => match db.ttl(key.as_bytes()) {
+                Ok(Some(ttl)) => println!("{}", ttl.as_millis().div_ceil(1000)),
+                Ok(None) => println!("(no ttl)"),
+                Err(err) => println!("Error: {}", err),
+            },
+            ["persist", key] => match db.persist(key.as_bytes()) {
+                Ok(persisted) => println!("{}", persisted),
+                Err(err) => println!("Error: {}", err),
+            },
+            ["deleteprefix", prefix] => print_result(db.delete_prefix(prefix.as_bytes())),
+            ["deleterange", start, end] => print_result(db.delete_range(start.as_bytes(), end.as_bytes())),
+            ["renameprefix", from, to] => print_result(db.rename_prefix(from.as_bytes(), to.as_bytes())),
+            ["history", key] => match db.history(key.as_bytes()) {
+                Ok(versions) if versions.is_empty() => println!("(empty)"),
+                Ok(versions) => {
+                    for version in versions {
+                        let value = version.value.map_or_else(
+                            || "(deleted)".to_string(),
+                            |value| String::from_utf8_lossy(&value).to_string(),
+                        );
+                        println!("{} {} {}", version.version, version.timestamp, value);
+                    }
+                }
+                Err(err) => println!("Error: {}", err),
+            },
+            ["type", key] => match db.key_type(key.as_bytes()) {
+                Ok(Some(kind)) => println!("{}", kind),
+                Ok(None) => println!("none"),
+                Err(err) => println!("Error: {}", err),
+            },
+            ["lpush", key, items] => print_result(db.lpush(key.as_bytes(), words(items))),
+            ["rpush", key, items] => print_result(db.rpush(key.as_bytes(), words(items))),
+            ["lpop", key] => print_values(db.lpop(key.as_bytes(), 1)),
+            ["rpop", key] => print_values(db.rpop(key.as_bytes(), 1)),
+            [op @ ("lpop" | "rpop"), key, count] => match count.parse::<usize>() {
+                Ok(count) if *op == "lpop" => print_values(db.lpop(key.as_bytes(), count)),
+                Ok(count) => print_values(db.rpop(key.as_bytes(), count)),
+                Err(_) => println!("Error: count must be a non-negative integer"),
+            },
+            ["lrange", key, range] => {
+                let bounds = range.split_once(' ')
+                    .and_then(|(start, stop)| Some((start.parse::<i64>().ok()?, stop.trim().parse::<i64>().ok()?)));
+                match bounds {
+                    Some((start, stop)) => print_values(db.lrange(key.as_bytes(), start, stop)),
+                    None => println!("Usage: lrange <key> <start> <stop>"),
+                }
+            }
+            ["llen", key] => print_result(db.llen(key.as_bytes())),
+            ["blpop", _, _] => {
+                let args: Vec<&str> = input.split_whitespace().skip(1).collect();
+                let (timeout, keys) = args.split_last().expect("two arguments matched");
+                match timeout.parse::<u64>() {
+                    Ok(timeout) => {
+                        let keys: Vec<&[u8]> = keys.iter().map(|key| key.as_bytes()).collect();
+                        match block_on(db.blpop(&keys, Duration::from_secs(timeout))) {
+                            Ok(Some((key, item))) => println!("{} {}", String::from_utf8_lossy(&key), String::from_utf8_lossy(&item)),
+                            Ok(None) => println!("(null)"),
+                            Err(err) => println!("Error: {}", err),
                         }
                     }
-                    Err(err) => println!("Error getting keys: {}", err),
+                    Err(_) => println!("Usage: blpop <key>... <timeout-secs>"),
+                }
+            }
+            ["lrem", key, rest] => match rest.split_once(' ') {
+                Some((count, item)) => match count.parse::<i64>() {
+                    Ok(count) => print_result(db.lrem(key.as_bytes(), count, item.as_bytes())),
+                    Err(_) => println!("Usage: lrem <key> <count> <item>"),
+                },
+                None => println!("Usage: lrem <key> <count> <item>"),
+            },
+            ["hset", key, rest] => match rest.split_once(' ') {
+                Some((field, value)) => {
+                    print_result(db.hset(key.as_bytes(), vec![(field.as_bytes().to_vec(), value.as_bytes().to_vec())]))
+                }
+                None => println!("Usage: hset <key> <field> <value>"),
+            },
+            ["hget", key, field] => match db.hget(key.as_bytes(), field.as_bytes()) {
+                Ok(Some(val)) => println!("{}", String::from_utf8_lossy(&val)),
+                Ok(None) => println!("(null)"),
+                Err(err) => println!("Error: {}", err),
+            },
+            ["hdel", key, fields] => print_result(db.hdel(key.as_bytes(), &words(fields))),
+            ["hgetall", key] => match db.hgetall(key.as_bytes()) {
+                Ok(fields) => {
+                    for (field, value) in fields {
+                        println!("{} {}", String::from_utf8_lossy(&field), String::from_utf8_lossy(&value));
+                    }
+                }
+                Err(err) => println!("Error: {}", err),
+            },
+            ["sadd", key, members] => print_result(db.sadd(key.as_bytes(), words(members))),
+            ["srem", key, members] => print_result(db.srem(key.as_bytes(), &words(members))),
+            ["smembers", key] => print_values(db.smembers(key.as_bytes())),
+            ["sismember", key, member] => print_result(db.sismember(key.as_bytes(), member.as_bytes())),
+            ["zadd", key, pairs] => {
+                let tokens: Vec<&str> = pairs.split_whitespace().collect();
+                let members: Option<Vec<(f64, Vec<u8>)>> = tokens
+                    .chunks(2)
+                    .map(|pair| match pair {
+                        [score, member] => Some((score.parse::<f64>().ok()?, member.as_bytes().to_vec())),
+                        _ => None,
+                    })
+                    .collect();
+                match members {
+                    Some(members) => print_result(db.zadd(key.as_bytes(), members)),
+                    None => println!("Usage: zadd <key> <score> <member> [<score> <member>...]"),
+                }
+            }
+            ["zrem", key, members] => print_result(db.zrem(key.as_bytes(), &words(members))),
+            ["zscore", key, member] => match db.zscore(key.as_bytes(), member.as_bytes()) {
+                Ok(Some(score)) => println!("{}", score),
+                Ok(None) => println!("(null)"),
+                Err(err) => println!("Error: {}", err),
+            },
+            ["zrank", key, member] => match db.zrank(key.as_bytes(), member.as_bytes()) {
+                Ok(Some(rank)) => println!("{}", rank),
+                Ok(None) => println!("(null)"),
+                Err(err) => println!("Error: {}", err),
+            },
+            ["zcard", key] => print_result(db.zcard(key.as_bytes())),
+            ["zrange", key, range] => {
+                let bounds = range.split_once(' ')
+                    .and_then(|(start, stop)| Some((start.parse::<i64>().ok()?, stop.trim().parse::<i64>().ok()?)));
+                match bounds {
+                    Some((start, stop)) => print_scored(db.zrange(key.as_bytes(), start, stop)),
+                    None => println!("Usage: zrange <key> <start> <stop>"),
+                }
+            }
+            ["zrangebyscore", key, range] => {
+                let bounds = range.split_once(' ')
+                    .and_then(|(min, max)| Some((min.parse::<f64>().ok()?, max.trim().parse::<f64>().ok()?)));
+                match bounds {
+                    Some((min, max)) => print_scored(db.zrange_by_score(key.as_bytes(), min, max, None)),
+                    None => println!("Usage: zrangebyscore <key> <min> <max>"),
+                }
+            }
+            ["zpopmin", key] => print_scored(db.zpopmin(key.as_bytes(), 1)),
+            ["zpopmin", key, count] => match count.parse::<usize>() {
+                Ok(count) => print_scored(db.zpopmin(key.as_bytes(), count)),
+                Err(_) => println!("Error: count must be a non-negative integer"),
+            },
+            ["xadd", key, rest] => match pairs(rest) {
+                Some(fields) if !fields.is_empty() => match db.xadd(key.as_bytes(), None, fields) {
+                    Ok(id) => println!("{}", id),
+                    Err(err) => println!("Error: {}", err),
+                },
+                _ => println!("Usage: xadd <key> <field> <value> [<field> <value>...]"),
+            },
+            ["xrange", key, range] => {
+                let bounds = range.split_once(' ')
+                    .and_then(|(start, end)| Some((StreamId::parse_bound(start)?, StreamId::parse_bound(end.trim())?)));
+                match bounds {
+                    Some((start, end)) => print_entries(db.xrange(key.as_bytes(), start, end, None)),
+                    None => println!("Usage: xrange <key> <start> <end>"),
+                }
+            }
+            ["xread", key, rest] => {
+                let args: Vec<&str> = rest.split_whitespace().collect();
+                let parse_after = |after: &str| match after {
+                    "$" => db.xlast_id(key.as_bytes()).ok(),
+                    _ => StreamId::parse_bound(after),
+                };
+                match args.as_slice() {
+                    [after] => match parse_after(after) {
+                        Some(after) => print_entries(db.xread(key.as_bytes(), after, None)),
+                        None => println!("Error: invalid stream ID"),
+                    },
+                    [after, block_ms] => match (parse_after(after), block_ms.parse::<u64>()) {
+                        (Some(after), Ok(block_ms)) => print_entries(block_on(
+                            db.xread_blocking(key.as_bytes(), after, None, Duration::from_millis(block_ms)),
+                        )),
+                        _ => println!("Usage: xread <key> <after-id> [block-ms]"),
+                    },
+                    _ => println!("Usage: xread <key> <after-id> [block-ms]"),
+                }
+            }
+            ["xlen", key] => print_result(db.xlen(key.as_bytes())),
+            ["xtrim", key, max_len] => match max_len.parse::<usize>() {
+                Ok(max_len) => print_result(db.xtrim_max_len(key.as_bytes(), max_len)),
+                Err(_) => println!("Usage: xtrim <key> <max-len>"),
+            },
+            ["xgroup", key, rest] => {
+                let args: Vec<&str> = rest.split_whitespace().collect();
+                let created = match args.as_slice() {
+                    [group] | [group, "$"] => Some(db.xgroup_create(key.as_bytes(), group, None)),
+                    [group, start] => StreamId::parse_bound(start).map(|start| db.xgroup_create(key.as_bytes(), group, Some(start))),
+                    _ => None,
+                };
+                match created {
+                    Some(Ok(true)) => println!("OK"),
+                    Some(Ok(false)) => println!("Error: consumer group already exists"),
+                    Some(Err(err)) => println!("Error: {}", err),
+                    None => println!("Usage: xgroup <key> <group> [start-id|$]"),
+                }
+            }
+            ["xreadgroup", key, rest] => {
+                let args: Vec<&str> = rest.split_whitespace().collect();
+                match args.as_slice() {
+                    [group, consumer] => print_entries(db.xreadgroup(key.as_bytes(), group, consumer, None)),
+                    [group, consumer, block_ms] => match block_ms.parse::<u64>() {
+                        Ok(block_ms) => print_entries(block_on(
+                            db.xreadgroup_blocking(key.as_bytes(), group, consumer, None, Duration::from_millis(block_ms)),
+                        )),
+                        Err(_) => println!("Usage: xreadgroup <key> <group> <consumer> [block-ms]"),
+                    },
+                    _ => println!("Usage: xreadgroup <key> <group> <consumer> [block-ms]"),
+                }
+            }
+            ["xack", key, rest] => {
+                let args: Vec<&str> = rest.split_whitespace().collect();
+                let ids: Option<Vec<StreamId>> = args.iter().skip(1).map(|id| id.parse().ok()).collect();
+                match (args.first(), ids) {
+                    (Some(group), Some(ids)) if !ids.is_empty() => print_result(db.xack(key.as_bytes(), group, &ids)),
+                    _ => println!("Usage: xack <key> <group> <id>..."),
+                }
+            }
+            ["xpending", key, group] => match db.xpending(key.as_bytes(), group) {
+                Ok(pending) if pending.is_empty() => println!("(empty)"),
+                Ok(pending) => {
+                    for (id, entry) in pending {
+                        println!("{} {} deliveries={}", id, entry.consumer, entry.delivery_count);
+                    }
+                }
+                Err(err) => println!("Error: {}", err),
+            },
+            ["publish", channel, message] => {
+                let receivers = db.publish(channel, message.as_bytes().to_vec());
+                println!("(delivered to {} subscribers)", receivers);
+            }
+            [cmd @ ("subscribe" | "psubscribe"), _, ..] => {
+                let names = input.split_whitespace().skip(1).map(str::to_string);
+                let filters = if *cmd == "subscribe" {
+                    names.map(ChannelFilter::Channel).collect()
+                } else {
+                    names.map(ChannelFilter::Pattern).collect()
+                };
+                listen(&db, filters);
+            }
+            ["keys", ..] => {
+                match KeysCommand::parse(input.split_whitespace().skip(1)) {
+                    Some(command) => command.run(&db),
+                    None => println!("Usage: keys [pattern] [prefix=P] [limit=N] [cursor=C] [values] [count]"),
                 }
             }
             ["help"] => {
@@ -65,13 +364,214 @@ pub fn repl(db: Arc<Kline>) -> Result<()> {
                 println!("  put <key> <value> - Store a key-value pair");
                 println!("  get <key> - Retrieve a value by key");
                 println!("  delete <key> - Remove a key-value pair");
-                println!("  keys - List all keys in the database");
+                println!("  incr <key> [by] - Increment a numeric value (integer or float)");
+                println!("  decr <key> [by] - Decrement an integer value");
+                println!("  append <key> <value> - Append to a value and print its new length");
+                println!("  getrange <key> <start> <end> - Print a byte range (inclusive, negative counts from end)");
+                println!("  setrange <key> <offset> <value> - Overwrite part of a value");
+                println!("  strlen <key> - Print the length of a value");
+                println!("  getset <key> <value> - Store a value and print the previous one");
+                println!("  type <key> - Print the type of the value at a key");
+                println!("  rename <old> <new> [overwrite] - Atomically move a key");
+                println!("  copy <src> <dst> - Copy a key to a new key");
+                println!("  expire <key> <secs> - Expire a key after secs seconds");
+                println!("  ttl <key> - Seconds until a key expires");
+                println!("  persist <key> - Remove a key's expiry");
+                println!("  deleteprefix <prefix> - Delete every key under a prefix and print how many were removed");
+                println!("  deleterange <start> <end> - Delete keys from start up to (not including) end");
+                println!("  renameprefix <from> <to> - Move every key under a prefix and print how many moved");
+                println!("  history <key> - Print retained versions of a key (version, timestamp, value)");
+                println!("  lpush|rpush <key> <item>... - Push items onto a list");
+                println!("  lpop|rpop <key> [count] - Pop items from a list");
+                println!("  lrange <key> <start> <stop> - Print list items (inclusive)");
+                println!("  llen <key> - Print the length of a list");
+                println!("  blpop <key>... <timeout-secs> - Pop the first item of the first non-empty list, waiting if needed");
+                println!("  lrem <key> <count> <item> - Remove occurrences of an item (0 for all, negative from the tail)");
+                println!("  hset <key> <field> <value> - Set a hash field");
+                println!("  hget <key> <field> - Get a hash field");
+                println!("  hdel <key> <field>... - Delete hash fields");
+                println!("  hgetall <key> - Print all fields of a hash");
+                println!("  sadd|srem <key> <member>... - Add or remove set members");
+                println!("  smembers <key> - Print all members of a set");
+                println!("  sismember <key> <member> - Check set membership");
+                println!("  zadd <key> <score> <member>... - Add sorted set members");
+                println!("  zrem <key> <member>... - Remove sorted set members");
+                println!("  zscore|zrank <key> <member> - Print a member's score or rank");
+                println!("  zcard <key> - Print the size of a sorted set");
+                println!("  zrange <key> <start> <stop> - Print members by rank (inclusive)");
+                println!("  zrangebyscore <key> <min> <max> - Print members by score (inclusive)");
+                println!("  zpopmin <key> [count] - Pop the lowest-scored members");
+                println!("  xadd <key> <field> <value>... - Append an entry to a stream");
+                println!("  xrange <key> <start> <end> - Print stream entries (- and + for the ends)");
+                println!("  xread <key> <after-id|$> [block-ms] - Print entries after an ID, optionally waiting");
+                println!("  xlen <key> - Print the number of entries in a stream");
+                println!("  xtrim <key> <max-len> - Keep only the newest entries");
+                println!("  xgroup <key> <group> [start-id|$] - Create a consumer group");
+                println!("  xreadgroup <key> <group> <consumer> [block-ms] - Read new entries for a consumer");
+                println!("  xack <key> <group> <id>... - Acknowledge delivered entries");
+                println!("  xpending <key> <group> - Print unacknowledged entries");
+                println!("  publish <channel> <message> - Publish a message to a channel");
+                println!("  subscribe <channel>... - Print messages on channels until Enter is pressed");
+                println!("  psubscribe <pattern>... - Like subscribe, with glob patterns (* and ?)");
+                println!("  keys [pattern] [prefix=P] [limit=N] [cursor=C] [values] [count] - List keys (all pages unless limit is set)");
                 println!("  exit - Exit the REPL");
             }
             ["exit"] => break,
-            _ => println!("Unknown command. Use put/get/delete/exit."),
+            _ => println!("Unknown command. Type 'help' for available commands."),
         }
     }
 
     Ok(())
 }
+
+/// Options of the `keys` command.
+struct KeysCommand {
+    scan: KeyScan,
+    limit: Option<usize>,
+    values: bool,
+    count: bool,
+}
+
+impl KeysCommand {
+    fn parse<'a>(args: impl Iterator<Item = &'a str>) -> Option<Self> {
+        let mut command = Self { scan: KeyScan::default(), limit: None, values: false, count: false };
+        for arg in args {
+            match arg.split_once('=') {
+                Some(("prefix", prefix)) => command.scan.prefix = prefix.as_bytes().to_vec(),
+                Some(("limit", limit)) => command.limit = Some(limit.parse().ok().filter(|&limit| limit > 0)?),
+                Some(("cursor", cursor)) => command.scan.after = Some(general_purpose::URL_SAFE_NO_PAD.decode(cursor).ok()?),
+                None if arg == "values" => command.values = true,
+                None if arg == "count" => command.count = true,
+                None if command.scan.pattern.is_none() => command.scan.pattern = Some(arg.as_bytes().to_vec()),
+                _ => return None,
+            }
+        }
+        Some(command)
+    }
+
+    /// Prints one page with its cursor if `limit` is set, otherwise every
+    /// match, fetched a page at a time so no single read holds the lock for
+    /// the whole listing.
+    fn run(mut self, db: &Kline) {
+        if self.count {
+            print_result(db.count_keys(&self.scan));
+            return;
+        }
+        loop {
+            let page = match db.scan_keys(&self.scan, self.limit.unwrap_or(DEFAULT_PAGE_SIZE), self.values) {
+                Ok(page) => page,
+                Err(err) => return println!("Error getting keys: {}", err),
+            };
+            for (key, value) in page.keys {
+                let key = match std::str::from_utf8(&key) {
+                    Ok(key) => key.to_string(),
+                    Err(_) => general_purpose::STANDARD.encode(&key),
+                };
+                match value {
+                    Some(value) => println!("{} = {}", key, String::from_utf8_lossy(&value)),
+                    None => println!("{}", key),
+                }
+            }
+            match page.next {
+                Some(next) if self.limit.is_some() => {
+                    return println!("(next: cursor={})", general_purpose::URL_SAFE_NO_PAD.encode(next));
+                }
+                Some(next) => self.scan.after = Some(next),
+                None => return,
+            }
+        }
+    }
+}
+
+fn print_result<T: std::fmt::Display>(result: Result<T>) {
+    match result {
+        Ok(value) => println!("{}", value),
+        Err(err) => println!("Error: {}", err),
+    }
+}
+
+fn print_values(result: Result<Vec<Vec<u8>>>) {
+    match result {
+        Ok(values) if values.is_empty() => println!("(empty)"),
+        Ok(values) => {
+            for value in values {
+                println!("{}", String::from_utf8_lossy(&value));
+            }
+        }
+        Err(err) => println!("Error: {}", err),
+    }
+}
+
+fn words(input: &str) -> Vec<Vec<u8>> {
+    input.split_whitespace().map(|word| word.as_bytes().to_vec()).collect()
+}
+
+fn print_scored(result: Result<Vec<(Vec<u8>, f64)>>) {
+    match result {
+        Ok(members) if members.is_empty() => println!("(empty)"),
+        Ok(members) => {
+            for (member, score) in members {
+                println!("{} {}", String::from_utf8_lossy(&member), score);
+            }
+        }
+        Err(err) => println!("Error: {}", err),
+    }
+}
+
+fn print_entries(result: Result<Vec<(StreamId, Fields)>>) {
+    match result {
+        Ok(entries) if entries.is_empty() => println!("(empty)"),
+        Ok(entries) => {
+            for (id, fields) in entries {
+                let fields: Vec<String> = fields
+                    .iter()
+                    .map(|(f, v)| format!("{} {}", String::from_utf8_lossy(f), String::from_utf8_lossy(v)))
+                    .collect();
+                println!("{} {}", id, fields.join(" "));
+            }
+        }
+        Err(err) => println!("Error: {}", err),
+    }
+}
+
+fn pairs(input: &str) -> Option<Fields> {
+    let words = words(input);
+    if !words.len().is_multiple_of(2) {
+        return None;
+    }
+    Some(words.chunks(2).map(|pair| (pair[0].clone(), pair[1].clone())).collect())
+}
+
+/// Prints messages on the subscribed channels until Enter is pressed.
+fn listen(db: &Kline, filters: Vec<ChannelFilter>) {
+    let mut subscription = db.subscribe_channels(filters);
+    println!("Listening, press Enter to stop...");
+
+    let (stop_tx, stop_rx) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        let _ = std::io::stdin().read_line(&mut String::new());
+        let _ = stop_tx.send(());
+    });
+
+    loop {
+        while let Some(message) = subscription.try_recv() {
+            println!("[{}] {}", message.channel, String::from_utf8_lossy(&message.payload));
+        }
+        if stop_rx.recv_timeout(Duration::from_millis(50)).is_ok() {
+            break;
+        }
+    }
+}
+
+/// Runs a blocking engine call from the synchronous REPL, reusing the
+/// surrounding tokio runtime when there is one.
+fn block_on<F: Future>(future: F) -> F::Output {
+    match tokio::runtime::Handle::try_current() {
+        Ok(handle) => tokio::task::block_in_place(|| handle.block_on(future)),
+        Err(_) => tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .expect("Failed to build runtime")
+            .block_on(future),
+    }
+}
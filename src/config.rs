@@ -1,4 +1,8 @@
+use std::collections::HashMap;
 use serde::{Deserialize, Serialize};
+use crate::constants::db::DEFAULT_RETAINED_SEGMENTS;
+use crate::constants::http::{DEFAULT_MAX_CONCURRENT_REQUESTS, DEFAULT_RATE_LIMIT_BURST};
+use crate::constants::storage::DEFAULT_MAX_BATCH_ITEMS;
 use crate::error::{KlineError, Result};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -7,6 +11,10 @@ pub struct KlineConfig {
     pub server: ServerConfig,
     pub limits: LimitsConfig,
     pub ttl: TtlConfig,
+    #[serde(default)]
+    pub history: HistoryConfig,
+    #[serde(default)]
+    pub auth: AuthConfig,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -14,6 +22,9 @@ pub struct StorageConfig {
     pub data_dir: String,
     pub compaction_interval_secs: u64,
     pub max_log_size_mb: u64,
+    /// Number of pre-compaction log segments kept for `changes_since`.
+    #[serde(default = "default_retained_segments")]
+    pub retained_segments: usize,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -21,6 +32,50 @@ pub struct ServerConfig {
     pub port: u16,
     pub bind_address: String,
     pub max_connections: usize,
+    #[serde(default)]
+    pub rate_limit: RateLimitConfig,
+    /// Serves HTTPS instead of HTTP when set.
+    #[serde(default)]
+    pub tls: Option<TlsConfig>,
+}
+
+/// Certificate and key for HTTPS, as PEM files. Both are read again on
+/// SIGHUP, so renewed certificates apply without a restart.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TlsConfig {
+    /// Certificate chain, leaf first.
+    pub cert_path: String,
+    pub key_path: String,
+    /// CA certificates that client certificates must chain to. When set,
+    /// every client must present one (mutual TLS).
+    #[serde(default)]
+    pub client_ca: Option<String>,
+}
+
+/// Limits on requests, applied before they reach a handler. Requests over
+/// either limit get `429 Too Many Requests`. Fields left out of
+/// `[server.rate_limit]` keep their defaults.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RateLimitConfig {
+    /// Requests handled at once across all clients; 0 disables the limit.
+    pub max_concurrent_requests: usize,
+    /// Sustained requests per second allowed from one client IP; 0
+    /// disables per-client limiting.
+    pub requests_per_sec: f64,
+    /// Requests a client IP may make at once before `requests_per_sec`
+    /// applies.
+    pub burst: u32,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            max_concurrent_requests: DEFAULT_MAX_CONCURRENT_REQUESTS,
+            requests_per_sec: 0.0,
+            burst: DEFAULT_RATE_LIMIT_BURST,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -28,6 +83,13 @@ pub struct LimitsConfig {
     pub max_key_size: usize,      
     pub max_value_size: usize,   
     pub max_keys: usize,        
+    #[serde(default)]
+    pub max_total_bytes: Option<usize>,
+    #[serde(default)]
+    pub prefix_quotas: HashMap<String, usize>,
+    /// Most keys accepted by one `mget`, `mput` or `mdelete`.
+    #[serde(default = "default_max_batch_items")]
+    pub max_batch_items: usize,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -37,6 +99,90 @@ pub struct TtlConfig {
     pub max_ttl_secs: u64,          
 }
 
+/// API tokens, required on every HTTP request when `enabled`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AuthConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default)]
+    pub tokens: Vec<TokenConfig>,
+}
+
+/// One API token. Only its hash is stored; `kline token create` prints the
+/// token itself once.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenConfig {
+    pub name: String,
+    /// Hex SHA-256 of the token.
+    pub hash: String,
+    pub role: Role,
+    /// Restricts the token to keys, lock names and channels starting with
+    /// this prefix.
+    #[serde(default)]
+    pub prefix: Option<String>,
+}
+
+/// What a token may do. Each role includes the ones before it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Role {
+    ReadOnly,
+    ReadWrite,
+    Admin,
+}
+
+impl Role {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Role::ReadOnly => "read-only",
+            Role::ReadWrite => "read-write",
+            Role::Admin => "admin",
+        }
+    }
+}
+
+impl std::str::FromStr for Role {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "read-only" => Ok(Role::ReadOnly),
+            "read-write" => Ok(Role::ReadWrite),
+            "admin" => Ok(Role::Admin),
+            _ => Err(format!("unknown role '{}' (expected read-only, read-write or admin)", s)),
+        }
+    }
+}
+
+/// Version history kept for keys under each configured prefix.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct HistoryConfig {
+    #[serde(default)]
+    pub retention: HashMap<String, RetentionPolicy>,
+}
+
+/// How much history to keep for a prefix. With both limits set, a version
+/// is dropped as soon as it falls outside either.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RetentionPolicy {
+    /// Keep at most this many versions, including the current one.
+    pub versions: Option<usize>,
+    /// Keep every version that was current at some point in the last
+    /// `max_age_secs` seconds.
+    pub max_age_secs: Option<u64>,
+}
+
+impl HistoryConfig {
+    /// The policy for the longest configured prefix of `key`, if any.
+    pub fn policy_for(&self, key: &[u8]) -> Option<&RetentionPolicy> {
+        self.retention
+            .iter()
+            .filter(|(prefix, _)| key.starts_with(prefix.as_bytes()))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(_, policy)| policy)
+    }
+}
+
 impl Default for KlineConfig {
     fn default() -> Self {
         Self {
@@ -44,34 +190,52 @@ impl Default for KlineConfig {
                 data_dir: "./data".to_string(),
                 compaction_interval_secs: 60,
                 max_log_size_mb: 100,
+                retained_segments: DEFAULT_RETAINED_SEGMENTS,
             },
             server: ServerConfig {
                 port: 3000,
                 bind_address: "127.0.0.1".to_string(),
                 max_connections: 1000,
+                rate_limit: RateLimitConfig::default(),
+                tls: None,
             },
             limits: LimitsConfig {
                 max_key_size: 1024,      
                 max_value_size: 10_485_760, 
                 max_keys: 1_000_000,      
+                max_total_bytes: None,
+                prefix_quotas: HashMap::new(),
+                max_batch_items: DEFAULT_MAX_BATCH_ITEMS,
             },
             ttl: TtlConfig {
                 cleanup_interval_secs: 30,
                 default_ttl_secs: None,
                 max_ttl_secs: 86400 * 365, 
             },
+            history: HistoryConfig::default(),
+            auth: AuthConfig::default(),
         }
     }
 }
 
+fn default_retained_segments() -> usize {
+    DEFAULT_RETAINED_SEGMENTS
+}
+
+fn default_max_batch_items() -> usize {
+    DEFAULT_MAX_BATCH_ITEMS
+}
+
 impl KlineConfig {
 
+    /// Reads `kline.conf` if it exists, or else starts from the defaults. A
+    /// config file that fails to parse is an error rather than ignored.
     pub fn load() -> Result<Self> {
-        let mut config = Self::default();
-        
-        if let Ok(file_config) = Self::from_file("kline.conf") {
-            config = file_config;
-        }
+        let mut config = if std::path::Path::new("kline.conf").exists() {
+            Self::from_file("kline.conf")?
+        } else {
+            Self::default()
+        };
         
         config.apply_env_vars();
         
@@ -94,10 +258,10 @@ impl KlineConfig {
     
     pub fn apply_env_vars(&mut self) {
         // KLINE_PORT=3000 -> server.port = 3000
-        if let Ok(port) = std::env::var("KLINE_PORT") {
-            if let Ok(port) = port.parse() {
-                self.server.port = port;
-            }
+        if let Ok(port) = std::env::var("KLINE_PORT")
+            && let Ok(port) = port.parse()
+        {
+            self.server.port = port;
         }
         
         // KLINE_DATA_DIR=/data -> storage.data_dir = "/data"
@@ -8,6 +8,8 @@ pub mod db {
     pub const COMPACTION_INTERVAL: Duration = Duration::from_secs(COMPACTION_INTERVAL_SECS);
     pub const DEFAULT_DB_FILE: &str = "kline.db";
     pub const TEMP_FILE_SUFFIX: &str = ".tmp";
+    pub const SEGMENT_SUFFIX: &str = ".wal";
+    pub const DEFAULT_RETAINED_SEGMENTS: usize = 8;
     pub const MAX_OPS_BEFORE_COMPACTION: usize = 1000;
 }
 
@@ -22,4 +24,47 @@ pub mod cli {
 pub mod storage {
     pub const INITIAL_HASHMAP_CAPACITY: usize = 1024;
     pub const IO_BUFFER_SIZE: usize = 8192;
+    /// Maximum combined size of a key's tag names and values.
+    pub const MAX_TAGS_SIZE: usize = 4096;
+    /// Default for `limits.max_batch_items`.
+    pub const DEFAULT_MAX_BATCH_ITEMS: usize = 1000;
+}
+
+/// Key listing constants
+pub mod keys {
+    /// Keys returned per page of `GET /keys` when no `limit` is given.
+    pub const DEFAULT_PAGE_SIZE: usize = 1000;
+    /// Largest `limit` accepted by `GET /keys`.
+    pub const MAX_PAGE_SIZE: usize = 10_000;
+}
+
+/// HTTP server constants
+pub mod http {
+    use super::*;
+
+    /// Allowance on top of the largest encoded value for the key, JSON
+    /// framing and other fields of a request body.
+    pub const BODY_OVERHEAD: usize = 64 * 1024;
+    /// Default for `server.rate_limit.max_concurrent_requests`.
+    pub const DEFAULT_MAX_CONCURRENT_REQUESTS: usize = 512;
+    /// Default for `server.rate_limit.burst`.
+    pub const DEFAULT_RATE_LIMIT_BURST: u32 = 50;
+    /// Client IPs tracked by the rate limiter before idle ones are dropped.
+    pub const RATE_LIMIT_PRUNE_THRESHOLD: usize = 10_000;
+    /// Time a client has to complete the TLS handshake.
+    pub const TLS_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+    /// Finished TLS handshakes queued for the HTTP server to pick up.
+    pub const TLS_ACCEPT_BACKLOG: usize = 128;
+}
+
+/// Change feed configuration constants
+pub mod changes {
+    /// Events buffered per subscriber before new ones are dropped.
+    pub const SUBSCRIBER_BUFFER: usize = 1024;
+}
+
+/// Pub/sub configuration constants
+pub mod pubsub {
+    /// Messages buffered per subscriber before new ones are dropped.
+    pub const SUBSCRIBER_BUFFER: usize = 1024;
 }
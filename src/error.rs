@@ -5,6 +5,9 @@ pub enum KlineError {
     #[error("Key not found: {key}")]
     KeyNotFound { key: String },
     
+    #[error("Key already exists: {key}")]
+    KeyExists { key: String },
+    
     #[error("Key expired: {key}")]
     KeyExpired { key: String },
     
@@ -14,12 +17,54 @@ pub enum KlineError {
     #[error("Value too large: {size} bytes (max: {max})")]
     ValueTooLarge { size: usize, max: usize },
     
+    #[error("Tags too large: {size} bytes (max: {max})")]
+    TagsTooLarge { size: usize, max: usize },
+    
+    #[error("Batch too large: {size} items (max: {max})")]
+    BatchTooLarge { size: usize, max: usize },
+    
+    #[error("Request body too large: {size} bytes (max: {max})")]
+    PayloadTooLarge { size: usize, max: usize },
+    
     #[error("Invalid TTL: {ttl} seconds")]
     InvalidTtl { ttl: u64 },
     
+    #[error("Invalid timeout: {millis} ms")]
+    InvalidTimeout { millis: u128 },
+    
     #[error("Database full: {current}/{max} keys")]
     DatabaseFull { current: usize, max: usize },
     
+    #[error("Storage full: {size} bytes (max: {max})")]
+    StorageFull { size: usize, max: usize },
+    
+    #[error("Prefix quota exceeded for '{prefix}': {current}/{max} keys")]
+    PrefixQuotaExceeded { prefix: String, current: usize, max: usize },
+    
+    #[error("Value is not a number: {key}")]
+    NotANumber { key: String },
+    
+    #[error("Numeric overflow: {key}")]
+    NumericOverflow { key: String },
+    
+    #[error("Wrong type for key {key}: expected {expected}, found {found}")]
+    WrongType { key: String, expected: &'static str, found: &'static str },
+    
+    #[error("Invalid stream ID: {id}")]
+    InvalidStreamId { id: String },
+    
+    #[error("No such consumer group: {group}")]
+    NoSuchGroup { group: String },
+    
+    #[error("Lease {name} is not held by {owner}")]
+    LeaseNotHeld { name: String, owner: String },
+    
+    #[error("Changes after sequence {requested} have been compacted away; earliest available is {earliest}")]
+    ChangesCompacted { requested: u64, earliest: u64 },
+    
+    #[error("TLS error: {reason}")]
+    Tls { reason: String },
+    
     #[error("Config parse error: {reason}")]
     ConfigParse { reason: String },
     
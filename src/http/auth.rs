@@ -0,0 +1,229 @@
+use axum::{
+    extract::{Query, Request, State},
+    http::{header, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use percent_encoding::percent_decode_str;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::Arc;
+use crate::config::{AuthConfig, Role};
+use super::encoding::Encoding;
+use super::error::{bad_request, error_response};
+use super::http::split_key_path;
+
+/// Tokens accepted by `require_token`, by hash.
+pub struct Auth {
+    tokens: HashMap<String, Access>,
+}
+
+/// What the token on a request may do. Added to the request's extensions
+/// for handlers that check keys the middleware cannot see, such as those
+/// in a bulk request body.
+#[derive(Debug, Clone)]
+pub struct Access {
+    pub name: String,
+    pub role: Role,
+    pub prefix: Option<String>,
+}
+
+impl Auth {
+    pub fn new(config: &AuthConfig) -> Self {
+        let tokens = config
+            .tokens
+            .iter()
+            .map(|token| {
+                let access = Access {
+                    name: token.name.clone(),
+                    role: token.role,
+                    prefix: token.prefix.clone(),
+                };
+                (token.hash.to_ascii_lowercase(), access)
+            })
+            .collect();
+        Self { tokens }
+    }
+}
+
+impl Access {
+    /// Fails unless every key starts with the token's prefix.
+    pub fn check_keys<'a>(&self, keys: impl IntoIterator<Item = &'a [u8]>) -> Result<(), Denied> {
+        let Some(prefix) = &self.prefix else {
+            return Ok(());
+        };
+        match keys.into_iter().find(|key| !key.starts_with(prefix.as_bytes())) {
+            Some(key) => Err(self.out_of_scope(key)),
+            None => Ok(()),
+        }
+    }
+
+    fn out_of_scope(&self, name: &[u8]) -> Denied {
+        let prefix = self.prefix.as_deref().unwrap_or_default();
+        Denied::Forbidden(match name {
+            [] => format!("Token '{}' is limited to '{}'; pass a key or prefix inside it", self.name, prefix),
+            _ => format!(
+                "Token '{}' is limited to '{}'; '{}' is outside it",
+                self.name,
+                prefix,
+                String::from_utf8_lossy(name)
+            ),
+        })
+    }
+}
+
+/// Why a request was refused.
+pub enum Denied {
+    Unauthorized(&'static str),
+    Forbidden(String),
+    BadRequest(String),
+}
+
+impl IntoResponse for Denied {
+    fn into_response(self) -> Response {
+        match self {
+            Denied::Unauthorized(message) => {
+                let mut response = error_response(StatusCode::UNAUTHORIZED, "unauthorized", message.to_string());
+                response.headers_mut().insert(header::WWW_AUTHENTICATE, "Bearer".parse().unwrap());
+                response
+            }
+            Denied::Forbidden(message) => error_response(StatusCode::FORBIDDEN, "forbidden", message),
+            Denied::BadRequest(message) => bad_request(message),
+        }
+    }
+}
+
+/// Hex SHA-256 of a token, as stored in `[[auth.tokens]]`.
+pub fn hash_token(token: &str) -> String {
+    Sha256::digest(token.as_bytes()).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// A new random token and its hash.
+pub fn create_token() -> (String, String) {
+    let mut bytes = [0u8; 32];
+    getrandom::fill(&mut bytes).expect("system random number generator is unavailable");
+    let token = format!("kline_{}", bytes.iter().map(|b| format!("{:02x}", b)).collect::<String>());
+    let hash = hash_token(&token);
+    (token, hash)
+}
+
+/// Authenticates the `Authorization: Bearer` token of every request, then
+/// checks its role against the route and its prefix against the keys the
+/// request names in its path or query.
+pub async fn require_token(State(auth): State<Arc<Auth>>, mut request: Request, next: Next) -> Response {
+    let token = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+    let Some(token) = token else {
+        return Denied::Unauthorized("Missing bearer token").into_response();
+    };
+    let Some(access) = auth.tokens.get(&hash_token(token.trim())) else {
+        return Denied::Unauthorized("Invalid token").into_response();
+    };
+
+    let required = required_role(request.method(), request.uri().path());
+    if access.role < required {
+        return Denied::Forbidden(format!(
+            "Token '{}' is {} but this request needs {}",
+            access.name,
+            access.role.as_str(),
+            required.as_str()
+        ))
+        .into_response();
+    }
+    if access.prefix.is_some()
+        && let Err(denied) = check_scope(access, &request)
+    {
+        return denied.into_response();
+    }
+
+    request.extensions_mut().insert(access.clone());
+    next.run(request).await
+}
+
+/// Deleting by prefix and reading metrics need `admin`; other reads,
+/// including `POST /mget`, need `read-only`; everything else `read-write`.
+fn required_role(method: &Method, path: &str) -> Role {
+    match (method, path) {
+        (_, "/metrics") | (&Method::DELETE, "/keys") => Role::Admin,
+        (&Method::GET | &Method::HEAD, _) | (_, "/mget") => Role::ReadOnly,
+        _ => Role::ReadWrite,
+    }
+}
+
+/// Query parameters that name keys, lock names or channels.
+#[derive(Deserialize)]
+struct ScopeParams {
+    key_encoding: Option<Encoding>,
+    to: Option<String>,
+    key: Option<String>,
+    keys: Option<String>,
+    in_flight: Option<String>,
+    prefix: Option<String>,
+    channels: Option<String>,
+    patterns: Option<String>,
+}
+
+/// Checks the names a request addresses against the token's prefix. Bulk
+/// request bodies are checked by their handlers instead.
+fn check_scope(access: &Access, request: &Request) -> Result<(), Denied> {
+    let Query(params) = Query::<ScopeParams>::try_from_uri(request.uri())
+        .map_err(|err| Denied::BadRequest(err.body_text()))?;
+    let path = request.uri().path();
+    let decode = |key: &str| -> Result<Vec<u8>, Denied> {
+        match params.key_encoding {
+            Some(encoding) => encoding
+                .decode(key.as_bytes())
+                .ok_or_else(|| Denied::BadRequest(format!("Key is not valid {}", encoding.as_str()))),
+            None => Ok(key.as_bytes().to_vec()),
+        }
+    };
+    let split = |list: &Option<String>| -> Vec<Vec<u8>> {
+        list.iter()
+            .flat_map(|list| list.split(','))
+            .filter(|name| !name.is_empty())
+            .map(|name| name.as_bytes().to_vec())
+            .collect()
+    };
+
+    let mut names = Vec::new();
+    if let Some(rest) = path.strip_prefix("/key/") {
+        let (key, _) = split_key_path(rest, request.method());
+        names.push(decode(&percent_decode(key))?);
+        if let Some(to) = &params.to {
+            names.push(decode(to)?);
+        }
+    } else if let Some(rest) = path.strip_prefix("/raw/") {
+        names.push(decode(&percent_decode(rest))?);
+    } else if let Some(rest) = ["/list/", "/hash/", "/set/", "/zset/", "/queue/", "/stream/", "/lock/", "/publish/"]
+        .iter()
+        .find_map(|area| path.strip_prefix(area))
+    {
+        let segment = rest.split('/').next().unwrap_or_default();
+        names.push(percent_decode(segment).into_bytes());
+        names.extend(split(&params.keys));
+        names.extend(params.in_flight.iter().map(|key| key.as_bytes().to_vec()));
+    } else if path == "/keys" || path.starts_with("/watch") {
+        // Without a key or a prefix these cover every key.
+        let scope = params.key.as_ref().or(params.prefix.as_ref());
+        names.push(scope.map(|name| name.as_bytes().to_vec()).unwrap_or_default());
+    } else if path.starts_with("/subscribe") {
+        names.extend(split(&params.channels));
+        // A pattern stays inside the prefix if it starts with it and the
+        // prefix itself has no wildcards.
+        let prefix = access.prefix.as_deref().unwrap_or_default();
+        for pattern in split(&params.patterns) {
+            if prefix.contains(['*', '?', '[', '\\']) || !pattern.starts_with(prefix.as_bytes()) {
+                return Err(access.out_of_scope(&pattern));
+            }
+        }
+    }
+    access.check_keys(names.iter().map(Vec::as_slice))
+}
+
+fn percent_decode(text: &str) -> String {
+    percent_decode_str(text).decode_utf8_lossy().into_owned()
+}
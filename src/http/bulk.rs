@@ -0,0 +1,139 @@
+use axum::{
+    extract::{Query, State},
+    response::IntoResponse,
+    routing::post,
+    Extension,
+    Json,
+    Router,
+};
+use std::sync::Arc;
+use crate::{Kline, KlineError};
+use super::auth::{Access, Denied};
+use super::encoding::Encoding;
+use super::error::bad_request;
+use super::requests::*;
+use super::responses::*;
+
+/// Routes that read or write many keys in one request. Keys, and `/mput`
+/// values, are decoded with `?key_encoding=` and `?encoding=`.
+pub fn routes() -> Router<Arc<Kline>> {
+    Router::new()
+        .route("/mget", post(mget))
+        .route("/mput", post(mput))
+        .route("/mdelete", post(mdelete))
+}
+
+/// Decodes `text` if an encoding was given. A bad item rejects the whole
+/// request, with the returned message.
+fn decode(text: &str, encoding: Option<Encoding>, what: &str) -> Result<Vec<u8>, String> {
+    match encoding {
+        Some(encoding) => encoding
+            .decode(text.as_bytes())
+            .ok_or_else(|| format!("{} is not valid {}: {}", what, encoding.as_str(), text)),
+        None => Ok(text.as_bytes().to_vec()),
+    }
+}
+
+fn decode_keys(keys: &[String], encoding: Option<Encoding>) -> Result<Vec<Vec<u8>>, String> {
+    keys.iter().map(|key| decode(key, encoding, "Key")).collect()
+}
+
+/// The request's token, when auth is enabled. Bulk keys arrive in the body,
+/// out of reach of `require_token`, so a prefix-scoped token is checked here.
+type TokenAccess = Option<Extension<Access>>;
+
+fn check_access<'a>(access: &TokenAccess, keys: impl IntoIterator<Item = &'a [u8]>) -> Result<(), Denied> {
+    access.as_ref().map_or(Ok(()), |Extension(access)| access.check_keys(keys))
+}
+
+async fn mget(Query(params): Query<BulkParams>, State(db): State<Arc<Kline>>, access: TokenAccess, Json(body): Json<KeysRequest>) -> impl IntoResponse {
+    let keys = match decode_keys(&body.keys, params.key_encoding) {
+        Ok(keys) => keys,
+        Err(message) => return bad_request(message),
+    };
+    if let Err(denied) = check_access(&access, keys.iter().map(Vec::as_slice)) {
+        return denied.into_response();
+    }
+    let values = match db.mget(&keys) {
+        Ok(values) => values,
+        Err(err) => return err.into_response(),
+    };
+    let mut items = Vec::with_capacity(values.len());
+    for (key, value) in body.keys.into_iter().zip(values) {
+        let item = match value {
+            Some(value) => {
+                let encoding = params.encoding.unwrap_or_else(|| Encoding::detect(&value));
+                match encoding.encode(&value) {
+                    Some(text) => ValueResponse::encoded(key, text, encoding),
+                    None => return bad_request(format!("Value of {} is not valid UTF-8; use encoding=base64 or hex", key)),
+                }
+            }
+            None => ValueResponse::not_found(key),
+        };
+        items.push(item);
+    }
+    let count = items.len();
+    Json(MgetResponse { items, count }).into_response()
+}
+
+/// With `"atomic": true` every entry is stored or, on the first failure,
+/// none is and that error is returned. Otherwise each entry gets its own
+/// status.
+async fn mput(Query(params): Query<BulkParams>, State(db): State<Arc<Kline>>, access: TokenAccess, Json(body): Json<MputRequest>) -> impl IntoResponse {
+    let mut pairs = Vec::with_capacity(body.entries.len());
+    for (key, value) in &body.entries {
+        match (decode(key, params.key_encoding, "Key"), decode(value, params.encoding, "Value")) {
+            (Ok(key), Ok(value)) => pairs.push((key, value)),
+            (Err(message), _) | (_, Err(message)) => return bad_request(message),
+        }
+    }
+    if let Err(denied) = check_access(&access, pairs.iter().map(|(key, _)| key.as_slice())) {
+        return denied.into_response();
+    }
+    let keys = body.entries.into_keys();
+
+    if body.atomic.unwrap_or(false) {
+        return match db.mput(pairs) {
+            Ok(()) => Json(BulkResponse::new(keys.map(ItemStatus::ok).collect())).into_response(),
+            Err(err) => err.into_response(),
+        };
+    }
+    match db.mput_each(pairs) {
+        Ok(results) => {
+            let items = keys
+                .zip(results)
+                .map(|(key, result)| match result {
+                    Ok(()) => ItemStatus::ok(key),
+                    Err(err) => ItemStatus::failed(key, &err),
+                })
+                .collect();
+            Json(BulkResponse::new(items)).into_response()
+        }
+        Err(err) => err.into_response(),
+    }
+}
+
+async fn mdelete(Query(params): Query<BulkParams>, State(db): State<Arc<Kline>>, access: TokenAccess, Json(body): Json<KeysRequest>) -> impl IntoResponse {
+    let keys = match decode_keys(&body.keys, params.key_encoding) {
+        Ok(keys) => keys,
+        Err(message) => return bad_request(message),
+    };
+    if let Err(denied) = check_access(&access, keys.iter().map(Vec::as_slice)) {
+        return denied.into_response();
+    }
+    match db.mdelete(&keys) {
+        Ok(deleted) => {
+            let items = body
+                .keys
+                .into_iter()
+                .zip(deleted)
+                .map(|(key, deleted)| match deleted {
+                    true => ItemStatus::ok(key),
+                    false => ItemStatus::failed(key.clone(), &KlineError::KeyNotFound { key }),
+                })
+                .collect();
+            Json(BulkResponse::new(items)).into_response()
+        }
+        Err(err) => err.into_response(),
+    }
+}
@@ -0,0 +1,185 @@
+use axum::{
+    body::Bytes,
+    extract::{Path, Query, State},
+    response::IntoResponse,
+    routing::{get, post, put},
+    Json,
+    Router,
+};
+use std::sync::Arc;
+use crate::{Kline, KlineError};
+use super::encoding::Encoding;
+use super::error::bad_request;
+use super::requests::*;
+use super::responses::*;
+
+/// Routes for the list, hash, set and sorted set types.
+pub fn routes() -> Router<Arc<Kline>> {
+    Router::new()
+        .route("/list/{key}", get(lrange))
+        .route("/list/{key}/len", get(llen))
+        .route("/list/{key}/lpush", post(lpush))
+        .route("/list/{key}/rpush", post(rpush))
+        .route("/list/{key}/lpop", post(lpop))
+        .route("/list/{key}/rpop", post(rpop))
+        .route("/hash/{key}", get(hgetall))
+        .route("/hash/{key}/{field}", put(hset).get(hget).delete(hdel))
+        .route("/set/{key}", get(smembers))
+        .route("/set/{key}/{member}", put(sadd).get(sismember).delete(srem))
+        .route("/zset/{key}", get(zrange).post(zadd))
+        .route("/zset/{key}/by_score", get(zrange_by_score))
+        .route("/zset/{key}/popmin", post(zpopmin))
+        .route("/zset/{key}/{member}", get(zscore).delete(zrem))
+}
+
+async fn lrange(Path(key): Path<String>, Query(params): Query<RangeParams>, State(db): State<Arc<Kline>>) -> impl IntoResponse {
+    match db.lrange(key.as_bytes(), params.start.unwrap_or(0), params.stop.unwrap_or(-1)) {
+        Ok(items) => Json(ValuesResponse::new(key, items)).into_response(),
+        Err(err) => err.into_response(),
+    }
+}
+
+async fn llen(Path(key): Path<String>, State(db): State<Arc<Kline>>) -> impl IntoResponse {
+    match db.llen(key.as_bytes()) {
+        Ok(length) => Json(LengthResponse::new(key, length)).into_response(),
+        Err(err) => err.into_response(),
+    }
+}
+
+async fn lpush(Path(key): Path<String>, State(db): State<Arc<Kline>>, Json(body): Json<ValuesRequest>) -> impl IntoResponse {
+    match db.lpush(key.as_bytes(), body.into_bytes()) {
+        Ok(length) => Json(LengthResponse::new(key, length)).into_response(),
+        Err(err) => err.into_response(),
+    }
+}
+
+async fn rpush(Path(key): Path<String>, State(db): State<Arc<Kline>>, Json(body): Json<ValuesRequest>) -> impl IntoResponse {
+    match db.rpush(key.as_bytes(), body.into_bytes()) {
+        Ok(length) => Json(LengthResponse::new(key, length)).into_response(),
+        Err(err) => err.into_response(),
+    }
+}
+
+async fn lpop(Path(key): Path<String>, Query(params): Query<CountParams>, State(db): State<Arc<Kline>>) -> impl IntoResponse {
+    match db.lpop(key.as_bytes(), params.count.unwrap_or(1)) {
+        Ok(items) => Json(ValuesResponse::new(key, items)).into_response(),
+        Err(err) => err.into_response(),
+    }
+}
+
+async fn rpop(Path(key): Path<String>, Query(params): Query<CountParams>, State(db): State<Arc<Kline>>) -> impl IntoResponse {
+    match db.rpop(key.as_bytes(), params.count.unwrap_or(1)) {
+        Ok(items) => Json(ValuesResponse::new(key, items)).into_response(),
+        Err(err) => err.into_response(),
+    }
+}
+
+async fn hgetall(Path(key): Path<String>, State(db): State<Arc<Kline>>) -> impl IntoResponse {
+    match db.hgetall(key.as_bytes()) {
+        Ok(fields) => Json(HashResponse::new(key, fields)).into_response(),
+        Err(err) => err.into_response(),
+    }
+}
+
+async fn hget(Path((key, field)): Path<(String, String)>, Query(params): Query<HashGetParams>, State(db): State<Arc<Kline>>) -> impl IntoResponse {
+    match db.hget(key.as_bytes(), field.as_bytes()) {
+        Ok(Some(value)) => {
+            let encoding = params.encoding.unwrap_or_else(|| Encoding::detect(&value));
+            match encoding.encode(&value) {
+                Some(text) => Json(ValueResponse::encoded(field, text, encoding)).into_response(),
+                None => bad_request("Value is not valid UTF-8; use encoding=base64 or hex"),
+            }
+        }
+        Ok(None) => KlineError::KeyNotFound { key: format!("{}/{}", key, field) }.into_response(),
+        Err(err) => err.into_response(),
+    }
+}
+
+async fn hset(Path((key, field)): Path<(String, String)>, State(db): State<Arc<Kline>>, body: Bytes) -> impl IntoResponse {
+    match db.hset(key.as_bytes(), vec![(field.into_bytes(), body.to_vec())]) {
+        Ok(_) => Json(StatusResponse::ok()).into_response(),
+        Err(err) => err.into_response(),
+    }
+}
+
+async fn hdel(Path((key, field)): Path<(String, String)>, State(db): State<Arc<Kline>>) -> impl IntoResponse {
+    match db.hdel(key.as_bytes(), &[field.into_bytes()]) {
+        Ok(_) => Json(StatusResponse::deleted()).into_response(),
+        Err(err) => err.into_response(),
+    }
+}
+
+async fn smembers(Path(key): Path<String>, State(db): State<Arc<Kline>>) -> impl IntoResponse {
+    match db.smembers(key.as_bytes()) {
+        Ok(members) => Json(ValuesResponse::new(key, members)).into_response(),
+        Err(err) => err.into_response(),
+    }
+}
+
+async fn sismember(Path((key, member)): Path<(String, String)>, State(db): State<Arc<Kline>>) -> impl IntoResponse {
+    match db.sismember(key.as_bytes(), member.as_bytes()) {
+        Ok(is_member) => Json(MemberResponse::new(key, member, is_member)).into_response(),
+        Err(err) => err.into_response(),
+    }
+}
+
+async fn sadd(Path((key, member)): Path<(String, String)>, State(db): State<Arc<Kline>>) -> impl IntoResponse {
+    match db.sadd(key.as_bytes(), vec![member.into_bytes()]) {
+        Ok(_) => Json(StatusResponse::ok()).into_response(),
+        Err(err) => err.into_response(),
+    }
+}
+
+async fn srem(Path((key, member)): Path<(String, String)>, State(db): State<Arc<Kline>>) -> impl IntoResponse {
+    match db.srem(key.as_bytes(), &[member.into_bytes()]) {
+        Ok(_) => Json(StatusResponse::deleted()).into_response(),
+        Err(err) => err.into_response(),
+    }
+}
+
+async fn zadd(Path(key): Path<String>, State(db): State<Arc<Kline>>, Json(body): Json<ZaddRequest>) -> impl IntoResponse {
+    let members = body.members.into_iter().map(|m| (m.score, m.member.into_bytes())).collect();
+    match db.zadd(key.as_bytes(), members) {
+        Ok(added) => Json(CountResponse::new(key, added)).into_response(),
+        Err(err) => err.into_response(),
+    }
+}
+
+async fn zrange(Path(key): Path<String>, Query(params): Query<RangeParams>, State(db): State<Arc<Kline>>) -> impl IntoResponse {
+    match db.zrange(key.as_bytes(), params.start.unwrap_or(0), params.stop.unwrap_or(-1)) {
+        Ok(members) => Json(ScoredMembersResponse::new(key, members)).into_response(),
+        Err(err) => err.into_response(),
+    }
+}
+
+async fn zrange_by_score(Path(key): Path<String>, Query(params): Query<ScoreRangeParams>, State(db): State<Arc<Kline>>) -> impl IntoResponse {
+    let min = params.min.unwrap_or(f64::NEG_INFINITY);
+    let max = params.max.unwrap_or(f64::INFINITY);
+    match db.zrange_by_score(key.as_bytes(), min, max, params.limit) {
+        Ok(members) => Json(ScoredMembersResponse::new(key, members)).into_response(),
+        Err(err) => err.into_response(),
+    }
+}
+
+async fn zpopmin(Path(key): Path<String>, Query(params): Query<CountParams>, State(db): State<Arc<Kline>>) -> impl IntoResponse {
+    match db.zpopmin(key.as_bytes(), params.count.unwrap_or(1)) {
+        Ok(members) => Json(ScoredMembersResponse::new(key, members)).into_response(),
+        Err(err) => err.into_response(),
+    }
+}
+
+async fn zscore(Path((key, member)): Path<(String, String)>, State(db): State<Arc<Kline>>) -> impl IntoResponse {
+    let result = db.zscore(key.as_bytes(), member.as_bytes())
+        .and_then(|score| Ok((score, db.zrank(key.as_bytes(), member.as_bytes())?)));
+    match result {
+        Ok((score, rank)) => Json(ScoreResponse::new(key, member, score, rank)).into_response(),
+        Err(err) => err.into_response(),
+    }
+}
+
+async fn zrem(Path((key, member)): Path<(String, String)>, State(db): State<Arc<Kline>>) -> impl IntoResponse {
+    match db.zrem(key.as_bytes(), &[member.into_bytes()]) {
+        Ok(_) => Json(StatusResponse::deleted()).into_response(),
+        Err(err) => err.into_response(),
+    }
+}
@@ -0,0 +1,68 @@
+use base64::Engine as _;
+use serde::Deserialize;
+
+const BASE64: base64::engine::GeneralPurpose = base64::engine::general_purpose::STANDARD;
+
+/// How binary data is carried in JSON bodies and text request bodies.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Encoding {
+    Utf8,
+    Base64,
+    Hex,
+}
+
+impl Encoding {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Encoding::Utf8 => "utf8",
+            Encoding::Base64 => "base64",
+            Encoding::Hex => "hex",
+        }
+    }
+
+    /// `utf8` if the bytes are valid UTF-8, otherwise `base64`, so that a
+    /// value is never altered by the default encoding.
+    pub fn detect(bytes: &[u8]) -> Self {
+        if std::str::from_utf8(bytes).is_ok() { Encoding::Utf8 } else { Encoding::Base64 }
+    }
+
+    /// Encodes `bytes` as text, or returns `None` for `utf8` when the bytes
+    /// are not valid UTF-8.
+    pub fn encode(self, bytes: &[u8]) -> Option<String> {
+        match self {
+            Encoding::Utf8 => String::from_utf8(bytes.to_vec()).ok(),
+            Encoding::Base64 => Some(BASE64.encode(bytes)),
+            Encoding::Hex => Some(bytes.iter().map(|b| format!("{:02x}", b)).collect()),
+        }
+    }
+
+    /// Longest text `encode` produces for `len` bytes, used to size request
+    /// body limits.
+    pub fn max_encoded_len(self, len: usize) -> usize {
+        match self {
+            Encoding::Utf8 => len,
+            Encoding::Base64 => len.div_ceil(3).saturating_mul(4),
+            Encoding::Hex => len.saturating_mul(2),
+        }
+    }
+
+    /// Decodes text produced by `encode`; `None` if it is malformed.
+    pub fn decode(self, text: &[u8]) -> Option<Vec<u8>> {
+        match self {
+            Encoding::Utf8 => Some(text.to_vec()),
+            Encoding::Base64 => BASE64.decode(text.trim_ascii()).ok(),
+            Encoding::Hex => {
+                // `from_str_radix` also takes a leading `+`, so check the
+                // digits first.
+                let text = text.trim_ascii();
+                if !text.len().is_multiple_of(2) || !text.iter().all(u8::is_ascii_hexdigit) {
+                    return None;
+                }
+                text.chunks(2)
+                    .map(|pair| u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok())
+                    .collect()
+            }
+        }
+    }
+}
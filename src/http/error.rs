@@ -0,0 +1,136 @@
+use axum::{
+    body::to_bytes,
+    extract::Request,
+    http::{header, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use crate::error::KlineError;
+use super::responses::ErrorResponse;
+
+impl KlineError {
+    /// Stable machine-readable name for the error, used as the `error`
+    /// field of HTTP error responses.
+    pub fn code(&self) -> &'static str {
+        match self {
+            KlineError::KeyNotFound { .. } => "key_not_found",
+            KlineError::KeyExists { .. } => "key_exists",
+            KlineError::KeyExpired { .. } => "key_expired",
+            KlineError::KeyTooLarge { .. } => "key_too_large",
+            KlineError::ValueTooLarge { .. } => "value_too_large",
+            KlineError::TagsTooLarge { .. } => "tags_too_large",
+            KlineError::BatchTooLarge { .. } => "batch_too_large",
+            KlineError::PayloadTooLarge { .. } => "payload_too_large",
+            KlineError::InvalidTtl { .. } => "invalid_ttl",
+            KlineError::InvalidTimeout { .. } => "invalid_timeout",
+            KlineError::DatabaseFull { .. } => "database_full",
+            KlineError::StorageFull { .. } => "storage_full",
+            KlineError::PrefixQuotaExceeded { .. } => "prefix_quota_exceeded",
+            KlineError::NotANumber { .. } => "not_a_number",
+            KlineError::NumericOverflow { .. } => "numeric_overflow",
+            KlineError::WrongType { .. } => "wrong_type",
+            KlineError::InvalidStreamId { .. } => "invalid_stream_id",
+            KlineError::NoSuchGroup { .. } => "no_such_group",
+            KlineError::LeaseNotHeld { .. } => "lease_not_held",
+            KlineError::ChangesCompacted { .. } => "changes_compacted",
+            KlineError::Tls { .. } => "tls_error",
+            KlineError::ConfigParse { .. } => "config_parse",
+            KlineError::ConfigSerialize { .. } => "config_serialize",
+            KlineError::Io(_) => "io_error",
+            KlineError::Serialization(_) => "serialization_error",
+            KlineError::InvalidKeyFormat => "invalid_key_format",
+            KlineError::LockPoisoned => "lock_poisoned",
+        }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            KlineError::KeyNotFound { .. }
+            | KlineError::KeyExpired { .. }
+            | KlineError::NoSuchGroup { .. } => StatusCode::NOT_FOUND,
+            KlineError::KeyTooLarge { .. }
+            | KlineError::ValueTooLarge { .. }
+            | KlineError::TagsTooLarge { .. }
+            | KlineError::BatchTooLarge { .. }
+            | KlineError::PayloadTooLarge { .. } => StatusCode::PAYLOAD_TOO_LARGE,
+            KlineError::DatabaseFull { .. }
+            | KlineError::StorageFull { .. }
+            | KlineError::PrefixQuotaExceeded { .. } => StatusCode::INSUFFICIENT_STORAGE,
+            KlineError::KeyExists { .. }
+            | KlineError::NotANumber { .. }
+            | KlineError::NumericOverflow { .. }
+            | KlineError::WrongType { .. }
+            | KlineError::LeaseNotHeld { .. } => StatusCode::CONFLICT,
+            KlineError::InvalidTtl { .. }
+            | KlineError::InvalidTimeout { .. }
+            | KlineError::InvalidStreamId { .. }
+            | KlineError::InvalidKeyFormat => StatusCode::BAD_REQUEST,
+            KlineError::ChangesCompacted { .. } => StatusCode::GONE,
+            KlineError::Tls { .. }
+            | KlineError::ConfigParse { .. }
+            | KlineError::ConfigSerialize { .. }
+            | KlineError::Io(_)
+            | KlineError::Serialization(_)
+            | KlineError::LockPoisoned => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl IntoResponse for KlineError {
+    fn into_response(self) -> Response {
+        let status = self.status();
+        error_response(status, self.code(), self.to_string())
+    }
+}
+
+/// Builds an `ErrorResponse` for failures that are not engine errors, such
+/// as malformed request parameters.
+pub fn error_response(status: StatusCode, error: &'static str, message: String) -> Response {
+    (status, Json(ErrorResponse { error, message })).into_response()
+}
+
+/// `400 Bad Request` with the `bad_request` error code.
+pub fn bad_request(message: impl Into<String>) -> Response {
+    error_response(StatusCode::BAD_REQUEST, "bad_request", message.into())
+}
+
+/// `400 Bad Request` with the `invalid_ttl` error code, for a TTL that is
+/// not a whole number of seconds.
+pub fn invalid_ttl(ttl: &str) -> Response {
+    let message = format!("Invalid TTL: {:?} is not a whole number of seconds", ttl);
+    error_response(StatusCode::BAD_REQUEST, "invalid_ttl", message)
+}
+
+/// Rewrites the plain-text rejections of axum's extractors, such as a
+/// query string or JSON body that fails to parse, and bodiless client
+/// errors such as `405 Method Not Allowed`, as `ErrorResponse`s.
+pub async fn json_rejections(request: Request, next: Next) -> Response {
+    let response = next.run(request).await;
+    let status = response.status();
+    let plain = match response.headers().get(header::CONTENT_TYPE) {
+        Some(content_type) => content_type.as_bytes().starts_with(b"text/plain"),
+        None => true,
+    };
+    if !status.is_client_error() || !plain {
+        return response;
+    }
+    let message = match to_bytes(response.into_body(), REJECTION_BODY_LIMIT).await {
+        Ok(body) if !body.is_empty() => String::from_utf8_lossy(&body).into_owned(),
+        _ => status.canonical_reason().unwrap_or_default().to_string(),
+    };
+    error_response(status, rejection_code(status), message)
+}
+
+/// Rejection messages are short; anything longer is not one.
+const REJECTION_BODY_LIMIT: usize = 64 * 1024;
+
+fn rejection_code(status: StatusCode) -> &'static str {
+    match status {
+        StatusCode::PAYLOAD_TOO_LARGE => "payload_too_large",
+        StatusCode::UNSUPPORTED_MEDIA_TYPE => "unsupported_media_type",
+        StatusCode::UNPROCESSABLE_ENTITY => "invalid_body",
+        StatusCode::METHOD_NOT_ALLOWED => "method_not_allowed",
+        _ => "bad_request",
+    }
+}
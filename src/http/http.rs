@@ -1,63 +1,539 @@
 use axum::{
-    body::Bytes, 
-    extract::{Path, State}, 
+    body::{Body, Bytes}, 
+    extract::{DefaultBodyLimit, Query, Request, State}, 
+    http::{header, HeaderMap, Method, StatusCode},
+    middleware,
     response::IntoResponse, 
-    routing::{delete, get, put}, 
+    routing::{get, post}, 
     Json, 
     Router
 };
 use std::sync::Arc;
-use crate::Kline;
-use base64::{Engine as _};
+use std::time::Duration;
+use base64::Engine as _;
+use tower::ServiceExt;
+use crate::constants::keys::{DEFAULT_PAGE_SIZE, MAX_PAGE_SIZE};
+use crate::storage::{At, KeyScan, MetaUpdate, Metadata, Tags};
+use crate::{Kline, KlineError};
+use super::auth::{require_token, Auth};
+use super::encoding::Encoding;
+use super::error::{bad_request, invalid_ttl, json_rejections};
+use super::key::KeyPath;
+use super::limits::{body_limit, content_length, read_body, reject_oversized};
+use super::rate_limit::{limit_requests, RateLimiter};
+use super::requests::*;
 use super::responses::*;
 
 pub fn create_router(db: Arc<Kline>) -> Router {
-    Router::new()
-        .route("/key/{key}", get(get_key))
-        .route("/key/{key}", put(put_key))
-        .route("/key/{key}", delete(delete_key))
-        .route("/keys", get(get_all_keys))
+    let body_limit = body_limit(&db.config().limits);
+    let limiter = Arc::new(RateLimiter::new(&db.config().server.rate_limit));
+    let keys = key_routes().with_state(db.clone());
+    let router = Router::new()
+        .route("/raw/{*key}", get(get_raw))
+        .route("/keys", get(list_keys).delete(delete_keys))
+        .merge(super::bulk::routes())
+        .merge(super::collections::routes())
+        .merge(super::streams::routes())
+        .merge(super::watch::routes())
+        .merge(super::pubsub::routes())
+        .merge(super::locks::routes())
+        .merge(super::queues::routes())
+        .merge(super::metrics::routes())
+        .fallback_service(keys.map_request(rewrite_key_path))
+        .layer(DefaultBodyLimit::max(body_limit))
+        .layer(middleware::from_fn_with_state(body_limit, reject_oversized));
+    let router = match &db.config().auth {
+        auth if auth.enabled => router.layer(middleware::from_fn_with_state(Arc::new(Auth::new(auth)), require_token)),
+        _ => router,
+    };
+    router
+        .layer(middleware::from_fn_with_state(limiter, limit_requests))
+        .layer(middleware::from_fn(json_rejections))
         .with_state(db)
 }
 
-async fn get_key(Path(key): Path<String>, State(db): State<Arc<Kline>>) -> impl IntoResponse {
-    let key_bytes = key.as_bytes();
-    match db.get(key_bytes) {
-        Ok(Some(value)) => {
-            let value_str = String::from_utf8_lossy(&value).to_string();
-            Json(ValueResponse::found(key, value_str))
+/// Routes for a single key, each matching a one-segment `{key}`.
+/// `create_router` reaches them through `rewrite_key_path`, as a fallback
+/// so that the path parameters of an outer route do not leak into them.
+fn key_routes() -> Router<Arc<Kline>> {
+    Router::new()
+        .route("/key/{key}", get(get_key).put(put_key).delete(delete_key))
+        .route("/key/{key}/incr", post(incr_key))
+        .route("/key/{key}/append", post(append_key))
+        .route("/key/{key}/history", get(key_history))
+        .route("/key/{key}/meta", get(key_meta))
+        .route("/key/{key}/rename", post(rename_key))
+        .route("/key/{key}/copy", post(copy_key))
+        .route("/key/{key}/ttl", get(key_ttl))
+        .route("/key/{key}/expire", post(expire_key))
+        .route("/key/{key}/persist", post(persist_key))
+}
+
+/// Sub-routes of `/key/{key}` and the method each one answers.
+const KEY_SUB_ROUTES: &[(&str, Method)] = &[
+    ("incr", Method::POST),
+    ("append", Method::POST),
+    ("history", Method::GET),
+    ("meta", Method::GET),
+    ("rename", Method::POST),
+    ("copy", Method::POST),
+    ("ttl", Method::GET),
+    ("expire", Method::POST),
+    ("persist", Method::POST),
+];
+
+/// Percent-encodes the slashes in the key part of a `/key/...` path so that
+/// keys such as `tenant/a/b` reach the one-segment routes in `key_routes`.
+/// A trailing `/meta`, `/incr` and so on is only taken as a sub-route for the
+/// method that sub-route answers; `?key_encoding=` reaches any other key.
+fn rewrite_key_path(mut request: Request) -> Request {
+    let uri = request.uri();
+    let Some(rest) = uri.path().strip_prefix("/key/") else {
+        return request;
+    };
+    let (key, sub_route) = split_key_path(rest, request.method());
+    let mut path = format!("/key/{}", key.replace('/', "%2F"));
+    if let Some(sub_route) = sub_route {
+        path.push('/');
+        path.push_str(sub_route);
+    }
+    if let Some(query) = uri.query() {
+        path.push('?');
+        path.push_str(query);
+    }
+    if let Ok(rewritten) = path.parse() {
+        *request.uri_mut() = rewritten;
+    }
+    request
+}
+
+/// Splits the part of a `/key/...` path after `/key/` into the key, still
+/// percent-encoded, and the sub-route `method` reaches, if any.
+pub(super) fn split_key_path<'a>(rest: &'a str, method: &Method) -> (&'a str, Option<&'static str>) {
+    KEY_SUB_ROUTES
+        .iter()
+        .filter(|(_, sub_method)| sub_method == method)
+        .find_map(|(name, _)| {
+            let key = rest.strip_suffix(name)?.strip_suffix('/')?;
+            (!key.is_empty()).then_some((key, Some(*name)))
+        })
+        .unwrap_or((rest, None))
+}
+
+/// Whether `GET /key/{key}` reaches `key` without `?key_encoding=`: it is
+/// valid UTF-8 and does not end in a `GET` sub-route such as `/meta`.
+fn addressable(key: &[u8]) -> bool {
+    let Ok(key) = std::str::from_utf8(key) else {
+        return false;
+    };
+    !KEY_SUB_ROUTES
+        .iter()
+        .filter(|(_, method)| method == Method::GET)
+        .any(|(name, _)| key.strip_suffix(name).is_some_and(|key| key.len() > 1 && key.ends_with('/')))
+}
+
+/// Returns the value as JSON, in `?encoding=` or else `utf8` when the
+/// value is valid UTF-8 and `base64` when it is not. With
+/// `Accept: application/octet-stream` the exact bytes are returned instead.
+/// A key with a TTL also gets an `X-Kline-TTL` header of seconds left.
+async fn get_key(key: KeyPath, Query(params): Query<ValueParams>, State(db): State<Arc<Kline>>, headers: HeaderMap) -> impl IntoResponse {
+    if let Some(range) = headers.get(header::RANGE).and_then(|v| v.to_str().ok()).and_then(parse_range) {
+        return get_key_range(&db, key, range).into_response();
+    }
+    if accepts_raw(&headers) {
+        return get_raw(key, State(db)).await.into_response();
+    }
+
+    match db.get_with_meta(&key.bytes) {
+        Ok(Some((value, meta))) => {
+            let encoding = params.encoding.unwrap_or_else(|| Encoding::detect(&value));
+            match encoding.encode(&value) {
+                Some(text) => with_ttl(Json(ValueResponse::encoded(key.id, text, encoding)), &meta),
+                None => bad_request("Value is not valid UTF-8; use encoding=base64 or hex"),
+            }
         }
-        Ok(None) => Json(ValueResponse::not_found(key.clone())),
-        Err(_) => Json(ValueResponse::not_found(key)),
+        Ok(None) => KlineError::KeyNotFound { key: key.id }.into_response(),
+        Err(err) => err.into_response(),
     }
 }
 
-async fn put_key(Path(key): Path<String>, State(db): State<Arc<Kline>>, body: Bytes) -> impl IntoResponse {
-    match db.put(key.into_bytes(), body.to_vec()) {
-        Ok(_) => Json(StatusResponse::ok()),
-        Err(err) => Json(StatusResponse::error(format!("Error storing key: {}", err))),
+fn accepts_raw(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|accept| accept.split(',').any(|t| t.trim().starts_with(OCTET_STREAM)))
+}
+
+const OCTET_STREAM: &str = "application/octet-stream";
+
+const TTL_HEADER: &str = "x-kline-ttl";
+
+/// Adds `X-Kline-TTL` with the seconds left, if the key has a TTL.
+fn with_ttl(response: impl IntoResponse, meta: &Metadata) -> axum::response::Response {
+    let mut response = response.into_response();
+    if let Some(ttl) = meta.ttl() {
+        response.headers_mut().insert(TTL_HEADER, ttl_secs(ttl).into());
     }
+    response
 }
 
-async fn delete_key(Path(key): Path<String>, State(db): State<Arc<Kline>>) -> impl IntoResponse {
-    match db.delete(&key.into_bytes()) {
-        Ok(_) => Json(StatusResponse::deleted()),
-        Err(err) => Json(StatusResponse::error(format!("Error deleting key: {}", err))),
+/// Returns the exact stored bytes with the key's stored `Content-Type`.
+async fn get_raw(key: KeyPath, State(db): State<Arc<Kline>>) -> impl IntoResponse {
+    match db.get_with_meta(&key.bytes) {
+        Ok(Some((value, meta))) => {
+            let content_type = meta.content_type.clone().unwrap_or_else(|| OCTET_STREAM.to_string());
+            with_ttl(([(header::CONTENT_TYPE, content_type)], value), &meta)
+        }
+        Ok(None) => KlineError::KeyNotFound { key: key.id }.into_response(),
+        Err(err) => err.into_response(),
     }
 }
 
-async fn get_all_keys(State(db): State<Arc<Kline>>) -> impl IntoResponse {
-    match db.keys() {
-        Ok(key_list) => {
-            let mut keys = vec![];
-            for key in key_list {
-                match std::str::from_utf8(&key) {
-                    Ok(k) => keys.push(k.to_string()),
-                    Err(_) => keys.push(base64::engine::general_purpose::STANDARD.encode(&key)),
-                }
-            }
-            Json(KeysResponse::new(keys))
+/// Serves a single `bytes=` range of a value as raw bytes with
+/// `206 Partial Content`.
+fn get_key_range(db: &Kline, key: KeyPath, range: ByteRange) -> axum::response::Response {
+    let slice = match db.get_slice_with_meta(&key.bytes, |total| range.resolve(total)) {
+        Ok(Some(slice)) => slice,
+        Ok(None) => return KlineError::KeyNotFound { key: key.id }.into_response(),
+        Err(err) => return err.into_response(),
+    };
+
+    let Some((start, bytes)) = slice.range else {
+        return (
+            StatusCode::RANGE_NOT_SATISFIABLE,
+            [(header::CONTENT_RANGE, format!("bytes */{}", slice.total))],
+        ).into_response();
+    };
+    let content_type = slice.meta.content_type.clone().unwrap_or_else(|| OCTET_STREAM.to_string());
+    let end = start + bytes.len() - 1;
+    let response = (
+        StatusCode::PARTIAL_CONTENT,
+        [
+            (header::CONTENT_TYPE, content_type),
+            (header::CONTENT_RANGE, format!("bytes {}-{}/{}", start, end, slice.total)),
+        ],
+        bytes,
+    );
+    with_ttl(response, &slice.meta)
+}
+
+/// A single `Range: bytes=` request, before the value's length is known.
+#[derive(Clone, Copy)]
+enum ByteRange {
+    /// `bytes=start-end` or, without an end, `bytes=start-`.
+    From(u64, Option<u64>),
+    /// `bytes=-suffix`, the last `suffix` bytes.
+    Suffix(u64),
+}
+
+impl ByteRange {
+    /// The inclusive, in-bounds byte range of a `total`-byte value, or
+    /// `None` if the range cannot be satisfied.
+    fn resolve(self, total: usize) -> Option<(usize, usize)> {
+        let total = total as u64;
+        let (start, end) = match self {
+            ByteRange::From(start, end) => (start, end.map_or(total, |end| end.saturating_add(1)).min(total)),
+            ByteRange::Suffix(suffix) => (total.saturating_sub(suffix), total),
+        };
+        (start < end).then(|| (start as usize, end as usize - 1))
+    }
+}
+
+/// Parses a `Range` header. Anything but a single, well-formed `bytes`
+/// range is `None` and, as HTTP allows, the header is ignored.
+fn parse_range(range: &str) -> Option<ByteRange> {
+    let (start, end) = range.trim().strip_prefix("bytes=")?.trim().split_once('-')?;
+    let number = |digits: &str| {
+        let digits = digits.trim();
+        if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        digits.parse::<u64>().ok()
+    };
+    match (start.trim().is_empty(), end.trim().is_empty()) {
+        (true, false) => number(end).map(ByteRange::Suffix),
+        (false, true) => number(start).map(|start| ByteRange::From(start, None)),
+        (false, false) => {
+            let (start, end) = (number(start)?, number(end)?);
+            (start <= end).then_some(ByteRange::From(start, Some(end)))
         }
-        Err(err) => Json(KeysResponse::error(&format!("Error getting keys: {}", err))),
+        (true, true) => None,
+    }
+}
+
+/// Stores the body, first decoding it if `?encoding=base64` or `hex` is
+/// given. A `Content-Type` header replaces the key's stored content type and
+/// an `X-Kline-Tags: name=value,...` header its tags; each is kept if its
+/// header is left out.
+/// The body is read as it arrives and rejected with `413` once it passes
+/// `max_value_size` in the request's encoding, or up front from its
+/// `Content-Length`.
+async fn put_key(key: KeyPath, Query(params): Query<ValueParams>, State(db): State<Arc<Kline>>, headers: HeaderMap, body: Body) -> impl IntoResponse {
+    let max = params
+        .encoding
+        .unwrap_or(Encoding::Utf8)
+        .max_encoded_len(db.config().limits.max_value_size);
+    let size = content_length(&headers);
+    if let Some(size) = size.filter(|size| *size > max) {
+        return KlineError::PayloadTooLarge { size, max }.into_response();
+    }
+    let body = match read_body(body, max, size).await {
+        Ok(body) => body,
+        Err(err) => return err.into_response(),
+    };
+    let value = match params.encoding {
+        Some(encoding) => match encoding.decode(&body) {
+            Some(value) => value,
+            None => return bad_request(format!("Body is not valid {}", encoding.as_str())),
+        },
+        None => body,
+    };
+    let ttl = match params.ttl.or_else(|| ttl_header(&headers)) {
+        Some(ttl) => match parse_ttl(&ttl) {
+            Some(ttl) => Some(ttl),
+            None => return invalid_ttl(&ttl),
+        },
+        None => None,
+    };
+    let meta = MetaUpdate {
+        content_type: headers.get(header::CONTENT_TYPE).and_then(|v| v.to_str().ok()).map(str::to_string),
+        tags: headers.get(TAGS_HEADER).and_then(|v| v.to_str().ok()).map(parse_tags),
+    };
+    let result = match ttl {
+        Some(ttl) => db.put_with_ttl(key.bytes, value, meta, ttl),
+        None => db.put_with_meta(key.bytes, value, meta),
+    };
+    match result {
+        Ok(_) => Json(StatusResponse::ok()).into_response(),
+        Err(err) => err.into_response(),
+    }
+}
+
+const TAGS_HEADER: &str = "x-kline-tags";
+
+/// The `X-Kline-TTL` request header as sent, if present.
+fn ttl_header(headers: &HeaderMap) -> Option<String> {
+    let value = headers.get(TTL_HEADER)?;
+    Some(String::from_utf8_lossy(value.as_bytes()).into_owned())
+}
+
+/// A TTL given as a whole number of seconds.
+fn parse_ttl(ttl: &str) -> Option<Duration> {
+    ttl.trim().parse().ok().map(Duration::from_secs)
+}
+
+fn parse_tags(header: &str) -> Tags {
+    header
+        .split(',')
+        .filter_map(|tag| tag.split_once('='))
+        .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+        .collect()
+}
+
+async fn key_meta(key: KeyPath, State(db): State<Arc<Kline>>) -> impl IntoResponse {
+    let key_type = db.key_type(&key.bytes);
+    match (db.meta(&key.bytes), key_type) {
+        (Ok(Some(meta)), Ok(Some(key_type))) => Json(MetaResponse::new(key.id, key_type, meta)).into_response(),
+        (Ok(_), Ok(_)) => KlineError::KeyNotFound { key: key.id }.into_response(),
+        (Err(err), _) | (_, Err(err)) => err.into_response(),
     }
 }
+
+/// Seconds left before the key expires. A `PUT` replaces the TTL (or
+/// clears it, without `?ttl=`); `incr` and `append` keep it.
+async fn key_ttl(key: KeyPath, State(db): State<Arc<Kline>>) -> impl IntoResponse {
+    match db.meta(&key.bytes) {
+        Ok(Some(meta)) => Json(TtlResponse::new(key.id, &meta)).into_response(),
+        Ok(None) => KlineError::KeyNotFound { key: key.id }.into_response(),
+        Err(err) => err.into_response(),
+    }
+}
+
+/// `POST /key/{key}/expire?ttl=N` sets the key to expire in N seconds. The
+/// TTL lasts until the next plain `PUT`; `incr` and `append` keep it.
+async fn expire_key(key: KeyPath, Query(params): Query<TtlParams>, State(db): State<Arc<Kline>>) -> impl IntoResponse {
+    let Some(ttl) = parse_ttl(&params.ttl) else {
+        return invalid_ttl(&params.ttl);
+    };
+    match db.expire(&key.bytes, ttl) {
+        Ok(true) => key_ttl(key, State(db)).await.into_response(),
+        Ok(false) => KlineError::KeyNotFound { key: key.id }.into_response(),
+        Err(err) => err.into_response(),
+    }
+}
+
+/// Removes the key's TTL; `persisted` is false if it had none.
+async fn persist_key(key: KeyPath, State(db): State<Arc<Kline>>) -> impl IntoResponse {
+    let persisted = match db.persist(&key.bytes) {
+        Ok(persisted) => persisted,
+        Err(err) => return err.into_response(),
+    };
+    match db.meta(&key.bytes) {
+        Ok(Some(_)) => Json(PersistResponse { key: key.id, persisted }).into_response(),
+        Ok(None) => KlineError::KeyNotFound { key: key.id }.into_response(),
+        Err(err) => err.into_response(),
+    }
+}
+
+async fn rename_key(key: KeyPath, Query(params): Query<RenameParams>, State(db): State<Arc<Kline>>) -> impl IntoResponse {
+    let to = match key.decode(&params.to) {
+        Ok(to) => to,
+        Err(invalid) => return invalid.into_response(),
+    };
+    match db.rename(&key.bytes, &to, params.overwrite.unwrap_or(false)) {
+        Ok(()) => Json(StatusResponse::ok()).into_response(),
+        Err(err) => err.into_response(),
+    }
+}
+
+async fn copy_key(key: KeyPath, Query(params): Query<RenameParams>, State(db): State<Arc<Kline>>) -> impl IntoResponse {
+    let to = match key.decode(&params.to) {
+        Ok(to) => to,
+        Err(invalid) => return invalid.into_response(),
+    };
+    match db.copy(&key.bytes, &to) {
+        Ok(()) => Json(StatusResponse::ok()).into_response(),
+        Err(err) => err.into_response(),
+    }
+}
+
+async fn append_key(key: KeyPath, State(db): State<Arc<Kline>>, body: Bytes) -> impl IntoResponse {
+    match db.append(&key.bytes, &body) {
+        Ok(length) => Json(LengthResponse::new(key.id, length)).into_response(),
+        Err(err) => err.into_response(),
+    }
+}
+
+async fn delete_key(key: KeyPath, State(db): State<Arc<Kline>>) -> impl IntoResponse {
+    match db.delete(&key.bytes) {
+        Ok(_) => Json(StatusResponse::deleted()).into_response(),
+        Err(err) => err.into_response(),
+    }
+}
+
+async fn incr_key(
+    key: KeyPath,
+    Query(params): Query<IncrParams>,
+    State(db): State<Arc<Kline>>,
+) -> impl IntoResponse {
+    let by = params.by.unwrap_or_else(|| "1".to_string());
+    let result = match by.parse::<i64>() {
+        Ok(by) => db.incr_by(&key.bytes, by).map(serde_json::Number::from),
+        Err(_) => match by.parse::<f64>() {
+            Ok(by) => db.incr_by_float(&key.bytes, by)
+                .map(|n| serde_json::Number::from_f64(n).expect("incr_by_float returns finite values")),
+            Err(_) => {
+                return bad_request(format!("Invalid increment: {}", by));
+            }
+        },
+    };
+
+    match result {
+        Ok(value) => Json(CounterResponse::new(key.id, value)).into_response(),
+        Err(err) => err.into_response(),
+    }
+}
+
+/// Lists retained versions, or with `?version=` or `?timestamp=` returns
+/// only the version current at that point.
+async fn key_history(key: KeyPath, Query(params): Query<HistoryParams>, State(db): State<Arc<Kline>>) -> impl IntoResponse {
+    let at = match (params.version, params.timestamp) {
+        (Some(version), _) => Some(At::Version(version)),
+        (None, Some(timestamp)) => Some(At::Timestamp(timestamp)),
+        (None, None) => None,
+    };
+    let result = match at {
+        Some(at) => db.get_at(&key.bytes, at).map(|version| version.into_iter().collect()),
+        None => db.history(&key.bytes),
+    };
+    match result {
+        Ok(versions) => Json(HistoryResponse::new(key.id, versions)).into_response(),
+        Err(err) => err.into_response(),
+    }
+}
+
+/// Deletes every key under `prefix`. `confirm` must repeat the prefix, and
+/// an empty prefix, which would match every key, is refused, so a mistyped
+/// or missing parameter cannot wipe the wrong keys.
+async fn delete_keys(Query(params): Query<DeletePrefixParams>, State(db): State<Arc<Kline>>) -> impl IntoResponse {
+    if params.confirm.as_deref() != Some(params.prefix.as_str()) {
+        return bad_request("confirm must repeat the prefix");
+    }
+    let prefix = match params.key_encoding {
+        Some(encoding) => match encoding.decode(params.prefix.as_bytes()) {
+            Some(prefix) => prefix,
+            None => return bad_request(format!("Prefix is not valid {}", encoding.as_str())),
+        },
+        None => params.prefix.as_bytes().to_vec(),
+    };
+    if prefix.is_empty() {
+        return bad_request("prefix must not be empty");
+    }
+    match db.delete_prefix(&prefix) {
+        Ok(count) => Json(CountResponse::new(params.prefix, count)).into_response(),
+        Err(err) => err.into_response(),
+    }
+}
+
+/// Lists one page of keys, smallest first. Keys are listed as-is when all
+/// of them can be fetched back by name; otherwise, or with `?key_encoding=`,
+/// all are encoded. Values follow the same rule for `?encoding=`.
+async fn list_keys(Query(params): Query<ListKeysParams>, State(db): State<Arc<Kline>>) -> impl IntoResponse {
+    let after = match params.cursor.as_deref().map(decode_cursor) {
+        Some(Some(after)) => Some(after),
+        Some(None) => return bad_request("Invalid cursor"),
+        None => None,
+    };
+    let scan = KeyScan {
+        prefix: params.prefix.unwrap_or_default().into_bytes(),
+        pattern: params.pattern.map(String::into_bytes),
+        after,
+    };
+    if params.count_only.unwrap_or(false) {
+        return match db.count_keys(&scan) {
+            Ok(count) => Json(KeyCountResponse { count }).into_response(),
+            Err(err) => err.into_response(),
+        };
+    }
+
+    let limit = params.limit.unwrap_or(DEFAULT_PAGE_SIZE);
+    if limit == 0 || limit > MAX_PAGE_SIZE {
+        return bad_request(format!("limit must be between 1 and {}", MAX_PAGE_SIZE));
+    }
+    let page = match db.scan_keys(&scan, limit, params.values.unwrap_or(false)) {
+        Ok(page) => page,
+        Err(err) => return err.into_response(),
+    };
+    let next_cursor = page.next.as_deref().map(encode_cursor);
+    let (keys, values): (Vec<_>, Vec<_>) = page.keys.into_iter().unzip();
+
+    let key_encoding = params.key_encoding.unwrap_or_else(|| {
+        if keys.iter().all(|key| addressable(key)) { Encoding::Utf8 } else { Encoding::Base64 }
+    });
+    let values = params.values.unwrap_or(false).then(|| {
+        let encoding = params.encoding.unwrap_or_else(|| {
+            let utf8 = values.iter().flatten().all(|value| std::str::from_utf8(value).is_ok());
+            if utf8 { Encoding::Utf8 } else { Encoding::Base64 }
+        });
+        (values, encoding)
+    });
+    if key_encoding == Encoding::Utf8 && keys.iter().any(|key| std::str::from_utf8(key).is_err()) {
+        return bad_request("Some keys are not valid UTF-8; use key_encoding=base64 or hex");
+    }
+    if let Some((values, Encoding::Utf8)) = &values
+        && values.iter().flatten().any(|value| std::str::from_utf8(value).is_err())
+    {
+        return bad_request("Some values are not valid UTF-8; use encoding=base64 or hex");
+    }
+    Json(KeysResponse::new(keys, key_encoding, values, next_cursor)).into_response()
+}
+
+/// Cursors are the last key of a page, base64url-encoded so they can be
+/// passed back in a query string as-is.
+fn encode_cursor(key: &[u8]) -> String {
+    base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(key)
+}
+
+fn decode_cursor(cursor: &str) -> Option<Vec<u8>> {
+    base64::engine::general_purpose::URL_SAFE_NO_PAD.decode(cursor).ok()
+}
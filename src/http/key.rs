@@ -0,0 +1,63 @@
+use axum::{
+    extract::{FromRequestParts, Path, Query},
+    http::request::Parts,
+    response::{IntoResponse, Response},
+};
+use serde::Deserialize;
+use super::encoding::Encoding;
+use super::error::bad_request;
+
+/// Query parameter selecting how the key in the path is encoded.
+#[derive(Deserialize)]
+pub struct KeyEncodingParams {
+    pub key_encoding: Option<Encoding>,
+}
+
+/// The key named by a route's `{key}` or `{*key}` segment, decoded with
+/// `?key_encoding=base64|hex` when given so that keys which are not valid
+/// UTF-8, or which would otherwise clash with a sub-route, can be addressed.
+pub struct KeyPath {
+    /// The key as written in the URL, echoed back in responses.
+    pub id: String,
+    pub bytes: Vec<u8>,
+    encoding: Option<Encoding>,
+}
+
+impl KeyPath {
+    /// Decodes another key from the same request, such as a rename target,
+    /// with the same `key_encoding`.
+    pub fn decode(&self, key: &str) -> Result<Vec<u8>, InvalidKey> {
+        decode_key(key, self.encoding)
+    }
+}
+
+/// A key that is not valid in the requested `key_encoding`.
+pub struct InvalidKey(Encoding);
+
+impl IntoResponse for InvalidKey {
+    fn into_response(self) -> Response {
+        bad_request(format!("Key is not valid {}", self.0.as_str()))
+    }
+}
+
+fn decode_key(key: &str, encoding: Option<Encoding>) -> Result<Vec<u8>, InvalidKey> {
+    match encoding {
+        Some(encoding) => encoding.decode(key.as_bytes()).ok_or(InvalidKey(encoding)),
+        None => Ok(key.as_bytes().to_vec()),
+    }
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for KeyPath {
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Path(id) = Path::<String>::from_request_parts(parts, state)
+            .await
+            .map_err(IntoResponse::into_response)?;
+        let Query(params) = Query::<KeyEncodingParams>::from_request_parts(parts, state)
+            .await
+            .map_err(IntoResponse::into_response)?;
+        let bytes = decode_key(&id, params.key_encoding).map_err(IntoResponse::into_response)?;
+        Ok(Self { id, bytes, encoding: params.key_encoding })
+    }
+}
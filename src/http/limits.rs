@@ -0,0 +1,54 @@
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    http::{header, HeaderMap},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use futures_util::StreamExt;
+use crate::config::LimitsConfig;
+use crate::constants::http::BODY_OVERHEAD;
+use crate::error::{KlineError, Result};
+use super::encoding::Encoding;
+
+/// Largest request body any route accepts: a value of `max_value_size` in
+/// its longest encoding (hex), plus room for the key and JSON framing.
+/// Bulk requests share this limit across all of their entries.
+pub fn body_limit(limits: &LimitsConfig) -> usize {
+    Encoding::Hex
+        .max_encoded_len(limits.max_value_size)
+        .saturating_add(limits.max_key_size)
+        .saturating_add(BODY_OVERHEAD)
+}
+
+/// The declared `Content-Length`, if any.
+pub fn content_length(headers: &HeaderMap) -> Option<usize> {
+    headers
+        .get(header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse().ok())
+}
+
+/// Rejects a request whose `Content-Length` is over `limit` with a JSON
+/// `413` before any of its body is read.
+pub async fn reject_oversized(State(limit): State<usize>, request: Request, next: Next) -> Response {
+    match content_length(request.headers()) {
+        Some(size) if size > limit => KlineError::PayloadTooLarge { size, max: limit }.into_response(),
+        _ => next.run(request).await,
+    }
+}
+
+/// Reads a body chunk by chunk, failing as soon as it passes `max` bytes,
+/// so a chunked upload without `Content-Length` is bounded as well.
+pub async fn read_body(body: Body, max: usize, size_hint: Option<usize>) -> Result<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(size_hint.unwrap_or(0).min(max));
+    let mut stream = body.into_data_stream();
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk.map_err(std::io::Error::other)?;
+        if bytes.len() + chunk.len() > max {
+            return Err(KlineError::PayloadTooLarge { size: bytes.len() + chunk.len(), max });
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+    Ok(bytes)
+}
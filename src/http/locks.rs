@@ -0,0 +1,56 @@
+use axum::{
+    extract::{Path, Query, State},
+    response::IntoResponse,
+    routing::{get, post},
+    Json,
+    Router,
+};
+use std::sync::Arc;
+use std::time::Duration;
+use crate::Kline;
+use super::requests::*;
+use super::responses::*;
+
+/// Routes for leases with fencing tokens.
+pub fn routes() -> Router<Arc<Kline>> {
+    Router::new()
+        .route("/lock/{name}", get(get_lease).post(acquire).delete(release))
+        .route("/lock/{name}/renew", post(renew))
+}
+
+async fn acquire(Path(name): Path<String>, Query(params): Query<LeaseParams>, State(db): State<Arc<Kline>>) -> impl IntoResponse {
+    let ttl = Duration::from_secs(params.ttl);
+    let acquired = match params.wait {
+        Some(wait) => db.acquire_lease_blocking(&name, &params.owner, ttl, Duration::from_secs(wait)).await,
+        None => db.acquire_lease(&name, &params.owner, ttl),
+    };
+    match acquired {
+        Ok(Some(lease)) => Json(LeaseResponse::acquired(lease)).into_response(),
+        Ok(None) => match db.lease(&name) {
+            Ok(holder) => Json(LeaseResponse::held(name, holder)).into_response(),
+            Err(err) => err.into_response(),
+        },
+        Err(err) => err.into_response(),
+    }
+}
+
+async fn renew(Path(name): Path<String>, Query(params): Query<LeaseParams>, State(db): State<Arc<Kline>>) -> impl IntoResponse {
+    match db.renew_lease(&name, &params.owner, Duration::from_secs(params.ttl)) {
+        Ok(lease) => Json(LeaseResponse::acquired(lease)).into_response(),
+        Err(err) => err.into_response(),
+    }
+}
+
+async fn release(Path(name): Path<String>, Query(params): Query<OwnerParams>, State(db): State<Arc<Kline>>) -> impl IntoResponse {
+    match db.release_lease(&name, &params.owner) {
+        Ok(()) => Json(StatusResponse::ok()).into_response(),
+        Err(err) => err.into_response(),
+    }
+}
+
+async fn get_lease(Path(name): Path<String>, State(db): State<Arc<Kline>>) -> impl IntoResponse {
+    match db.lease(&name) {
+        Ok(holder) => Json(LeaseResponse::held(name, holder)).into_response(),
+        Err(err) => err.into_response(),
+    }
+}
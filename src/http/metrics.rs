@@ -0,0 +1,45 @@
+use axum::{routing::get, Json, Router};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use crate::Kline;
+use super::responses::MetricsResponse;
+
+/// Process-wide counters for the HTTP server.
+pub struct Metrics {
+    pub connections_open: AtomicU64,
+    pub connections_total: AtomicU64,
+    /// Connections that had to wait because `max_connections` were open.
+    pub connections_limited: AtomicU64,
+    /// Requests rejected by `rate_limit.max_concurrent_requests`.
+    pub rejected_concurrency: AtomicU64,
+    /// Requests rejected by a client's `rate_limit.requests_per_sec`.
+    pub rejected_rate_limit: AtomicU64,
+}
+
+pub static METRICS: Metrics = Metrics {
+    connections_open: AtomicU64::new(0),
+    connections_total: AtomicU64::new(0),
+    connections_limited: AtomicU64::new(0),
+    rejected_concurrency: AtomicU64::new(0),
+    rejected_rate_limit: AtomicU64::new(0),
+};
+
+impl Metrics {
+    pub fn incr(counter: &AtomicU64) {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> MetricsResponse {
+        MetricsResponse {
+            connections_open: self.connections_open.load(Ordering::Relaxed),
+            connections_total: self.connections_total.load(Ordering::Relaxed),
+            connections_limited: self.connections_limited.load(Ordering::Relaxed),
+            rejected_concurrency: self.rejected_concurrency.load(Ordering::Relaxed),
+            rejected_rate_limit: self.rejected_rate_limit.load(Ordering::Relaxed),
+        }
+    }
+}
+
+pub fn routes() -> Router<Arc<Kline>> {
+    Router::new().route("/metrics", get(|| async { Json(METRICS.snapshot()) }))
+}
@@ -1,4 +1,23 @@
+pub mod auth;
+pub mod bulk;
+pub mod collections;
+pub mod encoding;
+pub mod error;
+#[allow(clippy::module_inception)]
 pub mod http;
+pub mod key;
+pub mod limits;
+pub mod locks;
+pub mod metrics;
+pub mod pubsub;
+pub mod queues;
+pub mod rate_limit;
+pub mod requests;
 pub mod responses;
+pub mod server;
+pub mod streams;
+pub mod tls;
+pub mod watch;
 
 pub use http::create_router;
+pub use server::serve;
@@ -0,0 +1,97 @@
+use axum::{
+    body::Bytes,
+    extract::{
+        ws::{Message as WsMessage, WebSocket, WebSocketUpgrade},
+        Path, Query, State,
+    },
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
+    routing::{get, post},
+    Json, Router,
+};
+use futures_util::stream;
+use std::convert::Infallible;
+use std::sync::Arc;
+use crate::storage::{ChannelFilter, ChannelSubscription, Message};
+use crate::Kline;
+use super::error::bad_request;
+use super::requests::*;
+use super::responses::*;
+
+/// Routes for fire-and-forget pub/sub channels.
+pub fn routes() -> Router<Arc<Kline>> {
+    Router::new()
+        .route("/publish/{channel}", post(publish))
+        .route("/subscribe", get(subscribe_sse))
+        .route("/subscribe/ws", get(subscribe_ws))
+}
+
+async fn publish(Path(channel): Path<String>, State(db): State<Arc<Kline>>, body: Bytes) -> Json<PublishResponse> {
+    let receivers = db.publish(&channel, body.to_vec());
+    Json(PublishResponse::new(channel, receivers))
+}
+
+fn filters(params: SubscribeParams) -> Vec<ChannelFilter> {
+    let split = |list: Option<String>| -> Vec<String> {
+        list.unwrap_or_default()
+            .split(',')
+            .filter(|name| !name.is_empty())
+            .map(str::to_string)
+            .collect()
+    };
+    let channels = split(params.channels).into_iter().map(ChannelFilter::Channel);
+    let patterns = split(params.patterns).into_iter().map(ChannelFilter::Pattern);
+    channels.chain(patterns).collect()
+}
+
+fn render(message: Message) -> String {
+    serde_json::to_string(&PubSubMessageResponse::new(message)).unwrap_or_default()
+}
+
+fn no_filters() -> Response {
+    bad_request("no channels or patterns given")
+}
+
+async fn subscribe_sse(Query(params): Query<SubscribeParams>, State(db): State<Arc<Kline>>) -> Response {
+    let filters = filters(params);
+    if filters.is_empty() {
+        return no_filters();
+    }
+    let subscription = db.subscribe_channels(filters);
+    let messages = stream::unfold(subscription, |mut subscription: ChannelSubscription| async move {
+        let data = render(subscription.recv().await?);
+        Some((Ok::<_, Infallible>(Event::default().event("message").data(data)), subscription))
+    });
+    Sse::new(messages).keep_alive(KeepAlive::default()).into_response()
+}
+
+async fn subscribe_ws(ws: WebSocketUpgrade, Query(params): Query<SubscribeParams>, State(db): State<Arc<Kline>>) -> Response {
+    let filters = filters(params);
+    if filters.is_empty() {
+        return no_filters();
+    }
+    let subscription = db.subscribe_channels(filters);
+    ws.on_upgrade(move |socket| forward_messages(socket, subscription))
+}
+
+/// Sends each message as a JSON text frame until the client disconnects.
+async fn forward_messages(mut socket: WebSocket, mut subscription: ChannelSubscription) {
+    loop {
+        tokio::select! {
+            message = subscription.recv() => {
+                let Some(message) = message else { break };
+                if socket.send(WsMessage::Text(render(message).into())).await.is_err() {
+                    break;
+                }
+            }
+            incoming = socket.recv() => {
+                match incoming {
+                    Some(Ok(WsMessage::Close(_))) | Some(Err(_)) | None => break,
+                    Some(Ok(_)) => {}
+                }
+            }
+        }
+    }
+}
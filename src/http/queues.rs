@@ -0,0 +1,79 @@
+use axum::{
+    body::Bytes,
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    routing::post,
+    Json,
+    Router,
+};
+use std::sync::Arc;
+use std::time::Duration;
+use crate::Kline;
+use super::encoding::Encoding;
+use super::error::bad_request;
+use super::requests::*;
+use super::responses::*;
+
+/// Routes for using lists as work queues: producers push with
+/// `/list/{key}/rpush`, consumers pop from the head here.
+pub fn routes() -> Router<Arc<Kline>> {
+    Router::new()
+        .route("/queue/{key}/pop", post(pop))
+        .route("/queue/{key}/ack", post(ack))
+}
+
+/// Pops an item and returns it in `?encoding=`, or else `utf8` when it is
+/// valid UTF-8 and `base64` when it is not. Replies `204 No Content` if no
+/// item arrives within `timeout`.
+async fn pop(Path(key): Path<String>, Query(params): Query<QueuePopParams>, State(db): State<Arc<Kline>>) -> impl IntoResponse {
+    let timeout = Duration::from_secs(params.timeout.unwrap_or(0));
+    let mut keys = vec![key];
+    keys.extend(params.keys.iter().flat_map(|keys| keys.split(',')).filter(|k| !k.is_empty()).map(str::to_string));
+
+    let popped = match params.in_flight {
+        Some(in_flight) if keys.len() == 1 => db
+            .blmove(keys[0].as_bytes(), in_flight.as_bytes(), true, false, timeout)
+            .await
+            .map(|item| item.map(|item| (keys[0].clone().into_bytes(), item))),
+        Some(_) => {
+            return bad_request("in_flight takes a single key");
+        }
+        None => {
+            let keys: Vec<&[u8]> = keys.iter().map(|key| key.as_bytes()).collect();
+            db.blpop(&keys, timeout).await
+        }
+    };
+
+    match popped {
+        Ok(Some((key, item))) => {
+            // The item is already off the queue, so rather than fail on
+            // `encoding=utf8` it falls back to base64, as the response says.
+            let encoding = params.encoding.unwrap_or_else(|| Encoding::detect(&item));
+            let (text, encoding) = match encoding.encode(&item) {
+                Some(text) => (text, encoding),
+                None => (Encoding::Base64.encode(&item).expect("base64 encodes any bytes"), Encoding::Base64),
+            };
+            Json(ValueResponse::encoded(String::from_utf8_lossy(&key).to_string(), text, encoding)).into_response()
+        }
+        Ok(None) => StatusCode::NO_CONTENT.into_response(),
+        Err(err) => err.into_response(),
+    }
+}
+
+/// Acknowledges an item taken with `in_flight` by removing it from the
+/// in-flight list named in the path. The body is decoded with `?encoding=`
+/// first, so an item can be acknowledged exactly as `pop` returned it.
+async fn ack(Path(key): Path<String>, Query(params): Query<QueueAckParams>, State(db): State<Arc<Kline>>, body: Bytes) -> impl IntoResponse {
+    let item = match params.encoding {
+        Some(encoding) => match encoding.decode(&body) {
+            Some(item) => item,
+            None => return bad_request(format!("Body is not valid {}", encoding.as_str())),
+        },
+        None => body.to_vec(),
+    };
+    match db.lrem(key.as_bytes(), 1, &item) {
+        Ok(count) => Json(CountResponse::new(key, count)).into_response(),
+        Err(err) => err.into_response(),
+    }
+}
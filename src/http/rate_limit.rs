@@ -0,0 +1,114 @@
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{header, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::Semaphore;
+use crate::config::RateLimitConfig;
+use crate::constants::http::RATE_LIMIT_PRUNE_THRESHOLD;
+use super::error::error_response;
+use super::metrics::{Metrics, METRICS};
+use super::server::ClientAddr;
+
+/// Shared state of the `limit_requests` middleware.
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    in_flight: Option<Arc<Semaphore>>,
+    buckets: Mutex<HashMap<IpAddr, Bucket>>,
+}
+
+/// Token bucket for one client IP, refilled at `requests_per_sec` up to
+/// `burst` tokens.
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn refill(&mut self, now: Instant, rate: f64, burst: f64) {
+        let elapsed = now.duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * rate).min(burst);
+        self.updated = now;
+    }
+}
+
+impl RateLimiter {
+    pub fn new(config: &RateLimitConfig) -> Self {
+        Self {
+            config: config.clone(),
+            in_flight: (config.max_concurrent_requests > 0)
+                .then(|| Arc::new(Semaphore::new(config.max_concurrent_requests))),
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Takes a token for `ip`, or returns how long until one is available.
+    fn take(&self, ip: IpAddr) -> Result<(), Duration> {
+        let rate = self.config.requests_per_sec;
+        if rate <= 0.0 {
+            return Ok(());
+        }
+        let burst = f64::from(self.config.burst.max(1));
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        // A full bucket is the same as none, so forget idle clients rather
+        // than letting the map grow with every address ever seen.
+        if buckets.len() >= RATE_LIMIT_PRUNE_THRESHOLD && !buckets.contains_key(&ip) {
+            buckets.retain(|_, bucket| {
+                bucket.refill(now, rate, burst);
+                bucket.tokens < burst
+            });
+        }
+
+        let bucket = buckets.entry(ip).or_insert(Bucket { tokens: burst, updated: now });
+        bucket.refill(now, rate, burst);
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / rate))
+        }
+    }
+}
+
+/// Rejects a request with `429` and `Retry-After` when its client IP is out
+/// of tokens or `max_concurrent_requests` are already being handled. The
+/// client IP comes from `ConnectInfo<ClientAddr>`; without it only the
+/// concurrency limit applies.
+pub async fn limit_requests(State(limiter): State<Arc<RateLimiter>>, request: Request, next: Next) -> Response {
+    if let Some(ConnectInfo(ClientAddr(addr))) = request.extensions().get::<ConnectInfo<ClientAddr>>()
+        && let Err(wait) = limiter.take(addr.ip())
+    {
+        Metrics::incr(&METRICS.rejected_rate_limit);
+        return too_many_requests("rate_limited", format!("Rate limit exceeded for {}", addr.ip()), wait);
+    }
+
+    let _permit = match &limiter.in_flight {
+        Some(in_flight) => match in_flight.clone().try_acquire_owned() {
+            Ok(permit) => Some(permit),
+            Err(_) => {
+                Metrics::incr(&METRICS.rejected_concurrency);
+                return too_many_requests(
+                    "too_many_requests",
+                    format!("Too many requests in progress (max: {})", limiter.config.max_concurrent_requests),
+                    Duration::from_secs(1),
+                );
+            }
+        },
+        None => None,
+    };
+    next.run(request).await
+}
+
+fn too_many_requests(error: &'static str, message: String, wait: Duration) -> Response {
+    let retry_after = wait.as_secs_f64().ceil().max(1.0) as u64;
+    let mut response = error_response(StatusCode::TOO_MANY_REQUESTS, error, message);
+    response.headers_mut().insert(header::RETRY_AFTER, retry_after.into());
+    response.into_response()
+}
@@ -0,0 +1,244 @@
+use std::collections::BTreeMap;
+use serde::Deserialize;
+use super::encoding::Encoding;
+
+/// Query parameters for `POST /key/{key}/incr`
+#[derive(Deserialize)]
+pub struct IncrParams {
+    pub by: Option<String>,
+}
+
+/// Query parameters for `GET` and `PUT /key/{key}`. On reads, `encoding`
+/// picks how the value is carried in JSON; on writes, how the body is
+/// decoded before it is stored.
+#[derive(Deserialize)]
+pub struct ValueParams {
+    pub encoding: Option<Encoding>,
+    /// Seconds until the key expires, on writes. The `X-Kline-TTL` header
+    /// does the same. Kept as text so a non-number is an `invalid_ttl`.
+    pub ttl: Option<String>,
+}
+
+/// Query parameters for `POST /key/{key}/expire`
+#[derive(Deserialize)]
+pub struct TtlParams {
+    pub ttl: String,
+}
+
+/// Query parameters for list range reads
+#[derive(Deserialize)]
+pub struct RangeParams {
+    pub start: Option<i64>,
+    pub stop: Option<i64>,
+}
+
+/// Query parameters for pops
+#[derive(Deserialize)]
+pub struct CountParams {
+    pub count: Option<usize>,
+}
+
+/// Body for pushes: `{"values": ["a", "b"]}`
+#[derive(Deserialize)]
+pub struct ValuesRequest {
+    pub values: Vec<String>,
+}
+
+impl ValuesRequest {
+    pub fn into_bytes(self) -> Vec<Vec<u8>> {
+        self.values.into_iter().map(String::into_bytes).collect()
+    }
+}
+
+/// Query parameters for sorted set score range reads. Missing bounds are
+/// unbounded.
+#[derive(Deserialize)]
+pub struct ScoreRangeParams {
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    pub limit: Option<usize>,
+}
+
+/// One member of a `ZaddRequest`
+#[derive(Deserialize)]
+pub struct ScoredMember {
+    pub member: String,
+    pub score: f64,
+}
+
+/// Body for sorted set adds: `{"members": [{"member": "a", "score": 1.0}]}`
+#[derive(Deserialize)]
+pub struct ZaddRequest {
+    pub members: Vec<ScoredMember>,
+}
+
+/// Body for stream appends. `id` is omitted or `*` to auto-generate one.
+#[derive(Deserialize)]
+pub struct XaddRequest {
+    pub id: Option<String>,
+    pub fields: BTreeMap<String, String>,
+}
+
+/// Query parameters for stream range reads. `-` and `+` mean the lowest
+/// and highest IDs.
+#[derive(Deserialize)]
+pub struct StreamRangeParams {
+    pub start: Option<String>,
+    pub end: Option<String>,
+    pub count: Option<usize>,
+}
+
+/// Query parameters for reading new stream entries, optionally blocking.
+/// `after=$` means entries added after the request arrives.
+#[derive(Deserialize)]
+pub struct StreamReadParams {
+    pub after: Option<String>,
+    pub count: Option<usize>,
+    pub block_ms: Option<u64>,
+}
+
+/// Query parameters for stream trimming
+#[derive(Deserialize)]
+pub struct TrimParams {
+    pub max_len: Option<usize>,
+    pub max_age_secs: Option<u64>,
+}
+
+/// Query parameters for consumer group creation. `$` or no `start` means
+/// only new entries.
+#[derive(Deserialize)]
+pub struct GroupCreateParams {
+    pub start: Option<String>,
+}
+
+/// Query parameters for consumer group reads
+#[derive(Deserialize)]
+pub struct GroupReadParams {
+    pub consumer: String,
+    pub count: Option<usize>,
+    pub block_ms: Option<u64>,
+}
+
+/// Body for acknowledging stream entries: `{"ids": ["1700000000000-0"]}`
+#[derive(Deserialize)]
+pub struct AckRequest {
+    pub ids: Vec<String>,
+}
+
+/// Query parameters for watching changes: an exact `key`, a `prefix`, or
+/// neither for every key.
+#[derive(Deserialize)]
+pub struct WatchParams {
+    pub key: Option<String>,
+    pub prefix: Option<String>,
+}
+
+/// Query parameters for `/subscribe`: comma-separated channel names and
+/// glob patterns
+#[derive(Deserialize)]
+pub struct SubscribeParams {
+    pub channels: Option<String>,
+    pub patterns: Option<String>,
+}
+
+/// Query parameters for acquiring or renewing a lease. `wait` long-polls
+/// for up to that many seconds while another owner holds the lease.
+#[derive(Deserialize)]
+pub struct LeaseParams {
+    pub owner: String,
+    pub ttl: u64,
+    pub wait: Option<u64>,
+}
+
+/// Query parameters identifying a lease owner
+#[derive(Deserialize)]
+pub struct OwnerParams {
+    pub owner: String,
+}
+
+/// Query parameters for `POST /queue/{key}/pop`. `timeout` is in seconds
+/// (0 or absent returns immediately), `keys` lists further comma-separated
+/// queues to try in order, and `in_flight` names a list to move the item
+/// into until it is acknowledged.
+#[derive(Deserialize)]
+pub struct QueuePopParams {
+    pub timeout: Option<u64>,
+    pub keys: Option<String>,
+    pub in_flight: Option<String>,
+    pub encoding: Option<Encoding>,
+}
+
+/// Query parameters for `GET /hash/{key}/{field}`: how the value is carried
+/// in JSON, `utf8` if it is valid UTF-8 and otherwise `base64` by default.
+#[derive(Deserialize)]
+pub struct HashGetParams {
+    pub encoding: Option<Encoding>,
+}
+
+/// Query parameters for `POST /queue/{in_flight}/ack`: how the item in the
+/// body is encoded, as returned by the pop that took it.
+#[derive(Deserialize)]
+pub struct QueueAckParams {
+    pub encoding: Option<Encoding>,
+}
+
+/// Query parameters for `GET /key/{key}/history`: a sequence number or a
+/// timestamp in milliseconds to read a single version at
+#[derive(Deserialize)]
+pub struct HistoryParams {
+    pub version: Option<u64>,
+    pub timestamp: Option<u64>,
+}
+
+/// Query parameters for `POST /key/{key}/rename` and `/copy`. `overwrite`
+/// only applies to renames.
+#[derive(Deserialize)]
+pub struct RenameParams {
+    pub to: String,
+    pub overwrite: Option<bool>,
+}
+
+/// Query parameters for `GET /keys`. `cursor` is the `next_cursor` of the
+/// previous page.
+#[derive(Deserialize)]
+pub struct ListKeysParams {
+    pub limit: Option<usize>,
+    pub cursor: Option<String>,
+    pub prefix: Option<String>,
+    pub pattern: Option<String>,
+    pub values: Option<bool>,
+    pub count_only: Option<bool>,
+    pub key_encoding: Option<Encoding>,
+    pub encoding: Option<Encoding>,
+}
+
+/// Query parameters for `/mget`, `/mput` and `/mdelete`: how keys and
+/// values in the body are encoded.
+#[derive(Deserialize)]
+pub struct BulkParams {
+    pub key_encoding: Option<Encoding>,
+    pub encoding: Option<Encoding>,
+}
+
+/// Body for `/mget` and `/mdelete`: `{"keys": ["a", "b"]}`
+#[derive(Deserialize)]
+pub struct KeysRequest {
+    pub keys: Vec<String>,
+}
+
+/// Body for `/mput`: `{"entries": {"a": "1"}, "atomic": true}`. Without
+/// `atomic`, each entry is stored or rejected on its own.
+#[derive(Deserialize)]
+pub struct MputRequest {
+    pub entries: BTreeMap<String, String>,
+    pub atomic: Option<bool>,
+}
+
+/// Query parameters for `DELETE /keys`: `confirm` must equal `prefix`, which
+/// is decoded with `key_encoding` when given.
+#[derive(Deserialize)]
+pub struct DeletePrefixParams {
+    pub prefix: String,
+    pub confirm: Option<String>,
+    pub key_encoding: Option<Encoding>,
+}
@@ -1,4 +1,9 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
 use serde::Serialize;
+use super::encoding::Encoding;
+use crate::KlineError;
+use crate::storage::{ChangeEvent, Fields, Lease, Message, Metadata, PendingEntry, StreamId, Tags, Version};
 
 /// Response for operations that return a simple status
 #[derive(Serialize)]
@@ -12,22 +17,294 @@ pub struct ValueResponse {
     pub key: String,
     pub value: Option<String>,
     pub found: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub encoding: Option<&'static str>,
 }
 
-/// Response for listing all keys
+/// One page of a key listing. Every key is written in `key_encoding`, and
+/// can be fetched again with `/key/{key}?key_encoding=` set to it. `values`
+/// is only present when requested, null for keys that are not strings.
 #[derive(Serialize)]
 pub struct KeysResponse {
     pub keys: Vec<String>,
     pub count: usize,
+    pub key_encoding: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub values: Option<Vec<Option<String>>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value_encoding: Option<&'static str>,
+    pub next_cursor: Option<String>,
 }
 
-/// Error response with more detailed information
+/// Response for `GET /keys?count_only=true`
+#[derive(Serialize)]
+pub struct KeyCountResponse {
+    pub count: usize,
+}
+
+/// Response for counter operations
+#[derive(Serialize)]
+pub struct CounterResponse {
+    pub key: String,
+    pub value: serde_json::Number,
+}
+
+/// Response for operations that return the new length of a value
+#[derive(Serialize)]
+pub struct LengthResponse {
+    pub key: String,
+    pub length: usize,
+}
+
+/// Response for operations that return several values, such as list reads
+#[derive(Serialize)]
+pub struct ValuesResponse {
+    pub key: String,
+    pub values: Vec<String>,
+    pub count: usize,
+}
+
+/// Response for reading a whole hash
+#[derive(Serialize)]
+pub struct HashResponse {
+    pub key: String,
+    pub fields: BTreeMap<String, String>,
+}
+
+/// Response for set membership checks
+#[derive(Serialize)]
+pub struct MemberResponse {
+    pub key: String,
+    pub member: String,
+    pub is_member: bool,
+}
+
+/// Response for operations that return how many elements they affected
+#[derive(Serialize)]
+pub struct CountResponse {
+    pub key: String,
+    pub count: usize,
+}
+
+/// A sorted set member with its score
+#[derive(Serialize)]
+pub struct ScoredMemberEntry {
+    pub member: String,
+    pub score: f64,
+}
+
+/// Response for sorted set range reads and pops, in ascending score order
+#[derive(Serialize)]
+pub struct ScoredMembersResponse {
+    pub key: String,
+    pub members: Vec<ScoredMemberEntry>,
+    pub count: usize,
+}
+
+/// Response for a single sorted set member lookup
+#[derive(Serialize)]
+pub struct ScoreResponse {
+    pub key: String,
+    pub member: String,
+    pub score: Option<f64>,
+    pub rank: Option<usize>,
+    pub found: bool,
+}
+
+/// Response for stream appends
+#[derive(Serialize)]
+pub struct StreamIdResponse {
+    pub key: String,
+    pub id: String,
+}
+
+/// A stream entry with its fields
+#[derive(Serialize)]
+pub struct StreamEntry {
+    pub id: String,
+    pub fields: BTreeMap<String, String>,
+}
+
+/// Response for stream reads, oldest entry first
+#[derive(Serialize)]
+pub struct StreamEntriesResponse {
+    pub key: String,
+    pub entries: Vec<StreamEntry>,
+    pub count: usize,
+}
+
+/// An entry delivered to a consumer but not yet acknowledged
+#[derive(Serialize)]
+pub struct PendingEntryInfo {
+    pub id: String,
+    pub consumer: String,
+    pub idle_ms: u64,
+    pub delivery_count: u32,
+}
+
+/// Response for a consumer group's pending entries
+#[derive(Serialize)]
+pub struct PendingResponse {
+    pub key: String,
+    pub group: String,
+    pub pending: Vec<PendingEntryInfo>,
+    pub count: usize,
+}
+
+/// A committed key mutation pushed to watchers
+#[derive(Serialize)]
+pub struct ChangeEventResponse {
+    pub seq: u64,
+    pub op: &'static str,
+    pub key: String,
+    pub value: Option<String>,
+    pub timestamp: u64,
+}
+
+/// Sent to watchers in place of events dropped because they fell behind
+#[derive(Serialize)]
+pub struct OverflowResponse {
+    pub dropped: u64,
+}
+
+/// Response for publishing to a channel
+#[derive(Serialize)]
+pub struct PublishResponse {
+    pub channel: String,
+    pub receivers: usize,
+}
+
+/// A pub/sub message as delivered to subscribers
+#[derive(Serialize)]
+pub struct PubSubMessageResponse {
+    pub channel: String,
+    pub pattern: Option<String>,
+    pub payload: String,
+}
+
+/// Response for lease operations. `token` is only included for the owner
+/// that just acquired or renewed the lease.
+#[derive(Serialize)]
+pub struct LeaseResponse {
+    pub name: String,
+    pub acquired: bool,
+    pub owner: Option<String>,
+    pub token: Option<u64>,
+    pub expires_at: Option<u64>,
+}
+
+/// A retained version of a key; `value` is null if the key was deleted
+#[derive(Serialize)]
+pub struct VersionEntry {
+    pub version: u64,
+    pub timestamp: u64,
+    pub value: Option<String>,
+}
+
+/// Response for history reads, oldest version first
+#[derive(Serialize)]
+pub struct HistoryResponse {
+    pub key: String,
+    pub versions: Vec<VersionEntry>,
+    pub count: usize,
+}
+
+/// Response for key metadata; times are milliseconds since the epoch
+#[derive(Serialize)]
+pub struct MetaResponse {
+    pub key: String,
+    #[serde(rename = "type")]
+    pub key_type: &'static str,
+    pub created_at: u64,
+    pub updated_at: u64,
+    pub content_type: Option<String>,
+    pub tags: Tags,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<u64>,
+}
+
+/// Response for `GET /key/{key}/ttl`. `ttl` is the whole seconds left and
+/// `expires_at` milliseconds since the epoch; both are null for a key that
+/// does not expire.
+#[derive(Serialize)]
+pub struct TtlResponse {
+    pub key: String,
+    pub ttl: Option<u64>,
+    pub expires_at: Option<u64>,
+}
+
+/// Response for `POST /key/{key}/persist`
+#[derive(Serialize)]
+pub struct PersistResponse {
+    pub key: String,
+    pub persisted: bool,
+}
+
+/// Response for `/mget`, one item per requested key in request order
+#[derive(Serialize)]
+pub struct MgetResponse {
+    pub items: Vec<ValueResponse>,
+    pub count: usize,
+}
+
+/// Outcome of one item of a bulk write; `error` and `message` are set when
+/// `status` is not 200
+#[derive(Serialize)]
+pub struct ItemStatus {
+    pub key: String,
+    pub status: u16,
+    pub error: Option<&'static str>,
+    pub message: Option<String>,
+}
+
+/// Response for `/mput` and `/mdelete`
+#[derive(Serialize)]
+pub struct BulkResponse {
+    pub items: Vec<ItemStatus>,
+    pub succeeded: usize,
+    pub failed: usize,
+}
+
+/// Error response with a stable machine-readable `error` code
 #[derive(Serialize)]
 pub struct ErrorResponse {
     pub error: &'static str,
     pub message: String,
 }
 
+/// Response for `GET /metrics`
+#[derive(Serialize)]
+pub struct MetricsResponse {
+    pub connections_open: u64,
+    pub connections_total: u64,
+    pub connections_limited: u64,
+    pub rejected_concurrency: u64,
+    pub rejected_rate_limit: u64,
+}
+
+impl ItemStatus {
+    pub fn ok(key: String) -> Self {
+        Self { key, status: 200, error: None, message: None }
+    }
+
+    pub fn failed(key: String, err: &KlineError) -> Self {
+        Self {
+            key,
+            status: err.status().as_u16(),
+            error: Some(err.code()),
+            message: Some(err.to_string()),
+        }
+    }
+}
+
+impl BulkResponse {
+    pub fn new(items: Vec<ItemStatus>) -> Self {
+        let succeeded = items.iter().filter(|item| item.status == 200).count();
+        let failed = items.len() - succeeded;
+        Self { items, succeeded, failed }
+    }
+}
+
 impl StatusResponse {
     pub fn ok() -> Self {
         Self { status: String::from("OK") }
@@ -36,10 +313,6 @@ impl StatusResponse {
     pub fn deleted() -> Self {
         Self { status: String::from("Deleted") }
     }
-    
-    pub fn error(msg: String) -> Self {
-        Self { status: msg }
-    }
 }
 
 impl ValueResponse {
@@ -48,6 +321,17 @@ impl ValueResponse {
             key,
             value: Some(value),
             found: true,
+            encoding: None,
+        }
+    }
+
+    /// A found value carried as text in the given encoding.
+    pub fn encoded(key: String, value: String, encoding: Encoding) -> Self {
+        Self {
+            key,
+            value: Some(value),
+            found: true,
+            encoding: Some(encoding.as_str()),
         }
     }
     
@@ -56,20 +340,234 @@ impl ValueResponse {
             key,
             value: None,
             found: false,
+            encoding: None,
         }
     }
 }
 
-impl KeysResponse {
-    pub fn new(keys: Vec<String>) -> Self {
-        let count = keys.len();
-        Self { keys, count }
+impl CounterResponse {
+    pub fn new(key: String, value: serde_json::Number) -> Self {
+        Self { key, value }
     }
-    
-    pub fn error(msg: &str) -> Self {
-        Self { 
-            keys: vec![format!("Error: {}", msg)], 
-            count: 0 
+}
+
+impl LengthResponse {
+    pub fn new(key: String, length: usize) -> Self {
+        Self { key, length }
+    }
+}
+
+impl ValuesResponse {
+    pub fn new(key: String, values: Vec<Vec<u8>>) -> Self {
+        let values: Vec<String> = values
+            .iter()
+            .map(|v| String::from_utf8_lossy(v).to_string())
+            .collect();
+        let count = values.len();
+        Self { key, values, count }
+    }
+}
+
+impl HashResponse {
+    pub fn new(key: String, fields: Vec<(Vec<u8>, Vec<u8>)>) -> Self {
+        let fields = fields
+            .iter()
+            .map(|(f, v)| (String::from_utf8_lossy(f).to_string(), String::from_utf8_lossy(v).to_string()))
+            .collect();
+        Self { key, fields }
+    }
+}
+
+impl MemberResponse {
+    pub fn new(key: String, member: String, is_member: bool) -> Self {
+        Self { key, member, is_member }
+    }
+}
+
+impl MetaResponse {
+    pub fn new(key: String, key_type: &'static str, meta: Metadata) -> Self {
+        Self {
+            key,
+            key_type,
+            created_at: meta.created_at,
+            updated_at: meta.updated_at,
+            content_type: meta.content_type,
+            tags: meta.tags,
+            expires_at: meta.expires_at,
         }
     }
+}
+
+impl TtlResponse {
+    pub fn new(key: String, meta: &Metadata) -> Self {
+        Self {
+            key,
+            ttl: meta.ttl().map(ttl_secs),
+            expires_at: meta.expires_at,
+        }
+    }
+}
+
+/// Whole seconds of `ttl`, rounded up so a live key never reports 0.
+pub fn ttl_secs(ttl: Duration) -> u64 {
+    ttl.as_millis().div_ceil(1000) as u64
+}
+
+impl HistoryResponse {
+    pub fn new(key: String, versions: Vec<Version>) -> Self {
+        let versions: Vec<VersionEntry> = versions
+            .into_iter()
+            .map(|version| VersionEntry {
+                version: version.version,
+                timestamp: version.timestamp,
+                value: version.value.map(|value| String::from_utf8_lossy(&value).to_string()),
+            })
+            .collect();
+        let count = versions.len();
+        Self { key, versions, count }
+    }
+}
+
+impl LeaseResponse {
+    pub fn acquired(lease: Lease) -> Self {
+        Self {
+            name: lease.name,
+            acquired: true,
+            owner: Some(lease.owner),
+            token: Some(lease.token),
+            expires_at: Some(lease.expires_at),
+        }
+    }
+
+    /// Describes the current holder, if any, without revealing its token.
+    pub fn held(name: String, holder: Option<Lease>) -> Self {
+        Self {
+            name,
+            acquired: false,
+            owner: holder.as_ref().map(|lease| lease.owner.clone()),
+            token: None,
+            expires_at: holder.map(|lease| lease.expires_at),
+        }
+    }
+}
+
+impl PublishResponse {
+    pub fn new(channel: String, receivers: usize) -> Self {
+        Self { channel, receivers }
+    }
+}
+
+impl PubSubMessageResponse {
+    pub fn new(message: Message) -> Self {
+        Self {
+            channel: message.channel,
+            pattern: message.pattern,
+            payload: String::from_utf8_lossy(&message.payload).to_string(),
+        }
+    }
+}
+
+impl CountResponse {
+    pub fn new(key: String, count: usize) -> Self {
+        Self { key, count }
+    }
+}
+
+impl ScoredMembersResponse {
+    pub fn new(key: String, members: Vec<(Vec<u8>, f64)>) -> Self {
+        let members: Vec<ScoredMemberEntry> = members
+            .into_iter()
+            .map(|(member, score)| ScoredMemberEntry {
+                member: String::from_utf8_lossy(&member).to_string(),
+                score,
+            })
+            .collect();
+        let count = members.len();
+        Self { key, members, count }
+    }
+}
+
+impl ScoreResponse {
+    pub fn new(key: String, member: String, score: Option<f64>, rank: Option<usize>) -> Self {
+        Self { key, member, score, rank, found: score.is_some() }
+    }
+}
+
+impl StreamIdResponse {
+    pub fn new(key: String, id: StreamId) -> Self {
+        Self { key, id: id.to_string() }
+    }
+}
+
+impl StreamEntriesResponse {
+    pub fn new(key: String, entries: Vec<(StreamId, Fields)>) -> Self {
+        let entries: Vec<StreamEntry> = entries
+            .into_iter()
+            .map(|(id, fields)| StreamEntry {
+                id: id.to_string(),
+                fields: fields
+                    .iter()
+                    .map(|(f, v)| (String::from_utf8_lossy(f).to_string(), String::from_utf8_lossy(v).to_string()))
+                    .collect(),
+            })
+            .collect();
+        let count = entries.len();
+        Self { key, entries, count }
+    }
+}
+
+impl PendingResponse {
+    pub fn new(key: String, group: String, pending: Vec<(StreamId, PendingEntry)>) -> Self {
+        let now_ms = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_millis() as u64);
+        let pending: Vec<PendingEntryInfo> = pending
+            .into_iter()
+            .map(|(id, entry)| PendingEntryInfo {
+                id: id.to_string(),
+                consumer: entry.consumer,
+                idle_ms: now_ms.saturating_sub(entry.delivered_ms),
+                delivery_count: entry.delivery_count,
+            })
+            .collect();
+        let count = pending.len();
+        Self { key, group, pending, count }
+    }
+}
+
+impl ChangeEventResponse {
+    pub fn new(event: ChangeEvent) -> Self {
+        Self {
+            seq: event.seq,
+            op: event.op.as_str(),
+            key: String::from_utf8_lossy(&event.key).to_string(),
+            value: event.value.map(|v| String::from_utf8_lossy(&v).to_string()),
+            timestamp: event.timestamp,
+        }
+    }
+}
+
+impl KeysResponse {
+    /// Encodes `keys` with `encoding`; `values` gives the values and their
+    /// encoding when they were requested.
+    pub fn new(
+        keys: Vec<Vec<u8>>,
+        encoding: Encoding,
+        values: Option<(Vec<Option<Vec<u8>>>, Encoding)>,
+        next_cursor: Option<String>,
+    ) -> Self {
+        let keys: Vec<String> = keys
+            .iter()
+            .filter_map(|key| encoding.encode(key))
+            .collect();
+        let count = keys.len();
+        let value_encoding = values.as_ref().map(|(_, encoding)| encoding.as_str());
+        let values = values.map(|(values, encoding)| {
+            values
+                .iter()
+                .map(|value| value.as_deref().and_then(|value| encoding.encode(value)))
+                .collect()
+        });
+        Self { keys, count, key_encoding: encoding.as_str(), values, value_encoding, next_cursor }
+    }
 }
\ No newline at end of file
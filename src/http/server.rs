@@ -0,0 +1,130 @@
+use axum::{
+    extract::connect_info::Connected,
+    serve::{IncomingStream, Listener},
+    Router,
+};
+use std::io;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::TcpListener;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use crate::config::ServerConfig;
+use crate::error::Result;
+use super::metrics::{Metrics, METRICS};
+use super::tls::{Certificates, TlsListener};
+
+/// Serves `app` on `listener` with at most `max_connections` open at once,
+/// over TLS when `[server.tls]` is set. Handlers can read the client
+/// address as `ConnectInfo<ClientAddr>`.
+pub async fn serve(listener: TcpListener, app: Router, config: &ServerConfig) -> Result<()> {
+    let app = app.into_make_service_with_connect_info::<ClientAddr>();
+    match &config.tls {
+        Some(tls) => {
+            let certificates = Arc::new(Certificates::load(tls)?);
+            certificates.reload_on_sighup()?;
+            let listener = LimitedListener::new(listener, config.max_connections);
+            axum::serve(TlsListener::new(listener, certificates)?, app).await?;
+        }
+        None => axum::serve(LimitedListener::new(listener, config.max_connections), app).await?,
+    }
+    Ok(())
+}
+
+/// Remote address of the connection a request arrived on.
+#[derive(Debug, Clone, Copy)]
+pub struct ClientAddr(pub SocketAddr);
+
+impl<L: Listener<Addr = SocketAddr>> Connected<IncomingStream<'_, LimitedListener<L>>> for ClientAddr {
+    fn connect_info(stream: IncomingStream<'_, LimitedListener<L>>) -> Self {
+        ClientAddr(*stream.remote_addr())
+    }
+}
+
+impl Connected<IncomingStream<'_, TlsListener>> for ClientAddr {
+    fn connect_info(stream: IncomingStream<'_, TlsListener>) -> Self {
+        ClientAddr(*stream.remote_addr())
+    }
+}
+
+/// Listener that stops accepting while `max` connections are open. Excess
+/// clients wait in the kernel's accept queue rather than being refused.
+pub struct LimitedListener<L> {
+    inner: L,
+    permits: Arc<Semaphore>,
+}
+
+impl<L> LimitedListener<L> {
+    pub fn new(inner: L, max: usize) -> Self {
+        Self {
+            inner,
+            permits: Arc::new(Semaphore::new(max.max(1))),
+        }
+    }
+}
+
+impl<L: Listener> Listener for LimitedListener<L> {
+    type Io = LimitedIo<L::Io>;
+    type Addr = L::Addr;
+
+    async fn accept(&mut self) -> (Self::Io, Self::Addr) {
+        let permit = match self.permits.clone().try_acquire_owned() {
+            Ok(permit) => permit,
+            Err(_) => {
+                Metrics::incr(&METRICS.connections_limited);
+                self.permits.clone().acquire_owned().await.expect("connection semaphore is never closed")
+            }
+        };
+        let (io, addr) = self.inner.accept().await;
+        Metrics::incr(&METRICS.connections_total);
+        METRICS.connections_open.fetch_add(1, Ordering::Relaxed);
+        (LimitedIo { io, _permit: permit }, addr)
+    }
+
+    fn local_addr(&self) -> io::Result<Self::Addr> {
+        self.inner.local_addr()
+    }
+}
+
+/// A connection holding one of its listener's permits until it is dropped.
+pub struct LimitedIo<Io> {
+    io: Io,
+    _permit: OwnedSemaphorePermit,
+}
+
+impl<Io> Drop for LimitedIo<Io> {
+    fn drop(&mut self) {
+        METRICS.connections_open.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl<Io: AsyncRead + Unpin> AsyncRead for LimitedIo<Io> {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.io).poll_read(cx, buf)
+    }
+}
+
+impl<Io: AsyncWrite + Unpin> AsyncWrite for LimitedIo<Io> {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.io).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.io).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.io).poll_shutdown(cx)
+    }
+
+    fn poll_write_vectored(mut self: Pin<&mut Self>, cx: &mut Context<'_>, bufs: &[io::IoSlice<'_>]) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.io).poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.io.is_write_vectored()
+    }
+}
@@ -0,0 +1,157 @@
+use axum::{
+    extract::{Path, Query, State},
+    response::IntoResponse,
+    routing::{get, post},
+    Json,
+    Router,
+};
+use std::sync::Arc;
+use std::time::Duration;
+use crate::storage::StreamId;
+use crate::Kline;
+use axum::http::StatusCode;
+use crate::KlineError;
+use super::error::{bad_request, error_response};
+use super::requests::*;
+use super::responses::*;
+
+/// Routes for the stream type and its consumer groups.
+pub fn routes() -> Router<Arc<Kline>> {
+    Router::new()
+        .route("/stream/{key}", get(xrange).post(xadd))
+        .route("/stream/{key}/read", get(xread))
+        .route("/stream/{key}/len", get(xlen))
+        .route("/stream/{key}/trim", post(xtrim))
+        .route("/stream/{key}/groups/{group}", post(xgroup_create))
+        .route("/stream/{key}/groups/{group}/read", post(xreadgroup))
+        .route("/stream/{key}/groups/{group}/ack", post(xack))
+        .route("/stream/{key}/groups/{group}/pending", get(xpending))
+}
+
+fn invalid_id(id: &str) -> axum::response::Response {
+    KlineError::InvalidStreamId { id: id.to_string() }.into_response()
+}
+
+async fn xadd(Path(key): Path<String>, State(db): State<Arc<Kline>>, Json(body): Json<XaddRequest>) -> impl IntoResponse {
+    let id = match body.id.as_deref() {
+        None | Some("*") => None,
+        Some(id) => match id.parse() {
+            Ok(id) => Some(id),
+            Err(_) => return invalid_id(id),
+        },
+    };
+    let fields = body.fields.into_iter().map(|(f, v)| (f.into_bytes(), v.into_bytes())).collect();
+    match db.xadd(key.as_bytes(), id, fields) {
+        Ok(id) => Json(StreamIdResponse::new(key, id)).into_response(),
+        Err(err) => err.into_response(),
+    }
+}
+
+async fn xrange(Path(key): Path<String>, Query(params): Query<StreamRangeParams>, State(db): State<Arc<Kline>>) -> impl IntoResponse {
+    let start = params.start.as_deref().unwrap_or("-");
+    let end = params.end.as_deref().unwrap_or("+");
+    let (Some(start_id), Some(end_id)) = (StreamId::parse_bound(start), StreamId::parse_bound(end)) else {
+        return invalid_id(&format!("{} {}", start, end));
+    };
+    match db.xrange(key.as_bytes(), start_id, end_id, params.count) {
+        Ok(entries) => Json(StreamEntriesResponse::new(key, entries)).into_response(),
+        Err(err) => err.into_response(),
+    }
+}
+
+async fn xread(Path(key): Path<String>, Query(params): Query<StreamReadParams>, State(db): State<Arc<Kline>>) -> impl IntoResponse {
+    let after = params.after.as_deref().unwrap_or("-");
+    let after_id = match after {
+        "$" => db.xlast_id(key.as_bytes()).ok(),
+        _ => StreamId::parse_bound(after),
+    };
+    let Some(after_id) = after_id else {
+        return invalid_id(after);
+    };
+    let result = match params.block_ms {
+        Some(block_ms) => db.xread_blocking(key.as_bytes(), after_id, params.count, Duration::from_millis(block_ms)).await,
+        None => db.xread(key.as_bytes(), after_id, params.count),
+    };
+    match result {
+        Ok(entries) => Json(StreamEntriesResponse::new(key, entries)).into_response(),
+        Err(err) => err.into_response(),
+    }
+}
+
+async fn xlen(Path(key): Path<String>, State(db): State<Arc<Kline>>) -> impl IntoResponse {
+    match db.xlen(key.as_bytes()) {
+        Ok(length) => Json(LengthResponse::new(key, length)).into_response(),
+        Err(err) => err.into_response(),
+    }
+}
+
+async fn xtrim(Path(key): Path<String>, Query(params): Query<TrimParams>, State(db): State<Arc<Kline>>) -> impl IntoResponse {
+    let result = match (params.max_len, params.max_age_secs) {
+        (Some(max_len), _) => db.xtrim_max_len(key.as_bytes(), max_len),
+        (None, Some(max_age)) => db.xtrim_max_age(key.as_bytes(), Duration::from_secs(max_age)),
+        (None, None) => {
+            return bad_request("Either max_len or max_age_secs is required");
+        }
+    };
+    match result {
+        Ok(removed) => Json(CountResponse::new(key, removed)).into_response(),
+        Err(err) => err.into_response(),
+    }
+}
+
+async fn xgroup_create(
+    Path((key, group)): Path<(String, String)>,
+    Query(params): Query<GroupCreateParams>,
+    State(db): State<Arc<Kline>>,
+) -> impl IntoResponse {
+    let start = match params.start.as_deref() {
+        None | Some("$") => None,
+        Some(start) => match StreamId::parse_bound(start) {
+            Some(id) => Some(id),
+            None => return invalid_id(start),
+        },
+    };
+    match db.xgroup_create(key.as_bytes(), &group, start) {
+        Ok(true) => Json(StatusResponse::ok()).into_response(),
+        Ok(false) => error_response(StatusCode::CONFLICT, "group_exists", format!("Consumer group already exists: {}", group)),
+        Err(err) => err.into_response(),
+    }
+}
+
+async fn xreadgroup(
+    Path((key, group)): Path<(String, String)>,
+    Query(params): Query<GroupReadParams>,
+    State(db): State<Arc<Kline>>,
+) -> impl IntoResponse {
+    let result = match params.block_ms {
+        Some(block_ms) => {
+            db.xreadgroup_blocking(key.as_bytes(), &group, &params.consumer, params.count, Duration::from_millis(block_ms)).await
+        }
+        None => db.xreadgroup(key.as_bytes(), &group, &params.consumer, params.count),
+    };
+    match result {
+        Ok(entries) => Json(StreamEntriesResponse::new(key, entries)).into_response(),
+        Err(err) => err.into_response(),
+    }
+}
+
+async fn xack(Path((key, group)): Path<(String, String)>, State(db): State<Arc<Kline>>, Json(body): Json<AckRequest>) -> impl IntoResponse {
+    let mut ids = Vec::with_capacity(body.ids.len());
+    for id in &body.ids {
+        match id.parse() {
+            Ok(parsed) => ids.push(parsed),
+            Err(_) => return invalid_id(id),
+        }
+    }
+    match db.xack(key.as_bytes(), &group, &ids) {
+        Ok(acked) => Json(CountResponse::new(key, acked)).into_response(),
+        Err(err) => err.into_response(),
+    }
+}
+
+async fn xpending(Path((key, group)): Path<(String, String)>, State(db): State<Arc<Kline>>) -> impl IntoResponse {
+    match db.xpending(key.as_bytes(), &group) {
+        Ok(pending) => Json(PendingResponse::new(key, group, pending)).into_response(),
+        Err(err) => err.into_response(),
+    }
+}
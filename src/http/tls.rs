@@ -0,0 +1,161 @@
+use axum::serve::Listener;
+use rustls::pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer};
+use rustls::server::WebPkiClientVerifier;
+use rustls::{RootCertStore, ServerConfig};
+use std::io;
+use std::net::SocketAddr;
+use std::sync::{Arc, RwLock};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio_rustls::{server::TlsStream, TlsAcceptor};
+use crate::config::TlsConfig;
+use crate::constants::http::{TLS_ACCEPT_BACKLOG, TLS_HANDSHAKE_TIMEOUT};
+use crate::error::{KlineError, Result};
+use super::server::{LimitedIo, LimitedListener};
+
+/// The server's certificates, loaded from the files in `[server.tls]`.
+/// New connections use whatever was loaded last; open ones keep theirs.
+pub struct Certificates {
+    config: TlsConfig,
+    current: RwLock<Arc<ServerConfig>>,
+}
+
+impl Certificates {
+    pub fn load(config: &TlsConfig) -> Result<Self> {
+        Ok(Self {
+            config: config.clone(),
+            current: RwLock::new(Arc::new(server_config(config)?)),
+        })
+    }
+
+    /// Reads the files again. On failure the certificates in use are kept.
+    pub fn reload(&self) -> Result<()> {
+        let fresh = Arc::new(server_config(&self.config)?);
+        *self.current.write().map_err(|_| KlineError::LockPoisoned)? = fresh;
+        Ok(())
+    }
+
+    fn current(&self) -> Arc<ServerConfig> {
+        self.current.read().unwrap_or_else(|poisoned| poisoned.into_inner()).clone()
+    }
+
+    /// Reloads on every SIGHUP for as long as the process runs.
+    pub fn reload_on_sighup(self: &Arc<Self>) -> Result<()> {
+        #[cfg(unix)]
+        {
+            use tokio::signal::unix::{signal, SignalKind};
+            let mut hangups = signal(SignalKind::hangup())?;
+            let certificates = Arc::clone(self);
+            tokio::spawn(async move {
+                while hangups.recv().await.is_some() {
+                    match certificates.reload() {
+                        Ok(()) => println!("Reloaded TLS certificates from {}", certificates.config.cert_path),
+                        Err(err) => eprintln!("Keeping current TLS certificates: {}", err),
+                    }
+                }
+            });
+        }
+        Ok(())
+    }
+}
+
+fn server_config(config: &TlsConfig) -> Result<ServerConfig> {
+    let certs = read_certs(&config.cert_path)?;
+    let key = PrivateKeyDer::from_pem_file(&config.key_path).map_err(|err| tls_error(&config.key_path, err))?;
+
+    let builder = match &config.client_ca {
+        Some(path) => {
+            let mut roots = RootCertStore::empty();
+            for cert in read_certs(path)? {
+                roots.add(cert).map_err(|err| tls_error(path, err))?;
+            }
+            let verifier = WebPkiClientVerifier::builder(Arc::new(roots))
+                .build()
+                .map_err(|err| tls_error(path, err))?;
+            ServerConfig::builder().with_client_cert_verifier(verifier)
+        }
+        None => ServerConfig::builder().with_no_client_auth(),
+    };
+    let mut server = builder
+        .with_single_cert(certs, key)
+        .map_err(|err| tls_error(&config.cert_path, err))?;
+    server.alpn_protocols = vec![b"http/1.1".to_vec()];
+    Ok(server)
+}
+
+fn read_certs(path: &str) -> Result<Vec<CertificateDer<'static>>> {
+    let certs = CertificateDer::pem_file_iter(path)
+        .and_then(|certs| certs.collect::<std::result::Result<Vec<_>, _>>())
+        .map_err(|err| tls_error(path, err))?;
+    if certs.is_empty() {
+        return Err(tls_error(path, "no certificates found"));
+    }
+    Ok(certs)
+}
+
+fn tls_error(path: &str, err: impl std::fmt::Display) -> KlineError {
+    KlineError::Tls { reason: format!("{}: {}", path, err) }
+}
+
+/// A connection that completed its TLS handshake, still holding its
+/// `max_connections` permit.
+type Handshaken = (TlsStream<LimitedIo<TcpStream>>, SocketAddr);
+
+/// Listener that completes the TLS handshake before handing a connection
+/// to the server. Handshakes run on their own tasks, so a client that
+/// stalls mid-handshake holds up no one else and is dropped after
+/// `TLS_HANDSHAKE_TIMEOUT`. Connections are counted against
+/// `max_connections` from the TCP accept on, so handshaking clients cannot
+/// exceed it either.
+pub struct TlsListener {
+    handshaken: mpsc::Receiver<Handshaken>,
+    local_addr: SocketAddr,
+}
+
+impl TlsListener {
+    pub fn new(listener: LimitedListener<TcpListener>, certificates: Arc<Certificates>) -> io::Result<Self> {
+        let local_addr = listener.local_addr()?;
+        let (sender, handshaken) = mpsc::channel(TLS_ACCEPT_BACKLOG);
+        tokio::spawn(accept_loop(listener, certificates, sender));
+        Ok(Self { handshaken, local_addr })
+    }
+}
+
+async fn accept_loop(
+    mut listener: LimitedListener<TcpListener>,
+    certificates: Arc<Certificates>,
+    sender: mpsc::Sender<Handshaken>,
+) {
+    while !sender.is_closed() {
+        let (stream, addr) = listener.accept().await;
+        let acceptor = TlsAcceptor::from(certificates.current());
+        let sender = sender.clone();
+        tokio::spawn(async move {
+            // The timeout also covers waiting for the server to take the
+            // connection, so a stalled client never keeps its permit.
+            let _ = tokio::time::timeout(TLS_HANDSHAKE_TIMEOUT, async {
+                if let Ok(stream) = acceptor.accept(stream).await {
+                    let _ = sender.send((stream, addr)).await;
+                }
+            })
+            .await;
+        });
+    }
+}
+
+impl Listener for TlsListener {
+    type Io = TlsStream<LimitedIo<TcpStream>>;
+    type Addr = SocketAddr;
+
+    async fn accept(&mut self) -> (Self::Io, Self::Addr) {
+        match self.handshaken.recv().await {
+            Some(connection) => connection,
+            // The accept loop only stops once this listener is dropped.
+            None => std::future::pending().await,
+        }
+    }
+
+    fn local_addr(&self) -> io::Result<Self::Addr> {
+        Ok(self.local_addr)
+    }
+}
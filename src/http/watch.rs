@@ -0,0 +1,82 @@
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        Query, State,
+    },
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse,
+    },
+    routing::get,
+    Router,
+};
+use futures_util::stream::{self, Stream};
+use std::convert::Infallible;
+use std::sync::Arc;
+use crate::storage::{ChangeMessage, KeyFilter, Subscription};
+use crate::Kline;
+use super::requests::*;
+use super::responses::*;
+
+/// Routes for watching key mutations over Server-Sent Events or WebSocket.
+pub fn routes() -> Router<Arc<Kline>> {
+    Router::new()
+        .route("/watch", get(watch_sse))
+        .route("/watch/ws", get(watch_ws))
+}
+
+fn filter(params: WatchParams) -> KeyFilter {
+    match (params.key, params.prefix) {
+        (Some(key), _) => KeyFilter::Exact(key.into_bytes()),
+        (None, Some(prefix)) if !prefix.is_empty() => KeyFilter::Prefix(prefix.into_bytes()),
+        _ => KeyFilter::All,
+    }
+}
+
+/// Renders a change message as `(event name, JSON payload)`.
+fn render(message: ChangeMessage) -> (&'static str, String) {
+    match message {
+        ChangeMessage::Event(event) => {
+            let name = event.op.as_str();
+            (name, serde_json::to_string(&ChangeEventResponse::new(event)).unwrap_or_default())
+        }
+        ChangeMessage::Overflow { dropped } => {
+            ("overflow", serde_json::to_string(&OverflowResponse { dropped }).unwrap_or_default())
+        }
+    }
+}
+
+async fn watch_sse(Query(params): Query<WatchParams>, State(db): State<Arc<Kline>>) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let subscription = db.subscribe(filter(params));
+    let events = stream::unfold(subscription, |mut subscription: Subscription| async move {
+        let (name, data) = render(subscription.recv().await?);
+        Some((Ok(Event::default().event(name).data(data)), subscription))
+    });
+    Sse::new(events).keep_alive(KeepAlive::default())
+}
+
+async fn watch_ws(ws: WebSocketUpgrade, Query(params): Query<WatchParams>, State(db): State<Arc<Kline>>) -> impl IntoResponse {
+    let subscription = db.subscribe(filter(params));
+    ws.on_upgrade(move |socket| forward_changes(socket, subscription))
+}
+
+/// Sends each change as a JSON text frame until the client disconnects.
+async fn forward_changes(mut socket: WebSocket, mut subscription: Subscription) {
+    loop {
+        tokio::select! {
+            message = subscription.recv() => {
+                let Some(message) = message else { break };
+                let (_, data) = render(message);
+                if socket.send(Message::Text(data.into())).await.is_err() {
+                    break;
+                }
+            }
+            incoming = socket.recv() => {
+                match incoming {
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                    Some(Ok(_)) => {}
+                }
+            }
+        }
+    }
+}
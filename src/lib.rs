@@ -3,6 +3,7 @@ pub mod cli;
 pub mod constants;
 pub mod config;
 pub mod error;
+pub mod http;
 
 pub use storage::Kline;
 pub use cli::repl;
@@ -1,10 +1,9 @@
 use std::sync::Arc;
-use kline::{Kline, repl, KlineConfig, Result};
+use kline::{http, Kline, KlineError, repl, KlineConfig, Result};
+use kline::config::{Role, TokenConfig};
 use tokio::task;
 use clap::{Parser, Subcommand};
 
-mod http;
-
 #[derive(Parser)]
 #[command(name = "kline")]
 #[command(about = "A high-performance key-value database")]
@@ -32,6 +31,27 @@ enum Commands {
         #[arg(short, long, default_value = "kline.conf")]
         output: String,
     },
+    /// Manage API tokens
+    Token {
+        #[command(subcommand)]
+        command: TokenCommands,
+    },
+}
+
+#[derive(Subcommand)]
+enum TokenCommands {
+    /// Generate a token and print the config entry that grants it
+    Create {
+        /// Name shown in errors and logs
+        #[arg(short, long)]
+        name: String,
+        /// read-only, read-write or admin
+        #[arg(short, long, default_value = "read-only")]
+        role: Role,
+        /// Limit the token to keys starting with this prefix
+        #[arg(long)]
+        prefix: Option<String>,
+    },
 }
 
 #[tokio::main]
@@ -45,6 +65,20 @@ async fn main() -> Result<()> {
             println!("Generated default config file: {}", output);
             return Ok(());
         }
+        Some(Commands::Token { command: TokenCommands::Create { name, role, prefix } }) => {
+            let (token, hash) = http::auth::create_token();
+            let entry = TokenConfig { name, hash, role, prefix };
+            let snippet = toml::to_string(&entry).map_err(|e| KlineError::ConfigSerialize { reason: e.to_string() })?;
+            println!("Token (shown once): {}", token);
+            println!();
+            println!("Add to {} and restart:", cli.config);
+            println!("[auth]");
+            println!("enabled = true");
+            println!();
+            println!("[[auth.tokens]]");
+            print!("{}", snippet);
+            return Ok(());
+        }
         Some(Commands::Server) | None => {
             // Start the server (default behavior)
         }
@@ -88,8 +122,12 @@ async fn start_server(config: KlineConfig) -> Result<()> {
         let app = http::create_router(http_db);
         let bind_addr = format!("{}:{}", server_config.bind_address, server_config.port);
         let listener = tokio::net::TcpListener::bind(&bind_addr).await.unwrap();
-        println!("HTTP server running at http://{}", bind_addr);
-        axum::serve(listener, app).await.unwrap();
+        let scheme = if server_config.tls.is_some() { "https" } else { "http" };
+        println!("HTTP server running at {}://{}", scheme, bind_addr);
+        if let Err(err) = http::serve(listener, app, &server_config).await {
+            eprintln!("HTTP server failed: {}", err);
+            std::process::exit(1);
+        }
     });
 
     // Start REPL
@@ -0,0 +1,98 @@
+use std::collections::HashSet;
+use crate::error::{KlineError, Result};
+use super::engine::{encode, Kline};
+use super::value::Value;
+
+/// Multi-key string operations. Each takes the store lock once for the
+/// whole batch, so no other write interleaves with it.
+impl Kline {
+    /// Values of `keys` in order, `None` for keys that are missing or do not
+    /// hold a string.
+    pub fn mget(&self, keys: &[Vec<u8>]) -> Result<Vec<Option<Vec<u8>>>> {
+        self.check_batch(keys.len())?;
+        let store = self.store.read().map_err(|_| KlineError::LockPoisoned)?;
+        Ok(keys
+            .iter()
+            .map(|key| match store.get(key) {
+                Some(Value::String(value)) => Some(value.clone()),
+                _ => None,
+            })
+            .collect())
+    }
+
+    /// Stores every pair or none of them. The batch is checked against the
+    /// limits as a whole and logged as one record, so replay after a crash
+    /// also applies all of it or nothing.
+    pub fn mput(&self, pairs: Vec<(Vec<u8>, Vec<u8>)>) -> Result<()> {
+        self.check_batch(pairs.len())?;
+        for (key, value) in &pairs {
+            self.check_sizes(key, value.len())?;
+        }
+        if pairs.is_empty() {
+            return Ok(());
+        }
+        let sizes: Vec<(&[u8], usize)> = pairs.iter().map(|(key, value)| (key.as_slice(), value.len())).collect();
+
+        let mut store = self.store.write().map_err(|_| KlineError::LockPoisoned)?;
+        for (key, _) in &pairs {
+            store.check_type(key, "string")?;
+        }
+        store.check_puts(&self.config.limits, &sizes)?;
+        let record: Vec<String> = pairs
+            .iter()
+            .map(|(key, value)| format!("{} {}", encode(key), encode(value)))
+            .collect();
+        self.append_log(&mut store, &format!("mput {}", record.join(" ")))?;
+
+        for (key, value) in pairs {
+            store.insert(key.clone(), Value::String(value));
+            self.string_changed(&mut store, &key);
+        }
+        Ok(())
+    }
+
+    /// Stores each pair on its own, as `put` would, and returns each
+    /// outcome in order. A failed pair does not stop the rest.
+    pub fn mput_each(&self, pairs: Vec<(Vec<u8>, Vec<u8>)>) -> Result<Vec<Result<()>>> {
+        self.check_batch(pairs.len())?;
+        let mut store = self.store.write().map_err(|_| KlineError::LockPoisoned)?;
+        Ok(pairs
+            .into_iter()
+            .map(|(key, value)| {
+                self.check_sizes(&key, value.len())?;
+                self.put_locked(&mut store, key, value)
+            })
+            .collect())
+    }
+
+    /// Deletes `keys` as one logged record and returns, in order, whether
+    /// each existed.
+    pub fn mdelete(&self, keys: &[Vec<u8>]) -> Result<Vec<bool>> {
+        self.check_batch(keys.len())?;
+        let mut store = self.store.write().map_err(|_| KlineError::LockPoisoned)?;
+        let mut seen = HashSet::new();
+        let existing: Vec<&Vec<u8>> = keys
+            .iter()
+            .filter(|key| store.get(key).is_some() && seen.insert(*key))
+            .collect();
+        if existing.is_empty() {
+            return Ok(vec![false; keys.len()]);
+        }
+
+        let record: Vec<String> = existing.iter().map(|key| encode(key)).collect();
+        self.append_log(&mut store, &format!("mdelete {}", record.join(" ")))?;
+        let deleted: Vec<bool> = keys.iter().map(|key| store.remove(key).is_some()).collect();
+        for key in existing {
+            self.string_changed(&mut store, key);
+        }
+        Ok(deleted)
+    }
+
+    fn check_batch(&self, size: usize) -> Result<()> {
+        let max = self.config.limits.max_batch_items;
+        if size > max {
+            return Err(KlineError::BatchTooLarge { size, max });
+        }
+        Ok(())
+    }
+}
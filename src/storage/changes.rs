@@ -0,0 +1,133 @@
+use std::sync::Mutex;
+use tokio::sync::mpsc;
+use crate::constants::changes::SUBSCRIBER_BUFFER;
+
+/// Kind of mutation reported in a `ChangeEvent`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeOp {
+    /// A string value was written; the event carries the new value.
+    Put,
+    /// The key was removed.
+    Delete,
+    /// A list, hash, set, sorted set or stream was modified in place.
+    Update,
+    /// The key was removed because its TTL passed.
+    Expire,
+}
+
+impl ChangeOp {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChangeOp::Put => "put",
+            ChangeOp::Delete => "delete",
+            ChangeOp::Update => "update",
+            ChangeOp::Expire => "expire",
+        }
+    }
+}
+
+/// A committed mutation of a single key.
+#[derive(Debug, Clone)]
+pub struct ChangeEvent {
+    pub seq: u64,
+    pub op: ChangeOp,
+    pub key: Vec<u8>,
+    pub value: Option<Vec<u8>>,
+    /// Milliseconds since the Unix epoch.
+    pub timestamp: u64,
+}
+
+/// Which keys a subscription receives events for.
+#[derive(Debug, Clone)]
+pub enum KeyFilter {
+    All,
+    Exact(Vec<u8>),
+    Prefix(Vec<u8>),
+}
+
+impl KeyFilter {
+    pub fn matches(&self, key: &[u8]) -> bool {
+        match self {
+            KeyFilter::All => true,
+            KeyFilter::Exact(exact) => key == exact.as_slice(),
+            KeyFilter::Prefix(prefix) => key.starts_with(prefix),
+        }
+    }
+}
+
+/// Item delivered to a subscriber. `Overflow` replaces events that were
+/// dropped because the subscriber's buffer was full.
+#[derive(Debug, Clone)]
+pub enum ChangeMessage {
+    Event(ChangeEvent),
+    Overflow { dropped: u64 },
+}
+
+/// Receiving end of `Kline::subscribe`. Dropping it unsubscribes.
+pub struct Subscription {
+    receiver: mpsc::Receiver<ChangeMessage>,
+}
+
+impl Subscription {
+    /// Waits for the next message; `None` once the database is dropped.
+    pub async fn recv(&mut self) -> Option<ChangeMessage> {
+        self.receiver.recv().await
+    }
+
+    /// Blocking variant of `recv` for use outside an async runtime.
+    pub fn blocking_recv(&mut self) -> Option<ChangeMessage> {
+        self.receiver.blocking_recv()
+    }
+
+    pub fn try_recv(&mut self) -> Option<ChangeMessage> {
+        self.receiver.try_recv().ok()
+    }
+}
+
+struct Subscriber {
+    filter: KeyFilter,
+    sender: mpsc::Sender<ChangeMessage>,
+    dropped: u64,
+}
+
+/// Fans committed mutations out to subscribers without ever blocking the
+/// writer: a subscriber whose buffer is full loses events and is told how
+/// many once it catches up.
+#[derive(Default)]
+pub struct ChangeFeed {
+    subscribers: Mutex<Vec<Subscriber>>,
+}
+
+impl ChangeFeed {
+    pub fn subscribe(&self, filter: KeyFilter) -> Subscription {
+        let (sender, receiver) = mpsc::channel(SUBSCRIBER_BUFFER);
+        let mut subscribers = self.subscribers.lock().unwrap_or_else(|e| e.into_inner());
+        subscribers.push(Subscriber { filter, sender, dropped: 0 });
+        Subscription { receiver }
+    }
+
+    /// Publishes an event to every matching subscriber. `event` is only
+    /// built if someone is listening for `key`.
+    pub fn publish(&self, key: &[u8], event: impl FnOnce() -> ChangeEvent) {
+        let mut subscribers = self.subscribers.lock().unwrap_or_else(|e| e.into_inner());
+        subscribers.retain(|subscriber| !subscriber.sender.is_closed());
+        if !subscribers.iter().any(|subscriber| subscriber.filter.matches(key)) {
+            return;
+        }
+
+        let event = event();
+        for subscriber in subscribers.iter_mut().filter(|s| s.filter.matches(key)) {
+            if subscriber.dropped > 0 {
+                let overflow = ChangeMessage::Overflow { dropped: subscriber.dropped };
+                if subscriber.sender.try_send(overflow).is_err() {
+                    subscriber.dropped += 1;
+                    continue;
+                }
+                subscriber.dropped = 0;
+            }
+            if subscriber.sender.try_send(ChangeMessage::Event(event.clone())).is_err() {
+                subscriber.dropped += 1;
+            }
+        }
+    }
+}
@@ -0,0 +1,672 @@
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+use crate::error::{KlineError, Result};
+use super::engine::{encode, encode_all, encode_pairs, key_string, now_ms, Kline};
+use super::stream::{Fields, PendingEntry, Stream, StreamId, ID_SIZE};
+use super::value::Value;
+use super::zset::{SortedSet, SCORE_SIZE};
+
+/// List, hash, set, sorted set and stream commands. Each mutation is logged as a single record
+/// holding only the elements it touches.
+impl Kline {
+    /// Pushes `items` onto the head of the list at `key` and returns its new
+    /// length. The last item ends up first, as with repeated single pushes.
+    pub fn lpush(&self, key: &[u8], items: Vec<Vec<u8>>) -> Result<usize> {
+        self.push(key, items, true)
+    }
+
+    /// Pushes `items` onto the tail of the list at `key` and returns its new
+    /// length.
+    pub fn rpush(&self, key: &[u8], items: Vec<Vec<u8>>) -> Result<usize> {
+        self.push(key, items, false)
+    }
+
+    fn push(&self, key: &[u8], items: Vec<Vec<u8>>, front: bool) -> Result<usize> {
+        for item in &items {
+            self.check_sizes(key, item.len())?;
+        }
+
+        let mut store = self.store.write().map_err(|_| KlineError::LockPoisoned)?;
+        store.check_type(key, "list")?;
+        if items.is_empty() {
+            return Ok(list_len(store.get(key)));
+        }
+        let added: usize = items.iter().map(Vec::len).sum();
+        let size = store.size_of(key) + added;
+        self.check_sizes(key, size)?;
+        store.check_limits(&self.config.limits, key, size)?;
+
+        let op = if front { "lpush" } else { "rpush" };
+        self.append_log(&mut store, &format!("{} {} {}", op, encode(key), encode_all(&items)))?;
+
+        let result = store.push(key, items, front).expect("type checked above");
+        self.key_changed(&store, key);
+        Ok(result)
+    }
+
+    /// Removes and returns up to `count` items from the head of the list.
+    pub fn lpop(&self, key: &[u8], count: usize) -> Result<Vec<Vec<u8>>> {
+        self.pop(key, count, true)
+    }
+
+    /// Removes and returns up to `count` items from the tail of the list.
+    pub fn rpop(&self, key: &[u8], count: usize) -> Result<Vec<Vec<u8>>> {
+        self.pop(key, count, false)
+    }
+
+    fn pop(&self, key: &[u8], count: usize, front: bool) -> Result<Vec<Vec<u8>>> {
+        let mut store = self.store.write().map_err(|_| KlineError::LockPoisoned)?;
+        store.check_type(key, "list")?;
+        if count == 0 || store.get(key).is_none() {
+            return Ok(Vec::new());
+        }
+
+        let op = if front { "lpop" } else { "rpop" };
+        self.append_log(&mut store, &format!("{} {} {}", op, encode(key), count))?;
+
+        let result = store.pop(key, count, front).expect("type checked above");
+        self.key_changed(&store, key);
+        Ok(result)
+    }
+
+    /// Returns the list items between `start` and `stop`, both inclusive.
+    /// Negative indexes count from the tail.
+    pub fn lrange(&self, key: &[u8], start: i64, stop: i64) -> Result<Vec<Vec<u8>>> {
+        let store = self.store.read().map_err(|_| KlineError::LockPoisoned)?;
+        store.check_type(key, "list")?;
+        let Some(Value::List(list)) = store.get(key) else {
+            return Ok(Vec::new());
+        };
+
+        let len = list.len() as i64;
+        let start = if start < 0 { (len + start).max(0) } else { start };
+        let stop = if stop < 0 { len + stop } else { stop.min(len - 1) };
+        if start > stop || start >= len {
+            return Ok(Vec::new());
+        }
+        Ok(list.range(start as usize..=stop as usize).cloned().collect())
+    }
+
+    pub fn llen(&self, key: &[u8]) -> Result<usize> {
+        let store = self.store.read().map_err(|_| KlineError::LockPoisoned)?;
+        store.check_type(key, "list")?;
+        Ok(list_len(store.get(key)))
+    }
+
+    /// Pops the head of the first non-empty list among `keys`, waiting up to
+    /// `timeout` for an item to be pushed if they are all empty. Returns the
+    /// key popped from along with the item.
+    pub async fn blpop(&self, keys: &[&[u8]], timeout: Duration) -> Result<Option<(Vec<u8>, Vec<u8>)>> {
+        self.blocking_pop(keys, true, timeout).await
+    }
+
+    /// Tail variant of `blpop`.
+    pub async fn brpop(&self, keys: &[&[u8]], timeout: Duration) -> Result<Option<(Vec<u8>, Vec<u8>)>> {
+        self.blocking_pop(keys, false, timeout).await
+    }
+
+    async fn blocking_pop(&self, keys: &[&[u8]], front: bool, timeout: Duration) -> Result<Option<(Vec<u8>, Vec<u8>)>> {
+        self.wait_for(keys, timeout, || {
+            for key in keys {
+                if let Some(item) = self.pop(key, 1, front)?.pop() {
+                    return Ok(Some((key.to_vec(), item)));
+                }
+            }
+            Ok(None)
+        }).await
+    }
+
+    /// Atomically pops an item from one end of `source` and pushes it onto
+    /// one end of `destination`, returning the item. Popping into an
+    /// in-flight list this way, then removing the item with `lrem` once it
+    /// has been processed, gives a queue that loses nothing if a consumer
+    /// dies mid-task.
+    pub fn lmove(&self, source: &[u8], destination: &[u8], from_front: bool, to_front: bool) -> Result<Option<Vec<u8>>> {
+        let mut store = self.store.write().map_err(|_| KlineError::LockPoisoned)?;
+        store.check_type(source, "list")?;
+        store.check_type(destination, "list")?;
+        let item = match store.get(source) {
+            Some(Value::List(list)) if from_front => list.front().cloned(),
+            Some(Value::List(list)) => list.back().cloned(),
+            _ => None,
+        };
+        let Some(item) = item else {
+            return Ok(None);
+        };
+        if source != destination {
+            let size = store.size_of(destination) + item.len();
+            self.check_sizes(destination, size)?;
+            store.check_limits(&self.config.limits, destination, size)?;
+        }
+
+        self.append_log(&mut store, &format!(
+            "lmove {} {} {} {}",
+            encode(source),
+            encode(destination),
+            end_name(from_front),
+            end_name(to_front)
+        ))?;
+
+        store.pop(source, 1, from_front).expect("type checked above");
+        store.push(destination, vec![item.clone()], to_front).expect("type checked above");
+        self.key_changed(&store, source);
+        if source != destination {
+            self.key_changed(&store, destination);
+        }
+        Ok(Some(item))
+    }
+
+    /// Like `lmove`, but waits up to `timeout` for `source` to be pushed to
+    /// if it is empty.
+    pub async fn blmove(&self, source: &[u8], destination: &[u8], from_front: bool, to_front: bool, timeout: Duration) -> Result<Option<Vec<u8>>> {
+        self.wait_for(&[source], timeout, || self.lmove(source, destination, from_front, to_front)).await
+    }
+
+    /// Removes occurrences of `item` from the list and returns how many were
+    /// removed: the first `count` from the head if positive, the last
+    /// `-count` from the tail if negative, or all of them if zero.
+    pub fn lrem(&self, key: &[u8], count: i64, item: &[u8]) -> Result<usize> {
+        let mut store = self.store.write().map_err(|_| KlineError::LockPoisoned)?;
+        store.check_type(key, "list")?;
+        let present = matches!(store.get(key), Some(Value::List(list)) if list.iter().any(|i| i == item));
+        if !present {
+            return Ok(0);
+        }
+
+        self.append_log(&mut store, &format!("lrem {} {} {}", encode(key), count, encode(item)))?;
+
+        let removed = store.lrem(key, count, item).expect("type checked above");
+        self.key_changed(&store, key);
+        Ok(removed)
+    }
+
+    /// Sets one or more hash fields and returns how many were newly created.
+    pub fn hset(&self, key: &[u8], fields: Vec<(Vec<u8>, Vec<u8>)>) -> Result<usize> {
+        for (field, value) in &fields {
+            self.check_sizes(key, field.len() + value.len())?;
+        }
+
+        let mut store = self.store.write().map_err(|_| KlineError::LockPoisoned)?;
+        store.check_type(key, "hash")?;
+        if fields.is_empty() {
+            return Ok(0);
+        }
+        // A field named twice keeps its last value, and an overwritten field
+        // gives back the size of the value it replaces.
+        let written: HashMap<&[u8], usize> = fields.iter().map(|(f, v)| (f.as_slice(), f.len() + v.len())).collect();
+        let replaced: usize = match store.get(key) {
+            Some(Value::Hash(hash)) => written.keys().filter_map(|f| hash.get(*f).map(|old| f.len() + old.len())).sum(),
+            _ => 0,
+        };
+        let size = store.size_of(key) + written.values().sum::<usize>() - replaced;
+        self.check_sizes(key, size)?;
+        store.check_limits(&self.config.limits, key, size)?;
+
+        let pairs = encode_pairs(fields.iter().map(|(f, v)| (f, v)));
+        self.append_log(&mut store, &format!("hset {} {}", encode(key), pairs))?;
+
+        let result = store.hset(key, fields).expect("type checked above");
+        self.key_changed(&store, key);
+        Ok(result)
+    }
+
+    pub fn hget(&self, key: &[u8], field: &[u8]) -> Result<Option<Vec<u8>>> {
+        let store = self.store.read().map_err(|_| KlineError::LockPoisoned)?;
+        store.check_type(key, "hash")?;
+        match store.get(key) {
+            Some(Value::Hash(hash)) => Ok(hash.get(field).cloned()),
+            _ => Ok(None),
+        }
+    }
+
+    /// Removes hash fields and returns how many of them existed.
+    pub fn hdel(&self, key: &[u8], fields: &[Vec<u8>]) -> Result<usize> {
+        let mut store = self.store.write().map_err(|_| KlineError::LockPoisoned)?;
+        store.check_type(key, "hash")?;
+        let Some(Value::Hash(hash)) = store.get(key) else {
+            return Ok(0);
+        };
+        if !fields.iter().any(|field| hash.contains_key(field)) {
+            return Ok(0);
+        }
+
+        self.append_log(&mut store, &format!("hdel {} {}", encode(key), encode_all(fields)))?;
+
+        let result = store.hdel(key, fields).expect("type checked above");
+        self.key_changed(&store, key);
+        Ok(result)
+    }
+
+    pub fn hgetall(&self, key: &[u8]) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        let store = self.store.read().map_err(|_| KlineError::LockPoisoned)?;
+        store.check_type(key, "hash")?;
+        match store.get(key) {
+            Some(Value::Hash(hash)) => Ok(hash.iter().map(|(f, v)| (f.clone(), v.clone())).collect()),
+            _ => Ok(Vec::new()),
+        }
+    }
+
+    /// Adds members to the set at `key` and returns how many were new.
+    pub fn sadd(&self, key: &[u8], members: Vec<Vec<u8>>) -> Result<usize> {
+        for member in &members {
+            self.check_sizes(key, member.len())?;
+        }
+
+        let mut store = self.store.write().map_err(|_| KlineError::LockPoisoned)?;
+        store.check_type(key, "set")?;
+        let mut seen = HashSet::new();
+        let new_members: Vec<Vec<u8>> = match store.get(key) {
+            Some(Value::Set(set)) => members.into_iter().filter(|m| !set.contains(m) && seen.insert(m.clone())).collect(),
+            _ => members.into_iter().filter(|m| seen.insert(m.clone())).collect(),
+        };
+        if new_members.is_empty() {
+            return Ok(0);
+        }
+        let added: usize = new_members.iter().map(Vec::len).sum();
+        let size = store.size_of(key) + added;
+        self.check_sizes(key, size)?;
+        store.check_limits(&self.config.limits, key, size)?;
+
+        self.append_log(&mut store, &format!("sadd {} {}", encode(key), encode_all(&new_members)))?;
+
+        let result = store.sadd(key, new_members).expect("type checked above");
+        self.key_changed(&store, key);
+        Ok(result)
+    }
+
+    /// Removes members from the set at `key` and returns how many existed.
+    pub fn srem(&self, key: &[u8], members: &[Vec<u8>]) -> Result<usize> {
+        let mut store = self.store.write().map_err(|_| KlineError::LockPoisoned)?;
+        store.check_type(key, "set")?;
+        let Some(Value::Set(set)) = store.get(key) else {
+            return Ok(0);
+        };
+        if !members.iter().any(|member| set.contains(member)) {
+            return Ok(0);
+        }
+
+        self.append_log(&mut store, &format!("srem {} {}", encode(key), encode_all(members)))?;
+
+        let result = store.srem(key, members).expect("type checked above");
+        self.key_changed(&store, key);
+        Ok(result)
+    }
+
+    pub fn smembers(&self, key: &[u8]) -> Result<Vec<Vec<u8>>> {
+        let store = self.store.read().map_err(|_| KlineError::LockPoisoned)?;
+        store.check_type(key, "set")?;
+        match store.get(key) {
+            Some(Value::Set(set)) => Ok(set.iter().cloned().collect()),
+            _ => Ok(Vec::new()),
+        }
+    }
+
+    pub fn sismember(&self, key: &[u8], member: &[u8]) -> Result<bool> {
+        let store = self.store.read().map_err(|_| KlineError::LockPoisoned)?;
+        store.check_type(key, "set")?;
+        match store.get(key) {
+            Some(Value::Set(set)) => Ok(set.contains(member)),
+            _ => Ok(false),
+        }
+    }
+
+    /// Adds members with scores to the sorted set at `key`, rescoring members
+    /// that already exist, and returns how many were new.
+    pub fn zadd(&self, key: &[u8], members: Vec<(f64, Vec<u8>)>) -> Result<usize> {
+        for (score, member) in &members {
+            if score.is_nan() {
+                return Err(KlineError::NotANumber { key: key_string(key) });
+            }
+            self.check_sizes(key, member.len())?;
+        }
+
+        let mut store = self.store.write().map_err(|_| KlineError::LockPoisoned)?;
+        store.check_type(key, "zset")?;
+        if members.is_empty() {
+            return Ok(0);
+        }
+        // Rescoring a member, or naming it twice, adds nothing.
+        let existing = match store.get(key) {
+            Some(Value::SortedSet(zset)) => Some(zset),
+            _ => None,
+        };
+        let new_members: HashSet<&[u8]> = members
+            .iter()
+            .map(|(_, member)| member.as_slice())
+            .filter(|member| existing.is_none_or(|zset| zset.score(member).is_none()))
+            .collect();
+        let added: usize = new_members.iter().map(|member| member.len() + SCORE_SIZE).sum();
+        let size = store.size_of(key) + added;
+        self.check_sizes(key, size)?;
+        store.check_limits(&self.config.limits, key, size)?;
+
+        let pairs: Vec<String> = members
+            .iter()
+            .map(|(score, member)| format!("{} {}", score, encode(member)))
+            .collect();
+        self.append_log(&mut store, &format!("zadd {} {}", encode(key), pairs.join(" ")))?;
+
+        let result = store.zadd(key, members).expect("type checked above");
+        self.key_changed(&store, key);
+        Ok(result)
+    }
+
+    /// Removes members from the sorted set at `key` and returns how many
+    /// existed.
+    pub fn zrem(&self, key: &[u8], members: &[Vec<u8>]) -> Result<usize> {
+        let mut store = self.store.write().map_err(|_| KlineError::LockPoisoned)?;
+        store.check_type(key, "zset")?;
+        let Some(Value::SortedSet(zset)) = store.get(key) else {
+            return Ok(0);
+        };
+        if !members.iter().any(|member| zset.score(member).is_some()) {
+            return Ok(0);
+        }
+
+        self.append_log(&mut store, &format!("zrem {} {}", encode(key), encode_all(members)))?;
+
+        let result = store.zrem(key, members).expect("type checked above");
+        self.key_changed(&store, key);
+        Ok(result)
+    }
+
+    pub fn zscore(&self, key: &[u8], member: &[u8]) -> Result<Option<f64>> {
+        self.with_zset(key, |zset| zset.and_then(|zset| zset.score(member)))
+    }
+
+    /// Zero-based rank of `member` in ascending score order.
+    pub fn zrank(&self, key: &[u8], member: &[u8]) -> Result<Option<usize>> {
+        self.with_zset(key, |zset| zset.and_then(|zset| zset.rank(member)))
+    }
+
+    pub fn zcard(&self, key: &[u8]) -> Result<usize> {
+        self.with_zset(key, |zset| zset.map_or(0, SortedSet::len))
+    }
+
+    /// Members with ranks between `start` and `stop`, both inclusive, with
+    /// their scores. Negative ranks count from the highest score.
+    pub fn zrange(&self, key: &[u8], start: i64, stop: i64) -> Result<Vec<(Vec<u8>, f64)>> {
+        self.with_zset(key, |zset| {
+            let Some(zset) = zset else {
+                return Vec::new();
+            };
+            let len = zset.len() as i64;
+            let start = if start < 0 { (len + start).max(0) } else { start };
+            let stop = if stop < 0 { len + stop } else { stop.min(len - 1) };
+            if start > stop || start >= len {
+                return Vec::new();
+            }
+            zset.range_by_rank(start as usize, stop as usize)
+        })
+    }
+
+    /// Members with `min <= score <= max` in ascending order, capped at
+    /// `limit` results when given.
+    pub fn zrange_by_score(&self, key: &[u8], min: f64, max: f64, limit: Option<usize>) -> Result<Vec<(Vec<u8>, f64)>> {
+        self.with_zset(key, |zset| match zset {
+            Some(zset) => zset
+                .range_by_score(min, max)
+                .take(limit.unwrap_or(usize::MAX))
+                .map(|(member, score)| (member.clone(), score))
+                .collect(),
+            None => Vec::new(),
+        })
+    }
+
+    /// Removes and returns up to `count` members with the lowest scores.
+    pub fn zpopmin(&self, key: &[u8], count: usize) -> Result<Vec<(Vec<u8>, f64)>> {
+        let mut store = self.store.write().map_err(|_| KlineError::LockPoisoned)?;
+        store.check_type(key, "zset")?;
+        if count == 0 || store.get(key).is_none() {
+            return Ok(Vec::new());
+        }
+
+        self.append_log(&mut store, &format!("zpopmin {} {}", encode(key), count))?;
+
+        let result = store.zpopmin(key, count).expect("type checked above");
+        self.key_changed(&store, key);
+        Ok(result)
+    }
+
+    fn with_zset<R>(&self, key: &[u8], f: impl FnOnce(Option<&SortedSet>) -> R) -> Result<R> {
+        let store = self.store.read().map_err(|_| KlineError::LockPoisoned)?;
+        store.check_type(key, "zset")?;
+        match store.get(key) {
+            Some(Value::SortedSet(zset)) => Ok(f(Some(zset))),
+            _ => Ok(f(None)),
+        }
+    }
+
+    /// Appends an entry to the stream at `key` and returns its ID. With
+    /// `id: None` the ID is generated from the current time; an explicit ID
+    /// must be greater than every ID already in the stream and below
+    /// `StreamId::MAX`, so later entries can still be given one.
+    pub fn xadd(&self, key: &[u8], id: Option<StreamId>, fields: Fields) -> Result<StreamId> {
+        let size: usize = fields.iter().map(|(f, v)| f.len() + v.len()).sum();
+        self.check_sizes(key, size)?;
+
+        let mut store = self.store.write().map_err(|_| KlineError::LockPoisoned)?;
+        store.check_type(key, "stream")?;
+        let last_id = match store.get(key) {
+            Some(Value::Stream(stream)) => stream.last_id,
+            _ => StreamId::MIN,
+        };
+        let id = match id {
+            Some(id) if id > last_id && id < StreamId::MAX => id,
+            Some(id) => return Err(KlineError::InvalidStreamId { id: id.to_string() }),
+            None => last_id
+                .generate_after(now_ms())
+                .filter(|id| *id < StreamId::MAX)
+                .ok_or_else(|| KlineError::InvalidStreamId { id: format!("after {}", last_id) })?,
+        };
+        let total = store.size_of(key) + ID_SIZE + size;
+        self.check_sizes(key, total)?;
+        store.check_limits(&self.config.limits, key, total)?;
+
+        let pairs = encode_pairs(fields.iter().map(|(f, v)| (f, v)));
+        self.append_log(&mut store, &format!("xadd {} {} {}", encode(key), id, pairs))?;
+
+        store.update_stream(key, |stream| ((), stream.add(id, fields))).expect("type checked above");
+        self.key_changed(&store, key);
+        Ok(id)
+    }
+
+    /// Entries with `start <= id <= end`, oldest first, capped at `count`.
+    pub fn xrange(&self, key: &[u8], start: StreamId, end: StreamId, count: Option<usize>) -> Result<Vec<(StreamId, Fields)>> {
+        self.with_stream(key, |stream| match stream {
+            Some(stream) => stream.range(start, end, count.unwrap_or(usize::MAX)),
+            None => Vec::new(),
+        })
+    }
+
+    /// Entries with IDs strictly greater than `after`.
+    pub fn xread(&self, key: &[u8], after: StreamId, count: Option<usize>) -> Result<Vec<(StreamId, Fields)>> {
+        match after.next() {
+            Some(start) => self.xrange(key, start, StreamId::MAX, count),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Like `xread`, but waits up to `timeout` for new entries when none are
+    /// available yet. Returns an empty list on timeout.
+    pub async fn xread_blocking(&self, key: &[u8], after: StreamId, count: Option<usize>, timeout: Duration) -> Result<Vec<(StreamId, Fields)>> {
+        let entries = self.wait_for(&[key], timeout, || {
+            let entries = self.xread(key, after, count)?;
+            Ok((!entries.is_empty()).then_some(entries))
+        }).await?;
+        Ok(entries.unwrap_or_default())
+    }
+
+    /// ID of the newest entry ever added to the stream, which is what `$`
+    /// resolves to in reads.
+    pub fn xlast_id(&self, key: &[u8]) -> Result<StreamId> {
+        self.with_stream(key, |stream| stream.map_or(StreamId::MIN, |stream| stream.last_id))
+    }
+
+    pub fn xlen(&self, key: &[u8]) -> Result<usize> {
+        self.with_stream(key, |stream| stream.map_or(0, |stream| stream.entries.len()))
+    }
+
+    /// Trims the stream to its newest `max_len` entries and returns how many
+    /// were removed.
+    pub fn xtrim_max_len(&self, key: &[u8], max_len: usize) -> Result<usize> {
+        self.xtrim(key, |stream| {
+            let excess = stream.entries.len().saturating_sub(max_len);
+            stream.entries.keys().nth(excess).copied().or(stream.last_id.next()).unwrap_or(StreamId::MAX)
+        })
+    }
+
+    /// Removes entries older than `max_age`, judged by the time embedded in
+    /// their IDs, and returns how many were removed.
+    pub fn xtrim_max_age(&self, key: &[u8], max_age: Duration) -> Result<usize> {
+        let min_ms = now_ms().saturating_sub(max_age.as_millis() as u64);
+        self.xtrim(key, |_| StreamId::new(min_ms, 0))
+    }
+
+    /// Logs the trim as the lowest ID to keep, so replay does not depend on
+    /// the clock.
+    fn xtrim(&self, key: &[u8], min_id: impl FnOnce(&Stream) -> StreamId) -> Result<usize> {
+        let mut store = self.store.write().map_err(|_| KlineError::LockPoisoned)?;
+        store.check_type(key, "stream")?;
+        let Some(Value::Stream(stream)) = store.get(key) else {
+            return Ok(0);
+        };
+        let min_id = min_id(stream);
+        if stream.entries.range(..min_id).next().is_none() {
+            return Ok(0);
+        }
+
+        self.append_log(&mut store, &format!("xtrim {} {}", encode(key), min_id))?;
+
+        let result = store.update_stream(key, |stream| stream.trim_before(min_id)).expect("type checked above");
+        self.key_changed(&store, key);
+        Ok(result)
+    }
+
+    /// Creates a consumer group that will deliver entries after `start`, or
+    /// only entries added from now on when `start` is `None`. Creates the
+    /// stream if needed. Returns `false` if the group already exists.
+    pub fn xgroup_create(&self, key: &[u8], group: &str, start: Option<StreamId>) -> Result<bool> {
+        self.check_sizes(key, 0)?;
+        let mut store = self.store.write().map_err(|_| KlineError::LockPoisoned)?;
+        store.check_type(key, "stream")?;
+        let last_id = match store.get(key) {
+            Some(Value::Stream(stream)) if stream.groups.contains_key(group) => return Ok(false),
+            Some(Value::Stream(stream)) => stream.last_id,
+            _ => {
+                store.check_limits(&self.config.limits, key, 0)?;
+                StreamId::MIN
+            }
+        };
+        let start = start.unwrap_or(last_id);
+
+        self.append_log(&mut store, &format!("xgroup {} {} {}", encode(key), encode(group.as_bytes()), start))?;
+
+        store.update_stream(key, |stream| {
+            stream.groups.entry(group.to_string()).or_default().last_delivered = start;
+            ((), 0)
+        }).expect("type checked above");
+        Ok(true)
+    }
+
+    /// Delivers up to `count` entries the group has not seen yet to
+    /// `consumer` and records them as pending until acknowledged.
+    pub fn xreadgroup(&self, key: &[u8], group: &str, consumer: &str, count: Option<usize>) -> Result<Vec<(StreamId, Fields)>> {
+        let mut store = self.store.write().map_err(|_| KlineError::LockPoisoned)?;
+        store.check_type(key, "stream")?;
+        let entries = match store.get(key) {
+            Some(Value::Stream(stream)) => {
+                let group = stream.groups.get(group).ok_or_else(|| KlineError::NoSuchGroup { group: group.to_string() })?;
+                match group.last_delivered.next() {
+                    Some(start) => stream.range(start, StreamId::MAX, count.unwrap_or(usize::MAX)),
+                    None => Vec::new(),
+                }
+            }
+            _ => return Err(KlineError::NoSuchGroup { group: group.to_string() }),
+        };
+        if entries.is_empty() {
+            return Ok(entries);
+        }
+
+        let now = now_ms();
+        let ids: Vec<StreamId> = entries.iter().map(|(id, _)| *id).collect();
+        let ids_str: Vec<String> = ids.iter().map(StreamId::to_string).collect();
+        self.append_log(&mut store, &format!(
+            "xdeliver {} {} {} {} {}",
+            encode(key),
+            encode(group.as_bytes()),
+            encode(consumer.as_bytes()),
+            now,
+            ids_str.join(" ")
+        ))?;
+
+        store.update_stream(key, |stream| (stream.deliver(group, consumer, now, &ids), 0)).expect("type checked above");
+        Ok(entries)
+    }
+
+    /// Like `xreadgroup`, but waits up to `timeout` for new entries when none
+    /// are available yet. Returns an empty list on timeout.
+    pub async fn xreadgroup_blocking(
+        &self,
+        key: &[u8],
+        group: &str,
+        consumer: &str,
+        count: Option<usize>,
+        timeout: Duration,
+    ) -> Result<Vec<(StreamId, Fields)>> {
+        let entries = self.wait_for(&[key], timeout, || {
+            let entries = self.xreadgroup(key, group, consumer, count)?;
+            Ok((!entries.is_empty()).then_some(entries))
+        }).await?;
+        Ok(entries.unwrap_or_default())
+    }
+
+    /// Acknowledges delivered entries and returns how many were pending.
+    pub fn xack(&self, key: &[u8], group: &str, ids: &[StreamId]) -> Result<usize> {
+        let mut store = self.store.write().map_err(|_| KlineError::LockPoisoned)?;
+        store.check_type(key, "stream")?;
+        let pending = match store.get(key) {
+            Some(Value::Stream(stream)) => stream.groups.get(group).ok_or_else(|| KlineError::NoSuchGroup { group: group.to_string() })?,
+            _ => return Err(KlineError::NoSuchGroup { group: group.to_string() }),
+        };
+        let acked: Vec<StreamId> = ids.iter().copied().filter(|id| pending.pending.contains_key(id)).collect();
+        if acked.is_empty() {
+            return Ok(0);
+        }
+
+        let ids_str: Vec<String> = acked.iter().map(StreamId::to_string).collect();
+        self.append_log(&mut store, &format!("xack {} {} {}", encode(key), encode(group.as_bytes()), ids_str.join(" ")))?;
+
+        Ok(store.update_stream(key, |stream| (stream.ack(group, &acked), 0)).expect("type checked above"))
+    }
+
+    /// Entries delivered to `group` but not yet acknowledged, oldest first.
+    pub fn xpending(&self, key: &[u8], group: &str) -> Result<Vec<(StreamId, PendingEntry)>> {
+        self.with_stream(key, |stream| {
+            let group = stream
+                .and_then(|stream| stream.groups.get(group))
+                .ok_or_else(|| KlineError::NoSuchGroup { group: group.to_string() })?;
+            Ok(group.pending.iter().map(|(id, pending)| (*id, pending.clone())).collect())
+        })?
+    }
+
+    fn with_stream<R>(&self, key: &[u8], f: impl FnOnce(Option<&Stream>) -> R) -> Result<R> {
+        let store = self.store.read().map_err(|_| KlineError::LockPoisoned)?;
+        store.check_type(key, "stream")?;
+        match store.get(key) {
+            Some(Value::Stream(stream)) => Ok(f(Some(stream))),
+            _ => Ok(f(None)),
+        }
+    }
+}
+
+fn end_name(front: bool) -> &'static str {
+    if front { "left" } else { "right" }
+}
+
+fn list_len(value: Option<&Value>) -> usize {
+    match value {
+        Some(Value::List(list)) => list.len(),
+        _ => 0,
+    }
+}
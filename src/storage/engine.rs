@@ -1,17 +1,32 @@
-use std::collections::HashMap;
-use std::fs::{File, OpenOptions};
+use std::fs::OpenOptions;
 use std::io::{BufRead, BufReader, Write};
-use std::sync::{Arc, RwLock, Mutex};
+use std::sync::{Arc, RwLock};
 use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use base64::{engine::general_purpose, Engine as _};
 use crate::constants::db::*;
 use crate::config::KlineConfig;
 use crate::error::{KlineError, Result};
+use super::changes::{ChangeEvent, ChangeFeed, ChangeOp, KeyFilter, Subscription};
+use super::history::Version;
+use super::keys::{keys_in_range, keys_with_prefix, prefix_renames};
+use super::lease::Lease;
+use super::meta::{decode_meta, encode_meta, meta_record, MetaUpdate, Metadata};
+use super::pubsub::{Broker, ChannelFilter, ChannelSubscription};
+use super::store::Store;
+use super::stream::{Fields, PendingEntry, StreamId};
+use super::value::Value;
+use super::waiters::Waiters;
+use super::wal::{split_header, ChangesSince, Wal};
 
 pub struct Kline {
-    store: Arc<RwLock<HashMap<Vec<u8>, Vec<u8>>>>,
-    log: Arc<Mutex<File>>,
-    config: KlineConfig,
+    pub(super) store: Arc<RwLock<Store>>,
+    wal: Arc<Wal>,
+    pub(super) config: KlineConfig,
+    pub(super) waiters: Waiters,
+    pub(super) lease_waiters: Waiters,
+    changes: Arc<ChangeFeed>,
+    broker: Broker,
 }
 
 impl Kline {
@@ -27,70 +42,389 @@ impl Kline {
             .create(true)
             .open(path)?;
 
-        let mut store = HashMap::new();
+        let mut store = Store::new(&config.limits);
         let reader = BufReader::new(&file);
+        let mut checkpoint = 0;
+        let mut last_seq = 0;
+        store.set_replaying(true);
 
         for line in reader.lines() {
             let line = line?;
-            let parts: Vec<&str> = line.splitn(3, ' ').collect();
-
-             match parts.as_slice() {
-                ["put", key_b64, value_b64] => {
-                    if let (Ok(key), Ok(value)) = (
-                        general_purpose::STANDARD.decode(key_b64),
-                        general_purpose::STANDARD.decode(value_b64),
-                    ) {
-                        store.insert(key, value);
+            let (seq, timestamp, record) = split_header(&line);
+            match seq {
+                Some(seq) => last_seq = last_seq.max(seq),
+                None => {
+                    if let Some(Ok(seq)) = record.strip_prefix("checkpoint ").map(str::parse::<u64>) {
+                        checkpoint = seq;
                     }
                 }
-                ["delete", key_b64] => {
-                    if let Ok(key) = general_purpose::STANDARD.decode(key_b64) {
-                        store.remove(&key);
-                    }
+            }
+            if let Some(timestamp) = timestamp {
+                store.set_clock(timestamp);
+            }
+            let keys = string_record_keys(&store, record);
+            Self::replay(&mut store, record);
+            if let Some(seq) = seq {
+                for key in keys {
+                    store.record_version(&config.history, &key, seq, timestamp.unwrap_or(0));
                 }
-                _ => {}
             }
         }
 
+        store.set_replaying(false);
         let store_arc = Arc::new(RwLock::new(store));
-        let _log = OpenOptions::new().append(true).create(true).open(path)?;
+        let wal = Arc::new(Wal::open(
+            path,
+            last_seq.max(checkpoint),
+            checkpoint,
+            config.storage.retained_segments,
+        )?);
 
         // compaction thread
 
-        let path_str = path.to_string();
         let store_for_thread = Arc::clone(&store_arc);
+        let wal_for_thread = Arc::clone(&wal);
+        let config_for_thread = config.clone();
 
         thread::spawn(move || loop {
             thread::sleep(COMPACTION_INTERVAL);
-            let temp_path = format!("{}{}", path_str, TEMP_FILE_SUFFIX);
-            if let Ok(store) = store_for_thread.read() {
-                if let Ok(mut temp_file) = File::create(&temp_path) {
-                    for (key, val) in store.iter() {
-                        let key_b64 = general_purpose::STANDARD.encode(key);
-                        let val_b64 = general_purpose::STANDARD.encode(val);
-                        let _ = writeln!(temp_file, "put {} {}", key_b64, val_b64);
-                    }
-                    // Atomically replace old log
-                    let _ = std::fs::rename(&temp_path, &path_str);
-                }
-            }
+            let _ = Self::compact_into(&store_for_thread, &wal_for_thread, &config_for_thread);
         });
 
+        // expiry thread
 
-        // reopen file for append (because BufReader uses read-only)
-        let log = OpenOptions::new()
-            .append(true)
-            .open(path)?;
+        let changes = Arc::new(ChangeFeed::default());
+        let store_for_thread = Arc::clone(&store_arc);
+        let wal_for_thread = Arc::clone(&wal);
+        let changes_for_thread = Arc::clone(&changes);
+        let config_for_thread = config.clone();
+        let cleanup_interval = Duration::from_secs(config.ttl.cleanup_interval_secs.max(1));
+
+        thread::spawn(move || loop {
+            thread::sleep(cleanup_interval);
+            let _ = Self::expire_into(&store_for_thread, &wal_for_thread, &changes_for_thread, &config_for_thread);
+        });
 
         Ok(Kline { 
             store: store_arc, 
-            log: Arc::new(Mutex::new(log)),
+            wal,
             config,
+            waiters: Waiters::default(),
+            lease_waiters: Waiters::default(),
+            changes,
+            broker: Broker::default(),
         })
     }
 
 
+    /// Applies one log line to the in-memory store. Unknown or malformed
+    /// lines are skipped.
+    fn replay(store: &mut Store, line: &str) {
+        let parts: Vec<&str> = line.split(' ').collect();
+
+        match parts.as_slice() {
+            ["put", key_b64, value_b64] => {
+                if let (Ok(key), Ok(value)) = (
+                    general_purpose::STANDARD.decode(key_b64),
+                    general_purpose::STANDARD.decode(value_b64),
+                ) {
+                    store.insert(key, Value::String(value));
+                }
+            }
+            ["put", key_b64, value_b64, content_type, tags @ ..] => {
+                if let (Some(key), Some(value), Some((content_type, tags))) =
+                    (decode(key_b64), decode(value_b64), decode_meta(content_type, tags))
+                {
+                    store.insert(key.clone(), Value::String(value));
+                    if let Some(meta) = store.meta_mut(&key) {
+                        meta.content_type = content_type;
+                        meta.tags = tags;
+                    }
+                }
+            }
+            ["overwrite", key_b64, value_b64] => {
+                if let (Some(key), Some(value)) = (decode(key_b64), decode(value_b64)) {
+                    store.overwrite(key, Value::String(value));
+                }
+            }
+            ["meta", key_b64, created_at, updated_at, content_type, tags @ ..] => {
+                if let (Some(key), Ok(created_at), Ok(updated_at), Some((content_type, tags))) = (
+                    decode(key_b64),
+                    created_at.parse::<u64>(),
+                    updated_at.parse::<u64>(),
+                    decode_meta(content_type, tags),
+                ) && let Some(meta) = store.meta_mut(&key)
+                {
+                    *meta = Metadata { created_at, updated_at, content_type, tags, expires_at: meta.expires_at };
+                }
+            }
+            ["setex", key_b64, expires_at, value_b64, meta @ ..] => {
+                if let (Some(key), Ok(expires_at), Some(value)) = (decode(key_b64), expires_at.parse::<u64>(), decode(value_b64)) {
+                    let meta = match meta {
+                        [] => None,
+                        [content_type, tags @ ..] => decode_meta(content_type, tags),
+                    };
+                    store.insert(key.clone(), Value::String(value));
+                    if let Some(stored) = store.meta_mut(&key) {
+                        stored.expires_at = Some(expires_at);
+                        if let Some((content_type, tags)) = meta {
+                            stored.content_type = content_type;
+                            stored.tags = tags;
+                        }
+                    }
+                }
+            }
+            ["expire", key_b64, expires_at] => {
+                if let (Some(key), Ok(expires_at)) = (decode(key_b64), expires_at.parse::<u64>())
+                    && let Some(meta) = store.meta_mut(&key)
+                {
+                    meta.expires_at = Some(expires_at);
+                }
+            }
+            ["persist", key_b64] => {
+                if let Some(key) = decode(key_b64)
+                    && let Some(meta) = store.meta_mut(&key)
+                {
+                    meta.expires_at = None;
+                }
+            }
+            ["expired", keys @ ..] => {
+                for key in keys.iter().filter_map(|key_b64| decode(key_b64)) {
+                    store.remove(&key);
+                }
+            }
+            ["delete", key_b64] => {
+                if let Ok(key) = general_purpose::STANDARD.decode(key_b64) {
+                    store.remove(&key);
+                }
+            }
+            ["mput", pairs @ ..] => {
+                for pair in pairs.chunks(2) {
+                    if let [key_b64, value_b64] = pair
+                        && let (Some(key), Some(value)) = (decode(key_b64), decode(value_b64))
+                    {
+                        store.insert(key, Value::String(value));
+                    }
+                }
+            }
+            ["mdelete", keys @ ..] => {
+                for key in keys.iter().filter_map(|key_b64| decode(key_b64)) {
+                    store.remove(&key);
+                }
+            }
+            ["clear"] => store.clear(),
+            ["rename", from_b64, to_b64] => {
+                if let (Some(from), Some(to)) = (decode(from_b64), decode(to_b64)) {
+                    store.rename(&[(from, to)]);
+                }
+            }
+            ["copy", from_b64, to_b64] => {
+                if let (Some(from), Some(to)) = (decode(from_b64), decode(to_b64)) {
+                    store.copy(&from, &to);
+                }
+            }
+            ["delrange", start_b64, end_b64] => {
+                if let (Some(start), Some(end)) = (decode(start_b64), decode(end_b64)) {
+                    for key in keys_in_range(store, &start, &end) {
+                        store.remove(&key);
+                    }
+                }
+            }
+            ["delprefix", prefix_b64] => {
+                if let Some(prefix) = decode(prefix_b64) {
+                    for key in keys_with_prefix(store, &prefix) {
+                        store.remove(&key);
+                    }
+                }
+            }
+            ["renameprefix", from_b64, to_b64] => {
+                if let (Some(from), Some(to)) = (decode(from_b64), decode(to_b64)) {
+                    let renames = prefix_renames(store, &from, &to);
+                    store.rename(&renames);
+                }
+            }
+            ["version", key_b64, version, timestamp, value] => {
+                if let (Some(key), Ok(version), Ok(timestamp)) = (decode(key_b64), version.parse::<u64>(), timestamp.parse::<u64>()) {
+                    let value = if *value == "-" { None } else { decode(value) };
+                    store.history_mut().restore(key, Version { version, timestamp, value });
+                }
+            }
+            ["lease", name_b64, owner_b64, token, expires_at] => {
+                if let (Some(name), Some(owner), Ok(token), Ok(expires_at)) = (
+                    decode_string(name_b64),
+                    decode_string(owner_b64),
+                    token.parse::<u64>(),
+                    expires_at.parse::<u64>(),
+                ) {
+                    store.leases_mut().insert(Lease { name, owner, token, expires_at });
+                }
+            }
+            ["unlease", name_b64] => {
+                if let Some(name) = decode_string(name_b64) {
+                    store.leases_mut().remove(&name);
+                }
+            }
+            ["fence", token] => {
+                if let Ok(token) = token.parse::<u64>() {
+                    store.leases_mut().advance_token(token);
+                }
+            }
+            ["append", key_b64, data_b64] => {
+                if let (Ok(key), Ok(data)) = (
+                    general_purpose::STANDARD.decode(key_b64),
+                    general_purpose::STANDARD.decode(data_b64),
+                ) {
+                    store.modify_string(&key, |value| value.extend_from_slice(&data));
+                }
+            }
+            ["setrange", key_b64, offset, data_b64] => {
+                if let (Ok(key), Ok(offset), Ok(data)) = (
+                    general_purpose::STANDARD.decode(key_b64),
+                    offset.parse::<usize>(),
+                    general_purpose::STANDARD.decode(data_b64),
+                ) {
+                    store.modify_string(&key, |value| write_at(value, offset, &data));
+                }
+            }
+            [op @ ("lpush" | "rpush"), key_b64, items @ ..] => {
+                if let (Some(key), Some(items)) = (decode(key_b64), decode_all(items)) {
+                    store.push(&key, items, *op == "lpush");
+                }
+            }
+            [op @ ("lpop" | "rpop"), key_b64, count] => {
+                if let (Some(key), Ok(count)) = (decode(key_b64), count.parse::<usize>()) {
+                    store.pop(&key, count, *op == "lpop");
+                }
+            }
+            ["lmove", source_b64, destination_b64, from, to] => {
+                if let (Some(source), Some(destination)) = (decode(source_b64), decode(destination_b64))
+                    && let Some(item) = store.pop(&source, 1, *from == "left").and_then(|mut items| items.pop())
+                {
+                    store.push(&destination, vec![item], *to == "left");
+                }
+            }
+            ["lrem", key_b64, count, item_b64] => {
+                if let (Some(key), Ok(count), Some(item)) = (decode(key_b64), count.parse::<i64>(), decode(item_b64)) {
+                    store.lrem(&key, count, &item);
+                }
+            }
+            ["hset", key_b64, pairs @ ..] => {
+                if let (Some(key), Some(fields)) = (decode(key_b64), decode_pairs(pairs)) {
+                    store.hset(&key, fields);
+                }
+            }
+            ["hdel", key_b64, fields @ ..] => {
+                if let (Some(key), Some(fields)) = (decode(key_b64), decode_all(fields)) {
+                    store.hdel(&key, &fields);
+                }
+            }
+            ["sadd", key_b64, members @ ..] => {
+                if let (Some(key), Some(members)) = (decode(key_b64), decode_all(members)) {
+                    store.sadd(&key, members);
+                }
+            }
+            ["srem", key_b64, members @ ..] => {
+                if let (Some(key), Some(members)) = (decode(key_b64), decode_all(members)) {
+                    store.srem(&key, &members);
+                }
+            }
+            ["zadd", key_b64, pairs @ ..] => {
+                if let Some(key) = decode(key_b64) {
+                    let members: Option<Vec<(f64, Vec<u8>)>> = pairs
+                        .chunks(2)
+                        .map(|pair| match pair {
+                            [score, member] => Some((score.parse::<f64>().ok()?, decode(member)?)),
+                            _ => None,
+                        })
+                        .collect();
+                    if let Some(members) = members {
+                        store.zadd(&key, members);
+                    }
+                }
+            }
+            ["zrem", key_b64, members @ ..] => {
+                if let (Some(key), Some(members)) = (decode(key_b64), decode_all(members)) {
+                    store.zrem(&key, &members);
+                }
+            }
+            ["zpopmin", key_b64, count] => {
+                if let (Some(key), Ok(count)) = (decode(key_b64), count.parse::<usize>()) {
+                    store.zpopmin(&key, count);
+                }
+            }
+            ["xadd", key_b64, id, fields @ ..] => {
+                if let (Some(key), Ok(id), Some(fields)) = (decode(key_b64), id.parse::<StreamId>(), decode_pairs(fields)) {
+                    store.update_stream(&key, |stream| ((), stream.add(id, fields)));
+                }
+            }
+            ["xtrim", key_b64, min_id] => {
+                if let (Some(key), Ok(min_id)) = (decode(key_b64), min_id.parse::<StreamId>()) {
+                    store.update_stream(&key, |stream| ((), stream.trim_before(min_id).1));
+                }
+            }
+            ["xsetid", key_b64, id] => {
+                if let (Some(key), Ok(id)) = (decode(key_b64), id.parse::<StreamId>()) {
+                    store.update_stream(&key, |stream| {
+                        stream.last_id = stream.last_id.max(id);
+                        ((), 0)
+                    });
+                }
+            }
+            ["xgroup", key_b64, group_b64, id] => {
+                if let (Some(key), Some(group), Ok(id)) = (decode(key_b64), decode_string(group_b64), id.parse::<StreamId>()) {
+                    store.update_stream(&key, |stream| {
+                        stream.groups.entry(group).or_default().last_delivered = id;
+                        ((), 0)
+                    });
+                }
+            }
+            ["xdeliver", key_b64, group_b64, consumer_b64, now_ms, ids @ ..] => {
+                if let (Some(key), Some(group), Some(consumer), Ok(now_ms), Some(ids)) = (
+                    decode(key_b64),
+                    decode_string(group_b64),
+                    decode_string(consumer_b64),
+                    now_ms.parse::<u64>(),
+                    parse_ids(ids),
+                ) {
+                    store.update_stream(&key, |stream| (stream.deliver(&group, &consumer, now_ms, &ids), 0));
+                }
+            }
+            ["xack", key_b64, group_b64, ids @ ..] => {
+                if let (Some(key), Some(group), Some(ids)) = (decode(key_b64), decode_string(group_b64), parse_ids(ids)) {
+                    store.update_stream(&key, |stream| (stream.ack(&group, &ids), 0));
+                }
+            }
+            ["xpending", key_b64, group_b64, consumer_b64, id, delivered_ms, count] => {
+                if let (Some(key), Some(group), Some(consumer), Ok(id), Ok(delivered_ms), Ok(delivery_count)) = (
+                    decode(key_b64),
+                    decode_string(group_b64),
+                    decode_string(consumer_b64),
+                    id.parse::<StreamId>(),
+                    delivered_ms.parse::<u64>(),
+                    count.parse::<u32>(),
+                ) {
+                    store.update_stream(&key, |stream| {
+                        if let Some(group) = stream.groups.get_mut(&group) {
+                            group.pending.insert(id, PendingEntry { consumer, delivered_ms, delivery_count });
+                        }
+                        ((), 0)
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+
     pub fn put(&self, key: Vec<u8>, value: Vec<u8>) -> Result<()> {
+        self.check_sizes(&key, value.len())?;
+        
+        // Hold the write lock across the limit check, the log append and the
+        // insert so concurrent puts cannot push the store past its limits.
+        let mut store = self.store.write().map_err(|_| KlineError::LockPoisoned)?;
+        self.put_locked(&mut store, key, value)
+    }
+
+    pub(super) fn check_sizes(&self, key: &[u8], value_len: usize) -> Result<()> {
         // Validate key and value sizes
         if key.len() > self.config.limits.max_key_size {
             return Err(KlineError::KeyTooLarge { 
@@ -99,75 +433,376 @@ impl Kline {
             });
         }
         
-        if value.len() > self.config.limits.max_value_size {
+        if value_len > self.config.limits.max_value_size {
             return Err(KlineError::ValueTooLarge { 
-                size: value.len(), 
+                size: value_len, 
                 max: self.config.limits.max_value_size 
             });
         }
+        Ok(())
+    }
+
+    /// Checks the key's type and limits, logs and inserts a value while the
+    /// caller holds the store write lock. An existing key keeps its content
+    /// type and tags.
+    pub(super) fn put_locked(&self, store: &mut Store, key: Vec<u8>, value: Vec<u8>) -> Result<()> {
+        self.put_locked_with_meta(store, key, value, MetaUpdate::default())
+    }
+
+    /// `put_locked`, also setting the content type and tags given in `meta`
+    /// in the same log record.
+    pub(super) fn put_locked_with_meta(&self, store: &mut Store, key: Vec<u8>, value: Vec<u8>, meta: MetaUpdate) -> Result<()> {
+        store.check_type(&key, "string")?;
+        store.check_limits(&self.config.limits, &key, value.len())?;
         
-        // Check if database is full
-        {
-            let store = self.store.read().map_err(|_| KlineError::LockPoisoned)?;
-            if store.len() >= self.config.limits.max_keys {
-                return Err(KlineError::DatabaseFull { 
-                    current: store.len(), 
-                    max: self.config.limits.max_keys 
-                });
-            }
-        }
-        
+        let meta = (!meta.is_empty()).then(|| meta.apply(store.meta(&key)));
         let key_b64 = general_purpose::STANDARD.encode(&key);
         let value_b64 = general_purpose::STANDARD.encode(&value);
-        
+        let record = match &meta {
+            Some((content_type, tags)) => format!("put {} {} {}", key_b64, value_b64, encode_meta(content_type, tags)),
+            None => format!("put {} {}", key_b64, value_b64),
+        };
+        self.append_log(store, &record)?;
+
+        store.insert(key.clone(), Value::String(value));
+        if let Some((content_type, tags)) = meta
+            && let Some(stored) = store.meta_mut(&key)
         {
-            let mut log = self.log.lock().map_err(|_| KlineError::LockPoisoned)?;
-            writeln!(log, "put {} {}", key_b64, value_b64)?;
-            log.flush()?;
+            stored.content_type = content_type;
+            stored.tags = tags;
         }
+        self.string_changed(store, &key);
+        Ok(())
+    }
 
-        let mut store = self.store.write().map_err(|_| KlineError::LockPoisoned)?;
-        store.insert(key, value);
+    /// Replaces the string at `key` with a value computed from the old one,
+    /// keeping its TTL, while the caller holds the store write lock.
+    fn overwrite_locked(&self, store: &mut Store, key: Vec<u8>, value: Vec<u8>) -> Result<()> {
+        store.check_limits(&self.config.limits, &key, value.len())?;
+        self.append_log(store, &format!("overwrite {} {}", encode(&key), encode(&value)))?;
+
+        store.overwrite(key.clone(), Value::String(value));
+        self.string_changed(store, &key);
         Ok(())
     }
 
+    /// Logs a record and sets the store's clock to its timestamp, so the
+    /// mutation that follows stamps the same time replay will.
+    pub(super) fn append_log(&self, store: &mut Store, line: &str) -> Result<()> {
+        let timestamp = now_ms();
+        self.wal.append(line, timestamp)?;
+        store.set_clock(timestamp);
+        Ok(())
+    }
+
+    pub fn config(&self) -> &KlineConfig {
+        &self.config
+    }
+
+    /// Sequence number of the last committed mutation.
+    pub fn last_seq(&self) -> u64 {
+        self.wal.last_seq()
+    }
+
+    /// Reads committed mutations with sequence numbers greater than `seq`
+    /// back from the log, oldest first, so a consumer can resume where it
+    /// left off after a restart. History is served from the live log plus
+    /// the segments kept by compaction (`storage.retained_segments`); if
+    /// records after `seq` have been discarded this fails with
+    /// `ChangesCompacted` and the consumer must resynchronise from a full
+    /// read of the store.
+    pub fn changes_since(&self, seq: u64) -> Result<ChangesSince> {
+        self.wal.changes_since(seq)
+    }
+
+    /// Subscribes to committed mutations of keys matching `filter`. Events
+    /// arrive in commit order; a subscriber that falls more than
+    /// `SUBSCRIBER_BUFFER` events behind receives `ChangeMessage::Overflow`
+    /// in place of the events it missed.
+    pub fn subscribe(&self, filter: KeyFilter) -> Subscription {
+        self.changes.subscribe(filter)
+    }
+
+    /// Publishes `payload` on `channel` and returns how many subscribers
+    /// received it. Messages are not persisted.
+    pub fn publish(&self, channel: &str, payload: Vec<u8>) -> usize {
+        self.broker.publish(channel, payload)
+    }
+
+    /// Subscribes to messages published on channels matching any of
+    /// `filters`.
+    pub fn subscribe_channels(&self, filters: Vec<ChannelFilter>) -> ChannelSubscription {
+        self.broker.subscribe(filters)
+    }
+
+    /// Wakes blocked readers of `key` and publishes a change event for it.
+    /// Called with the store write lock held, right after the mutation is
+    /// applied, so events are emitted in commit order.
+    pub(super) fn key_changed(&self, store: &Store, key: &[u8]) {
+        self.waiters.wake(key);
+        // Every mutation is logged under the same write lock just before
+        // this call, so the last WAL sequence number is this change's.
+        let seq = self.wal.last_seq();
+        self.changes.publish(key, || {
+            let (op, value) = match store.get(key) {
+                None => (ChangeOp::Delete, None),
+                Some(Value::String(bytes)) => (ChangeOp::Put, Some(bytes.clone())),
+                Some(_) => (ChangeOp::Update, None),
+            };
+            ChangeEvent {
+                seq,
+                op,
+                key: key.to_vec(),
+                value,
+                timestamp: self.wal.last_timestamp(),
+            }
+        });
+    }
+
+    /// Records a history version for a string write or delete that was just
+    /// logged, then reports the change like any other.
+    pub(super) fn string_changed(&self, store: &mut Store, key: &[u8]) {
+        store.record_version(&self.config.history, key, self.wal.last_seq(), self.wal.last_timestamp());
+        self.key_changed(store, key);
+    }
+
+    /// Retries `attempt` until it yields a value or `timeout` elapses,
+    /// sleeping between attempts until one of `keys` is written.
+    pub(super) async fn wait_for<T>(
+        &self,
+        keys: &[&[u8]],
+        timeout: Duration,
+        mut attempt: impl FnMut() -> Result<Option<T>>,
+    ) -> Result<Option<T>> {
+        let deadline = deadline(timeout)?;
+        let notify = self.waiters.register(keys);
+        loop {
+            if let Some(value) = attempt()? {
+                return Ok(Some(value));
+            }
+            if tokio::time::timeout_at(deadline, notify.notified()).await.is_err() {
+                return Ok(None);
+            }
+        }
+    }
+
+    /// Atomically adds `delta` to the integer stored at `key` and returns the
+    /// new value. A missing key counts as 0.
+    pub fn incr_by(&self, key: &[u8], delta: i64) -> Result<i64> {
+        self.check_sizes(key, 0)?;
+        let mut store = self.store.write().map_err(|_| KlineError::LockPoisoned)?;
+        let current = match store.get_string(key)? {
+            Some(value) => std::str::from_utf8(value)
+                .ok()
+                .and_then(|s| s.parse::<i64>().ok())
+                .ok_or_else(|| KlineError::NotANumber { key: key_string(key) })?,
+            None => 0,
+        };
+
+        let next = current
+            .checked_add(delta)
+            .ok_or_else(|| KlineError::NumericOverflow { key: key_string(key) })?;
+        self.overwrite_locked(&mut store, key.to_vec(), next.to_string().into_bytes())?;
+        Ok(next)
+    }
+
+    pub fn decr_by(&self, key: &[u8], delta: i64) -> Result<i64> {
+        let delta = delta
+            .checked_neg()
+            .ok_or_else(|| KlineError::NumericOverflow { key: key_string(key) })?;
+        self.incr_by(key, delta)
+    }
+
+    /// Float variant of `incr_by`. The result is stored in its shortest
+    /// decimal form.
+    pub fn incr_by_float(&self, key: &[u8], delta: f64) -> Result<f64> {
+        self.check_sizes(key, 0)?;
+        let mut store = self.store.write().map_err(|_| KlineError::LockPoisoned)?;
+        let current = match store.get_string(key)? {
+            Some(value) => std::str::from_utf8(value)
+                .ok()
+                .and_then(|s| s.parse::<f64>().ok())
+                .filter(|n| n.is_finite())
+                .ok_or_else(|| KlineError::NotANumber { key: key_string(key) })?,
+            None => 0.0,
+        };
+
+        let next = current + delta;
+        if !next.is_finite() {
+            return Err(KlineError::NumericOverflow { key: key_string(key) });
+        }
+        self.overwrite_locked(&mut store, key.to_vec(), next.to_string().into_bytes())?;
+        Ok(next)
+    }
+
 
 
     pub fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
         let store = self.store.read().map_err(|_| KlineError::LockPoisoned)?;
-        Ok(store.get(key).cloned())
+        Ok(store.get_string(key)?.cloned())
+    }
+
+    /// Stores `value` under `key` and returns the previous value. Unlike
+    /// `put`, the key keeps its TTL.
+    pub fn get_set(&self, key: Vec<u8>, value: Vec<u8>) -> Result<Option<Vec<u8>>> {
+        self.check_sizes(&key, value.len())?;
+        let mut store = self.store.write().map_err(|_| KlineError::LockPoisoned)?;
+        let old = store.get_string(&key)?.cloned();
+        self.overwrite_locked(&mut store, key, value)?;
+        Ok(old)
+    }
+
+    /// Appends `data` to the value at `key`, creating it if missing, and
+    /// returns the new length. Only the appended bytes are logged.
+    pub fn append(&self, key: &[u8], data: &[u8]) -> Result<usize> {
+        let mut store = self.store.write().map_err(|_| KlineError::LockPoisoned)?;
+        let new_len = store.get_string(key)?.map_or(0, |v| v.len()) + data.len();
+        self.check_sizes(key, new_len)?;
+        store.check_limits(&self.config.limits, key, new_len)?;
+
+        let key_b64 = general_purpose::STANDARD.encode(key);
+        let data_b64 = general_purpose::STANDARD.encode(data);
+        self.append_log(&mut store, &format!("append {} {}", key_b64, data_b64))?;
+
+        let length = store.modify_string(key, |value| {
+            value.extend_from_slice(data);
+            value.len()
+        }).expect("type checked above");
+        self.string_changed(&mut store, key);
+        Ok(length)
+    }
+
+    /// Overwrites the value at `key` starting at `offset`, zero-padding it if
+    /// it is shorter, and returns the new length. Only the written bytes are
+    /// logged.
+    pub fn set_range(&self, key: &[u8], offset: usize, data: &[u8]) -> Result<usize> {
+        let mut store = self.store.write().map_err(|_| KlineError::LockPoisoned)?;
+        let current_len = store.get_string(key)?.map_or(0, |v| v.len());
+        let new_len = current_len.max(offset.saturating_add(data.len()));
+        self.check_sizes(key, new_len)?;
+        store.check_limits(&self.config.limits, key, new_len)?;
+
+        let key_b64 = general_purpose::STANDARD.encode(key);
+        let data_b64 = general_purpose::STANDARD.encode(data);
+        self.append_log(&mut store, &format!("setrange {} {} {}", key_b64, offset, data_b64))?;
+
+        let length = store.modify_string(key, |value| {
+            write_at(value, offset, data);
+            value.len()
+        }).expect("type checked above");
+        self.string_changed(&mut store, key);
+        Ok(length)
+    }
+
+    /// Returns the bytes of the value at `key` between `start` and `end`,
+    /// both inclusive. Negative offsets count from the end of the value.
+    pub fn get_range(&self, key: &[u8], start: i64, end: i64) -> Result<Option<Vec<u8>>> {
+        let store = self.store.read().map_err(|_| KlineError::LockPoisoned)?;
+        Ok(store.get_string(key)?.map(|value| {
+            let len = value.len() as i64;
+            let start = if start < 0 { (len + start).max(0) } else { start };
+            let end = if end < 0 { len + end } else { end.min(len - 1) };
+            if start > end || start >= len {
+                Vec::new()
+            } else {
+                value[start as usize..=end as usize].to_vec()
+            }
+        }))
+    }
+
+    pub fn strlen(&self, key: &[u8]) -> Result<usize> {
+        let store = self.store.read().map_err(|_| KlineError::LockPoisoned)?;
+        Ok(store.get_string(key)?.map_or(0, |v| v.len()))
+    }
+
+    /// Returns the type name of the value at `key`, if it exists.
+    pub fn key_type(&self, key: &[u8]) -> Result<Option<&'static str>> {
+        let store = self.store.read().map_err(|_| KlineError::LockPoisoned)?;
+        Ok(store.get(key).map(Value::type_name))
     }
 
 
      pub fn delete(&self, key: &[u8]) -> Result<()> {
         let key_b64 = general_purpose::STANDARD.encode(key);
         
-        {
-            let mut log = self.log.lock().map_err(|_| KlineError::LockPoisoned)?;
-            writeln!(log, "delete {}", key_b64)?;
-            log.flush()?;
-        }
-        
         let mut store = self.store.write().map_err(|_| KlineError::LockPoisoned)?;
-        store.remove(key);
+        self.append_log(&mut store, &format!("delete {}", key_b64))?;
+        
+        if store.remove(key).is_some() {
+            self.string_changed(&mut store, key);
+        }
         Ok(())
     }
 
     pub fn compact(&mut self) -> Result<()> {
-        let temp_path = format!("{}{}", DEFAULT_DB_FILE, TEMP_FILE_SUFFIX);
-        let mut temp_file = File::create(&temp_path)?;
+        Self::compact_into(&self.store, &self.wal, &self.config)
+    }
 
-        let store = self.store.read().map_err(|_| KlineError::LockPoisoned)?;
-        for (key, value) in store.iter() {
-            let key_b64 = general_purpose::STANDARD.encode(key);
-            let value_b64 = general_purpose::STANDARD.encode(value);
-            writeln!(temp_file, "put {} {}", key_b64, value_b64)?;
+    /// Rewrites the log as one record per live key. The replaced log is kept
+    /// as a segment so `changes_since` can still read past the checkpoint.
+    /// Does nothing if there have been no writes since the last compaction.
+    fn compact_into(store: &RwLock<Store>, wal: &Wal, config: &KlineConfig) -> Result<()> {
+        let store = store.read().map_err(|_| KlineError::LockPoisoned)?;
+        if !wal.has_changes() {
+            return Ok(());
         }
 
-        std::fs::rename(&temp_path, DEFAULT_DB_FILE)?;
-        let new_log = OpenOptions::new().append(true).open(DEFAULT_DB_FILE)?;
-        self.log = Arc::new(Mutex::new(new_log));
-        Ok(())
+        wal.rotate(|file| {
+            let now = now_ms();
+            for record in store.leases().snapshot_records(now) {
+                writeln!(file, "{}", record)?;
+            }
+            for record in store.history().snapshot_records(&config.history, now) {
+                writeln!(file, "{}", record)?;
+            }
+            for (key, value) in store.iter() {
+                for record in snapshot_records(key, value) {
+                    writeln!(file, "{}", record)?;
+                }
+                if let Some(meta) = store.meta(key) {
+                    writeln!(file, "{}", meta_record(key, meta))?;
+                    if let Some(expires_at) = meta.expires_at {
+                        writeln!(file, "expire {} {}", encode(key), expires_at)?;
+                    }
+                }
+            }
+            Ok(())
+        })
+    }
+
+    /// Deletes every key whose TTL has passed, as one logged record, and
+    /// publishes an `Expire` event for each. Runs every
+    /// `ttl.cleanup_interval_secs`; expired keys are hidden from reads
+    /// before then. Expired leases are dropped in the same sweep.
+    pub fn expire_due(&self) -> Result<usize> {
+        Self::expire_into(&self.store, &self.wal, &self.changes, &self.config)
+    }
+
+    fn expire_into(store: &RwLock<Store>, wal: &Wal, changes: &ChangeFeed, config: &KlineConfig) -> Result<usize> {
+        let mut store = store.write().map_err(|_| KlineError::LockPoisoned)?;
+        let now = now_ms();
+        // Expired leases need no record: replay already treats them as gone.
+        store.leases_mut().remove_expired(now);
+        let keys = store.expired_keys(now);
+        if keys.is_empty() {
+            return Ok(0);
+        }
+
+        let record = format!("expired {}", encode_all(&keys));
+        let seq = wal.append(&record, now)?;
+        store.set_clock(now);
+        for key in &keys {
+            store.remove(key);
+            store.record_version(&config.history, key, seq, now);
+            changes.publish(key, || ChangeEvent {
+                seq,
+                op: ChangeOp::Expire,
+                key: key.clone(),
+                value: None,
+                timestamp: now,
+            });
+        }
+        Ok(keys.len())
     }
 
     pub fn keys(&self) -> Result<Vec<Vec<u8>>> {
@@ -178,8 +813,156 @@ impl Kline {
     pub fn clear(&mut self) -> Result<()> {
         {
             let mut store = self.store.write().map_err(|_| KlineError::LockPoisoned)?;
+            self.append_log(&mut store, "clear")?;
             store.clear();
         } // store lock is dropped here
         self.compact() // write empty state to disk
     }
 }
+
+/// Keys written by a string record, whose replay may add history versions.
+/// Range, prefix and prefix-rename records name no keys, so theirs are
+/// looked up in `store` before the record is replayed.
+fn string_record_keys(store: &Store, record: &str) -> Vec<Vec<u8>> {
+    let mut parts = record.split(' ');
+    let keys: Vec<&str> = match parts.next() {
+        Some("put" | "overwrite" | "setex" | "delete" | "append" | "setrange") => parts.take(1).collect(),
+        Some("rename" | "copy") => parts.take(2).collect(),
+        Some("mput") => parts.step_by(2).collect(),
+        Some("mdelete" | "expired") => parts.collect(),
+        Some("delrange") => {
+            return match (parts.next().and_then(decode), parts.next().and_then(decode)) {
+                (Some(start), Some(end)) => keys_in_range(store, &start, &end),
+                _ => Vec::new(),
+            };
+        }
+        Some("delprefix") => {
+            return match parts.next().and_then(decode) {
+                Some(prefix) => keys_with_prefix(store, &prefix),
+                None => Vec::new(),
+            };
+        }
+        Some("renameprefix") => {
+            return match (parts.next().and_then(decode), parts.next().and_then(decode)) {
+                (Some(from), Some(to)) => prefix_renames(store, &from, &to)
+                    .into_iter()
+                    .flat_map(|(old, new)| [old, new])
+                    .collect(),
+                _ => Vec::new(),
+            };
+        }
+        _ => Vec::new(),
+    };
+    keys.into_iter().filter_map(decode).collect()
+}
+
+/// Builds the log records that recreate `value` under `key`.
+fn snapshot_records(key: &[u8], value: &Value) -> Vec<String> {
+    let key_b64 = encode(key);
+    match value {
+        Value::String(bytes) => vec![format!("put {} {}", key_b64, encode(bytes))],
+        Value::List(items) => vec![format!("rpush {} {}", key_b64, encode_all(items))],
+        Value::Hash(fields) => vec![format!("hset {} {}", key_b64, encode_pairs(fields))],
+        Value::Set(members) => vec![format!("sadd {} {}", key_b64, encode_all(members))],
+        Value::SortedSet(zset) => {
+            let pairs: Vec<String> = zset
+                .iter()
+                .map(|(member, score)| format!("{} {}", score, encode(member)))
+                .collect();
+            vec![format!("zadd {} {}", key_b64, pairs.join(" "))]
+        }
+        Value::Stream(stream) => {
+            let mut records = vec![format!("xsetid {} {}", key_b64, stream.last_id)];
+            for (id, fields) in &stream.entries {
+                records.push(format!("xadd {} {} {}", key_b64, id, encode_pairs(fields.iter().map(|(f, v)| (f, v)))));
+            }
+            for (name, group) in &stream.groups {
+                let group_b64 = encode(name.as_bytes());
+                records.push(format!("xgroup {} {} {}", key_b64, group_b64, group.last_delivered));
+                for (id, pending) in &group.pending {
+                    records.push(format!(
+                        "xpending {} {} {} {} {} {}",
+                        key_b64,
+                        group_b64,
+                        encode(pending.consumer.as_bytes()),
+                        id,
+                        pending.delivered_ms,
+                        pending.delivery_count
+                    ));
+                }
+            }
+            records
+        }
+    }
+}
+
+pub(super) fn encode(bytes: &[u8]) -> String {
+    general_purpose::STANDARD.encode(bytes)
+}
+
+/// Encodes each item and joins them with spaces, as used by multi-value
+/// records.
+pub(super) fn encode_all<'a>(items: impl IntoIterator<Item = &'a Vec<u8>>) -> String {
+    items.into_iter().map(|item| encode(item)).collect::<Vec<_>>().join(" ")
+}
+
+/// Encodes field/value pairs as `field value field value ...`.
+pub(super) fn encode_pairs<'a>(pairs: impl IntoIterator<Item = (&'a Vec<u8>, &'a Vec<u8>)>) -> String {
+    pairs
+        .into_iter()
+        .map(|(field, value)| format!("{} {}", encode(field), encode(value)))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+pub(super) fn decode(b64: &str) -> Option<Vec<u8>> {
+    general_purpose::STANDARD.decode(b64).ok()
+}
+
+fn decode_all(items: &[&str]) -> Option<Vec<Vec<u8>>> {
+    items.iter().map(|item| decode(item)).collect()
+}
+
+fn decode_string(b64: &str) -> Option<String> {
+    String::from_utf8(decode(b64)?).ok()
+}
+
+fn decode_pairs(items: &[&str]) -> Option<Fields> {
+    if !items.len().is_multiple_of(2) {
+        return None;
+    }
+    items
+        .chunks(2)
+        .map(|pair| Some((decode(pair[0])?, decode(pair[1])?)))
+        .collect()
+}
+
+fn parse_ids(ids: &[&str]) -> Option<Vec<StreamId>> {
+    ids.iter().map(|id| id.parse().ok()).collect()
+}
+
+pub(super) fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis() as u64)
+}
+
+/// The instant `timeout` from now, for blocking reads; fails if that is
+/// too far in the future to represent.
+pub(super) fn deadline(timeout: Duration) -> Result<tokio::time::Instant> {
+    tokio::time::Instant::now()
+        .checked_add(timeout)
+        .ok_or(KlineError::InvalidTimeout { millis: timeout.as_millis() })
+}
+
+pub(super) fn key_string(key: &[u8]) -> String {
+    String::from_utf8_lossy(key).to_string()
+}
+
+fn write_at(value: &mut Vec<u8>, offset: usize, data: &[u8]) {
+    let end = offset + data.len();
+    if value.len() < end {
+        value.resize(end, 0);
+    }
+    value[offset..end].copy_from_slice(data);
+}
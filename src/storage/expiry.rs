@@ -0,0 +1,88 @@
+use std::time::Duration;
+use crate::error::{KlineError, Result};
+use super::engine::{encode, key_string, now_ms, Kline};
+use super::meta::{check_tags, encode_meta, MetaUpdate};
+use super::value::Value;
+
+/// Key expiry. An expired key reads as missing straight away and is
+/// deleted, with a logged record, by the next cleanup sweep.
+impl Kline {
+    /// Stores `value` under `key` to expire `ttl` from now, setting the
+    /// content type and tags given in `meta`.
+    pub fn put_with_ttl(&self, key: Vec<u8>, value: Vec<u8>, meta: MetaUpdate, ttl: Duration) -> Result<()> {
+        self.check_ttl(ttl)?;
+        self.check_sizes(&key, value.len())?;
+        if let Some(tags) = &meta.tags {
+            check_tags(tags)?;
+        }
+
+        let mut store = self.store.write().map_err(|_| KlineError::LockPoisoned)?;
+        store.check_type(&key, "string")?;
+        store.check_limits(&self.config.limits, &key, value.len())?;
+        let meta = (!meta.is_empty()).then(|| meta.apply(store.meta(&key)));
+        let expires_at = now_ms() + ttl.as_millis() as u64;
+        let record = match &meta {
+            Some((content_type, tags)) => format!("setex {} {} {} {}", encode(&key), expires_at, encode(&value), encode_meta(content_type, tags)),
+            None => format!("setex {} {} {}", encode(&key), expires_at, encode(&value)),
+        };
+        self.append_log(&mut store, &record)?;
+
+        store.insert(key.clone(), Value::String(value));
+        if let Some(stored) = store.meta_mut(&key) {
+            stored.expires_at = Some(expires_at);
+            if let Some((content_type, tags)) = meta {
+                stored.content_type = content_type;
+                stored.tags = tags;
+            }
+        }
+        self.string_changed(&mut store, &key);
+        Ok(())
+    }
+
+    /// Sets `key` to expire `ttl` from now. Returns `false` if it does not
+    /// exist.
+    pub fn expire(&self, key: &[u8], ttl: Duration) -> Result<bool> {
+        self.check_ttl(ttl)?;
+        let mut store = self.store.write().map_err(|_| KlineError::LockPoisoned)?;
+        if store.meta(key).is_none() {
+            return Ok(false);
+        }
+        let expires_at = now_ms() + ttl.as_millis() as u64;
+        self.append_log(&mut store, &format!("expire {} {}", encode(key), expires_at))?;
+        if let Some(meta) = store.meta_mut(key) {
+            meta.expires_at = Some(expires_at);
+        }
+        Ok(true)
+    }
+
+    /// Removes the TTL from `key`. Returns `false` if it does not exist or
+    /// had no TTL.
+    pub fn persist(&self, key: &[u8]) -> Result<bool> {
+        let mut store = self.store.write().map_err(|_| KlineError::LockPoisoned)?;
+        if store.meta(key).is_none_or(|meta| meta.expires_at.is_none()) {
+            return Ok(false);
+        }
+        self.append_log(&mut store, &format!("persist {}", encode(key)))?;
+        if let Some(meta) = store.meta_mut(key) {
+            meta.expires_at = None;
+        }
+        Ok(true)
+    }
+
+    /// Time left before `key` expires, or `None` if it has no TTL.
+    pub fn ttl(&self, key: &[u8]) -> Result<Option<Duration>> {
+        let store = self.store.read().map_err(|_| KlineError::LockPoisoned)?;
+        match store.meta(key) {
+            Some(meta) => Ok(meta.ttl()),
+            None => Err(KlineError::KeyNotFound { key: key_string(key) }),
+        }
+    }
+
+    /// Rejects a zero TTL or one longer than `ttl.max_ttl_secs`.
+    pub(super) fn check_ttl(&self, ttl: Duration) -> Result<()> {
+        if ttl.is_zero() || ttl.as_secs() > self.config.ttl.max_ttl_secs {
+            return Err(KlineError::InvalidTtl { ttl: ttl.as_secs() });
+        }
+        Ok(())
+    }
+}
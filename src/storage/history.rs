@@ -0,0 +1,133 @@
+use std::collections::{HashMap, VecDeque};
+use crate::config::{HistoryConfig, RetentionPolicy};
+use crate::error::{KlineError, Result};
+use super::engine::{encode, Kline};
+
+/// One retained version of a string key.
+#[derive(Debug, Clone)]
+pub struct Version {
+    /// Sequence number of the log record that wrote this version.
+    pub version: u64,
+    /// Milliseconds since the Unix epoch.
+    pub timestamp: u64,
+    /// `None` if the key was deleted.
+    pub value: Option<Vec<u8>>,
+}
+
+/// Point in a key's history to read with `Kline::get_at`.
+#[derive(Debug, Clone, Copy)]
+pub enum At {
+    /// The version current as of this sequence number.
+    Version(u64),
+    /// The version current at this time, in milliseconds since the epoch.
+    Timestamp(u64),
+}
+
+/// Retained versions per key, oldest first, for keys under a prefix with a
+/// `RetentionPolicy`.
+#[derive(Default)]
+pub struct History {
+    keys: HashMap<Vec<u8>, VecDeque<Version>>,
+}
+
+impl History {
+    /// Records `version` for `key` if its prefix has a retention policy, then
+    /// drops versions the policy no longer covers as of `version.timestamp`.
+    pub fn record(&mut self, config: &HistoryConfig, key: &[u8], version: Version) {
+        let Some(policy) = config.policy_for(key) else {
+            return;
+        };
+        let now = version.timestamp;
+        let versions = self.keys.entry(key.to_vec()).or_default();
+        // A delete only counts if there was something to delete.
+        if version.value.is_none() && versions.back().is_none_or(|last| last.value.is_none()) {
+            if versions.is_empty() {
+                self.keys.remove(key);
+            }
+            return;
+        }
+        versions.push_back(version);
+        prune(versions, policy, now);
+    }
+
+    /// Inserts a version read back from a snapshot record.
+    pub fn restore(&mut self, key: Vec<u8>, version: Version) {
+        self.keys.entry(key).or_default().push_back(version);
+    }
+
+    pub fn versions(&self, key: &[u8]) -> Vec<Version> {
+        self.keys.get(key).map_or_else(Vec::new, |versions| versions.iter().cloned().collect())
+    }
+
+    pub fn get_at(&self, key: &[u8], at: At) -> Option<Version> {
+        self.keys
+            .get(key)?
+            .iter()
+            .rev()
+            .find(|version| match at {
+                At::Version(seq) => version.version <= seq,
+                At::Timestamp(ms) => version.timestamp <= ms,
+            })
+            .cloned()
+    }
+
+    pub fn clear(&mut self) {
+        self.keys.clear();
+    }
+
+    /// Log records that recreate the history still covered by `config` at
+    /// `now`. Keys whose prefix lost its policy are dropped.
+    pub fn snapshot_records(&self, config: &HistoryConfig, now: u64) -> Vec<String> {
+        let mut records = Vec::new();
+        for (key, versions) in &self.keys {
+            let Some(policy) = config.policy_for(key) else {
+                continue;
+            };
+            for version in versions.iter().skip(expired(versions, policy, now)) {
+                let value = version.value.as_deref().map_or_else(|| "-".to_string(), encode);
+                records.push(format!("version {} {} {} {}", encode(key), version.version, version.timestamp, value));
+            }
+        }
+        records
+    }
+}
+
+fn prune(versions: &mut VecDeque<Version>, policy: &RetentionPolicy, now: u64) {
+    let expired = expired(versions, policy, now);
+    versions.drain(..expired);
+}
+
+/// Number of leading versions the policy no longer covers: those beyond its
+/// count and those already superseded before its age cutoff. The newest
+/// version is always kept.
+fn expired(versions: &VecDeque<Version>, policy: &RetentionPolicy, now: u64) -> usize {
+    let mut expired = match policy.versions {
+        Some(max) => versions.len().saturating_sub(max.max(1)),
+        None => 0,
+    };
+    if let Some(max_age_secs) = policy.max_age_secs {
+        let cutoff = now.saturating_sub(max_age_secs * 1000);
+        while expired + 1 < versions.len() && versions[expired + 1].timestamp < cutoff {
+            expired += 1;
+        }
+    }
+    expired
+}
+
+/// Version history commands
+impl Kline {
+    /// Retained versions of the string at `key`, oldest first. Empty unless
+    /// a `[history.retention]` policy covers the key.
+    pub fn history(&self, key: &[u8]) -> Result<Vec<Version>> {
+        let store = self.store.read().map_err(|_| KlineError::LockPoisoned)?;
+        Ok(store.history().versions(key))
+    }
+
+    /// The version of `key` that was current at `at`, if it is still
+    /// retained. A version whose `value` is `None` means the key did not
+    /// exist at that point.
+    pub fn get_at(&self, key: &[u8], at: At) -> Result<Option<Version>> {
+        let store = self.store.read().map_err(|_| KlineError::LockPoisoned)?;
+        Ok(store.history().get_at(key, at))
+    }
+}
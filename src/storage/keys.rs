@@ -0,0 +1,207 @@
+use std::collections::BinaryHeap;
+use crate::error::{KlineError, Result};
+use super::engine::{encode, key_string, Kline};
+use super::glob;
+use super::store::Store;
+use super::value::Value;
+
+/// Which keys a listing includes, and where a page of it starts.
+#[derive(Debug, Clone, Default)]
+pub struct KeyScan {
+    /// Only keys starting with this prefix.
+    pub prefix: Vec<u8>,
+    /// Only keys matching this glob, where `*` matches any run of bytes,
+    /// `?` one byte and `\` escapes the next byte.
+    pub pattern: Option<Vec<u8>>,
+    /// Only keys ordered after this one: the `next` cursor of the previous
+    /// page.
+    pub after: Option<Vec<u8>>,
+}
+
+impl KeyScan {
+    fn matches(&self, key: &[u8]) -> bool {
+        key.starts_with(&self.prefix)
+            && self.after.as_deref().is_none_or(|after| key > after)
+            && self.pattern.as_deref().is_none_or(|pattern| glob::matches(pattern, key))
+    }
+}
+
+/// One page of a key listing in ascending byte order. Each value is set
+/// only when values were requested and the key holds a string.
+#[derive(Debug, Clone)]
+pub struct KeyPage {
+    pub keys: Vec<(Vec<u8>, Option<Vec<u8>>)>,
+    /// Where the next page starts, or `None` if this is the last one.
+    pub next: Option<Vec<u8>>,
+}
+
+/// The `(from, to)` pairs that moving every key under `from` to `to`
+/// produces, in key order.
+pub(super) fn prefix_renames(store: &Store, from: &[u8], to: &[u8]) -> Vec<(Vec<u8>, Vec<u8>)> {
+    let mut renames: Vec<(Vec<u8>, Vec<u8>)> = store
+        .keys()
+        .filter(|key| key.starts_with(from))
+        .map(|key| (key.clone(), [to, &key[from.len()..]].concat()))
+        .collect();
+    renames.sort();
+    renames
+}
+
+/// Keys in `[start, end)`, or every key from `start` on if `end` is empty.
+pub(super) fn keys_in_range(store: &Store, start: &[u8], end: &[u8]) -> Vec<Vec<u8>> {
+    store
+        .keys()
+        .filter(|key| key.as_slice() >= start && (end.is_empty() || key.as_slice() < end))
+        .cloned()
+        .collect()
+}
+
+pub(super) fn keys_with_prefix(store: &Store, prefix: &[u8]) -> Vec<Vec<u8>> {
+    store.keys().filter(|key| key.starts_with(prefix)).cloned().collect()
+}
+
+/// Whole-key operations. Each is logged as one record and applied under a
+/// single write lock, so no reader sees a half-finished move.
+impl Kline {
+    /// Moves the value at `old` to `new`, keeping its metadata. Fails with
+    /// `KeyExists` if `new` exists and `overwrite` is false.
+    pub fn rename(&self, old: &[u8], new: &[u8], overwrite: bool) -> Result<()> {
+        self.check_sizes(new, 0)?;
+        let mut store = self.store.write().map_err(|_| KlineError::LockPoisoned)?;
+        if store.get(old).is_none() {
+            return Err(KlineError::KeyNotFound { key: key_string(old) });
+        }
+        if old == new {
+            return Ok(());
+        }
+        if !overwrite && store.get(new).is_some() {
+            return Err(KlineError::KeyExists { key: key_string(new) });
+        }
+        let renames = [(old.to_vec(), new.to_vec())];
+        store.check_renames(&self.config.limits, &renames)?;
+
+        self.append_log(&mut store, &format!("rename {} {}", encode(old), encode(new)))?;
+
+        store.rename(&renames);
+        self.string_changed(&mut store, old);
+        self.string_changed(&mut store, new);
+        Ok(())
+    }
+
+    /// Copies the value at `src` to `dst`, which must not exist yet. The copy
+    /// gets the source's content type and tags.
+    pub fn copy(&self, src: &[u8], dst: &[u8]) -> Result<()> {
+        let mut store = self.store.write().map_err(|_| KlineError::LockPoisoned)?;
+        if store.get(src).is_none() {
+            return Err(KlineError::KeyNotFound { key: key_string(src) });
+        }
+        if store.get(dst).is_some() {
+            return Err(KlineError::KeyExists { key: key_string(dst) });
+        }
+        let size = store.size_of(src);
+        self.check_sizes(dst, size)?;
+        store.check_limits(&self.config.limits, dst, size)?;
+
+        self.append_log(&mut store, &format!("copy {} {}", encode(src), encode(dst)))?;
+
+        store.copy(src, dst);
+        self.string_changed(&mut store, dst);
+        Ok(())
+    }
+
+    /// Moves every key starting with `from` to the same key starting with
+    /// `to` instead, and returns how many were moved. Fails with `KeyExists`,
+    /// moving nothing, if a target key exists and is not itself being moved.
+    pub fn rename_prefix(&self, from: &[u8], to: &[u8]) -> Result<usize> {
+        let mut store = self.store.write().map_err(|_| KlineError::LockPoisoned)?;
+        let renames = prefix_renames(&store, from, to);
+        if renames.is_empty() || from == to {
+            return Ok(0);
+        }
+        for (_, new) in &renames {
+            self.check_sizes(new, 0)?;
+            if store.get(new).is_some() && !new.starts_with(from) {
+                return Err(KlineError::KeyExists { key: key_string(new) });
+            }
+        }
+        store.check_renames(&self.config.limits, &renames)?;
+
+        self.append_log(&mut store, &format!("renameprefix {} {}", encode(from), encode(to)))?;
+
+        store.rename(&renames);
+        for (old, new) in &renames {
+            self.string_changed(&mut store, old);
+            self.string_changed(&mut store, new);
+        }
+        Ok(renames.len())
+    }
+
+    /// Deletes every key in `[start, end)` in byte order (to the last key
+    /// if `end` is empty) and returns how many were removed. Logged as one
+    /// range tombstone rather than one record per key.
+    pub fn delete_range(&self, start: &[u8], end: &[u8]) -> Result<usize> {
+        let mut store = self.store.write().map_err(|_| KlineError::LockPoisoned)?;
+        let keys = keys_in_range(&store, start, end);
+        self.delete_keys(&mut store, &keys, &format!("delrange {} {}", encode(start), encode(end)))
+    }
+
+    /// Deletes every key starting with `prefix` and returns how many were
+    /// removed. Logged as one tombstone record.
+    pub fn delete_prefix(&self, prefix: &[u8]) -> Result<usize> {
+        let mut store = self.store.write().map_err(|_| KlineError::LockPoisoned)?;
+        let keys = keys_with_prefix(&store, prefix);
+        self.delete_keys(&mut store, &keys, &format!("delprefix {}", encode(prefix)))
+    }
+
+    fn delete_keys(&self, store: &mut Store, keys: &[Vec<u8>], record: &str) -> Result<usize> {
+        if keys.is_empty() {
+            return Ok(0);
+        }
+        self.append_log(store, record)?;
+        for key in keys {
+            store.remove(key);
+            self.string_changed(store, key);
+        }
+        Ok(keys.len())
+    }
+
+    /// Lists up to `limit` keys matching `scan`, smallest first. Pages
+    /// resume after the last key returned rather than at an offset, so keys
+    /// written or deleted between pages never shift or repeat the rest.
+    pub fn scan_keys(&self, scan: &KeyScan, limit: usize, values: bool) -> Result<KeyPage> {
+        let store = self.store.read().map_err(|_| KlineError::LockPoisoned)?;
+        // Keeps the `limit + 1` smallest matches; the extra one shows
+        // whether another page follows.
+        let mut smallest: BinaryHeap<&Vec<u8>> = BinaryHeap::with_capacity(limit + 1);
+        for key in store.keys().filter(|key| scan.matches(key)) {
+            if smallest.len() <= limit {
+                smallest.push(key);
+            } else if smallest.peek().is_some_and(|largest| key < *largest) {
+                smallest.pop();
+                smallest.push(key);
+            }
+        }
+        let more = smallest.len() > limit;
+        let mut keys = smallest.into_sorted_vec();
+        keys.truncate(limit);
+
+        let next = if more { keys.last().map(|key| key.to_vec()) } else { None };
+        let keys = keys
+            .into_iter()
+            .map(|key| {
+                let value = match store.get(key) {
+                    Some(Value::String(value)) if values => Some(value.clone()),
+                    _ => None,
+                };
+                (key.clone(), value)
+            })
+            .collect();
+        Ok(KeyPage { keys, next })
+    }
+
+    /// Counts the keys matching `scan` without listing them.
+    pub fn count_keys(&self, scan: &KeyScan) -> Result<usize> {
+        let store = self.store.read().map_err(|_| KlineError::LockPoisoned)?;
+        Ok(store.keys().filter(|key| scan.matches(key)).count())
+    }
+}
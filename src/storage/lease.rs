@@ -0,0 +1,177 @@
+use std::collections::HashMap;
+use std::time::Duration;
+use crate::error::{KlineError, Result};
+use super::engine::{deadline, encode, now_ms, Kline};
+
+/// A named lease held by one owner until `expires_at`.
+#[derive(Debug, Clone)]
+pub struct Lease {
+    pub name: String,
+    pub owner: String,
+    /// Fencing token, strictly greater than that of every earlier
+    /// acquisition of any lease.
+    pub token: u64,
+    /// Milliseconds since the Unix epoch.
+    pub expires_at: u64,
+}
+
+impl Lease {
+    pub fn is_expired(&self, now_ms: u64) -> bool {
+        self.expires_at <= now_ms
+    }
+}
+
+/// Lease table kept alongside the key space. An expired lease reads as
+/// released straight away and is dropped by the next TTL cleanup sweep.
+#[derive(Default)]
+pub struct Leases {
+    active: HashMap<String, Lease>,
+    last_token: u64,
+}
+
+impl Leases {
+    /// The live lease called `name`, if any.
+    pub fn get(&self, name: &str, now_ms: u64) -> Option<&Lease> {
+        self.active.get(name).filter(|lease| !lease.is_expired(now_ms))
+    }
+
+    pub fn insert(&mut self, lease: Lease) {
+        self.last_token = self.last_token.max(lease.token);
+        self.active.insert(lease.name.clone(), lease);
+    }
+
+    pub fn remove(&mut self, name: &str) {
+        self.active.remove(name);
+    }
+
+    /// Drops every lease expired by `now_ms` and returns how many there were.
+    pub fn remove_expired(&mut self, now_ms: u64) -> usize {
+        let before = self.active.len();
+        self.active.retain(|_, lease| !lease.is_expired(now_ms));
+        before - self.active.len()
+    }
+
+    pub fn next_token(&self) -> u64 {
+        self.last_token + 1
+    }
+
+    /// Raises the token counter to at least `token`.
+    pub fn advance_token(&mut self, token: u64) {
+        self.last_token = self.last_token.max(token);
+    }
+
+    /// Log records that recreate the token counter and unexpired leases.
+    pub fn snapshot_records(&self, now_ms: u64) -> Vec<String> {
+        let mut records = Vec::new();
+        if self.last_token > 0 {
+            records.push(format!("fence {}", self.last_token));
+        }
+        for lease in self.active.values().filter(|lease| !lease.is_expired(now_ms)) {
+            records.push(lease_record(lease));
+        }
+        records
+    }
+}
+
+pub(super) fn lease_record(lease: &Lease) -> String {
+    format!(
+        "lease {} {} {} {}",
+        encode(lease.name.as_bytes()),
+        encode(lease.owner.as_bytes()),
+        lease.token,
+        lease.expires_at
+    )
+}
+
+/// Lease commands
+impl Kline {
+    /// Acquires the lease `name` for `owner` for `ttl`, or returns `None` if
+    /// another owner holds it. An owner re-acquiring a lease it still holds
+    /// keeps its token and gets a new expiry.
+    pub fn acquire_lease(&self, name: &str, owner: &str, ttl: Duration) -> Result<Option<Lease>> {
+        self.check_ttl(ttl)?;
+        let mut store = self.store.write().map_err(|_| KlineError::LockPoisoned)?;
+        let now = now_ms();
+        let token = match store.leases().get(name, now) {
+            Some(lease) if lease.owner != owner => return Ok(None),
+            Some(lease) => lease.token,
+            None => store.leases().next_token(),
+        };
+
+        let lease = Lease {
+            name: name.to_string(),
+            owner: owner.to_string(),
+            token,
+            expires_at: now + ttl.as_millis() as u64,
+        };
+        self.append_log(&mut store, &lease_record(&lease))?;
+        store.leases_mut().insert(lease.clone());
+        Ok(Some(lease))
+    }
+
+    /// Like `acquire_lease`, but waits up to `timeout` for the current holder
+    /// to release the lease or let it expire.
+    pub async fn acquire_lease_blocking(&self, name: &str, owner: &str, ttl: Duration, timeout: Duration) -> Result<Option<Lease>> {
+        let deadline = deadline(timeout)?;
+        let notify = self.lease_waiters.register(&[name.as_bytes()]);
+        loop {
+            if let Some(lease) = self.acquire_lease(name, owner, ttl)? {
+                return Ok(Some(lease));
+            }
+
+            // Nothing is notified when a lease expires, so also wake up when
+            // the current one is due to.
+            let wake_at = match self.lease(name)? {
+                Some(held) => {
+                    let remaining = Duration::from_millis(held.expires_at.saturating_sub(now_ms()));
+                    deadline.min(tokio::time::Instant::now() + remaining)
+                }
+                None => tokio::time::Instant::now(),
+            };
+            let _ = tokio::time::timeout_at(wake_at, notify.notified()).await;
+            if tokio::time::Instant::now() >= deadline {
+                return self.acquire_lease(name, owner, ttl);
+            }
+        }
+    }
+
+    /// Extends a lease held by `owner` to expire `ttl` from now.
+    pub fn renew_lease(&self, name: &str, owner: &str, ttl: Duration) -> Result<Lease> {
+        self.check_ttl(ttl)?;
+        let mut store = self.store.write().map_err(|_| KlineError::LockPoisoned)?;
+        let now = now_ms();
+        let mut lease = self.owned_lease(store.leases(), name, owner, now)?;
+        lease.expires_at = now + ttl.as_millis() as u64;
+
+        self.append_log(&mut store, &lease_record(&lease))?;
+        store.leases_mut().insert(lease.clone());
+        Ok(lease)
+    }
+
+    /// Releases a lease held by `owner`, waking anyone waiting for it.
+    pub fn release_lease(&self, name: &str, owner: &str) -> Result<()> {
+        let mut store = self.store.write().map_err(|_| KlineError::LockPoisoned)?;
+        self.owned_lease(store.leases(), name, owner, now_ms())?;
+
+        self.append_log(&mut store, &format!("unlease {}", encode(name.as_bytes())))?;
+        store.leases_mut().remove(name);
+        self.lease_waiters.wake(name.as_bytes());
+        Ok(())
+    }
+
+    /// The current holder of the lease `name`, if it is held.
+    pub fn lease(&self, name: &str) -> Result<Option<Lease>> {
+        let store = self.store.read().map_err(|_| KlineError::LockPoisoned)?;
+        Ok(store.leases().get(name, now_ms()).cloned())
+    }
+
+    fn owned_lease(&self, leases: &Leases, name: &str, owner: &str, now: u64) -> Result<Lease> {
+        match leases.get(name, now) {
+            Some(lease) if lease.owner == owner => Ok(lease.clone()),
+            _ => Err(KlineError::LeaseNotHeld {
+                name: name.to_string(),
+                owner: owner.to_string(),
+            }),
+        }
+    }
+}
@@ -0,0 +1,165 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+use crate::constants::storage::MAX_TAGS_SIZE;
+use crate::error::{KlineError, Result};
+use super::engine::{decode, encode, now_ms, Kline};
+
+/// Free-form string tags attached to a key.
+pub type Tags = BTreeMap<String, String>;
+
+/// Bookkeeping stored with every key and persisted in the log.
+#[derive(Debug, Clone, Default)]
+pub struct Metadata {
+    /// Milliseconds since the Unix epoch.
+    pub created_at: u64,
+    /// Milliseconds since the Unix epoch.
+    pub updated_at: u64,
+    /// MIME type the value was stored with, if the writer gave one.
+    pub content_type: Option<String>,
+    pub tags: Tags,
+    /// When the key expires, in milliseconds since the epoch, if it has a
+    /// TTL.
+    pub expires_at: Option<u64>,
+}
+
+impl Metadata {
+    pub fn new(now: u64) -> Self {
+        Self {
+            created_at: now,
+            updated_at: now,
+            ..Self::default()
+        }
+    }
+
+    /// Time left before the key expires, if it has a TTL.
+    pub fn ttl(&self) -> Option<Duration> {
+        self.expires_at.map(|at| Duration::from_millis(at.saturating_sub(now_ms())))
+    }
+}
+
+/// Content type and tags given with a write. A field left as `None` keeps
+/// what the key already has.
+#[derive(Debug, Clone, Default)]
+pub struct MetaUpdate {
+    pub content_type: Option<String>,
+    pub tags: Option<Tags>,
+}
+
+impl MetaUpdate {
+    pub fn is_empty(&self) -> bool {
+        self.content_type.is_none() && self.tags.is_none()
+    }
+
+    /// The content type and tags a key ends up with, given what it had.
+    pub(super) fn apply(self, current: Option<&Metadata>) -> (Option<String>, Tags) {
+        let content_type = self.content_type.or_else(|| current.and_then(|meta| meta.content_type.clone()));
+        let tags = self.tags.unwrap_or_else(|| current.map(|meta| meta.tags.clone()).unwrap_or_default());
+        (content_type, tags)
+    }
+}
+
+/// Rejects tags totalling more than `MAX_TAGS_SIZE` bytes.
+pub(super) fn check_tags(tags: &Tags) -> Result<()> {
+    let size: usize = tags.iter().map(|(name, value)| name.len() + value.len()).sum();
+    if size > MAX_TAGS_SIZE {
+        return Err(KlineError::TagsTooLarge { size, max: MAX_TAGS_SIZE });
+    }
+    Ok(())
+}
+
+/// Encodes a content type and tags as `content_type|- tag value tag value ...`.
+pub(super) fn encode_meta(content_type: &Option<String>, tags: &Tags) -> String {
+    let mut parts = vec![content_type.as_deref().map_or_else(|| "-".to_string(), |ct| encode(ct.as_bytes()))];
+    for (name, value) in tags {
+        parts.push(encode(name.as_bytes()));
+        parts.push(encode(value.as_bytes()));
+    }
+    parts.join(" ")
+}
+
+pub(super) fn decode_meta(content_type: &str, tags: &[&str]) -> Option<(Option<String>, Tags)> {
+    let content_type = match content_type {
+        "-" => None,
+        encoded => Some(String::from_utf8(decode(encoded)?).ok()?),
+    };
+    if !tags.len().is_multiple_of(2) {
+        return None;
+    }
+    let tags = tags
+        .chunks(2)
+        .map(|pair| Some((String::from_utf8(decode(pair[0])?).ok()?, String::from_utf8(decode(pair[1])?).ok()?)))
+        .collect::<Option<Tags>>()?;
+    Some((content_type, tags))
+}
+
+/// Snapshot record restoring a key's metadata after its value.
+pub(super) fn meta_record(key: &[u8], meta: &Metadata) -> String {
+    format!(
+        "meta {} {} {} {}",
+        encode(key),
+        meta.created_at,
+        meta.updated_at,
+        encode_meta(&meta.content_type, &meta.tags)
+    )
+}
+
+/// Part of a string value, read by `Kline::get_slice_with_meta`.
+#[derive(Debug, Clone)]
+pub struct Slice {
+    /// Length of the whole value.
+    pub total: usize,
+    /// Start offset and bytes of the slice, if the range was satisfiable.
+    pub range: Option<(usize, Vec<u8>)>,
+    pub meta: Metadata,
+}
+
+/// Key metadata commands
+impl Kline {
+    /// Stores `value` under `key`, setting the content type and tags given
+    /// in `meta` and keeping any the key had that are not.
+    pub fn put_with_meta(&self, key: Vec<u8>, value: Vec<u8>, meta: MetaUpdate) -> Result<()> {
+        self.check_sizes(&key, value.len())?;
+        if let Some(tags) = &meta.tags {
+            check_tags(tags)?;
+        }
+
+        let mut store = self.store.write().map_err(|_| KlineError::LockPoisoned)?;
+        self.put_locked_with_meta(&mut store, key, value, meta)
+    }
+
+    /// Returns the string at `key` together with its metadata.
+    pub fn get_with_meta(&self, key: &[u8]) -> Result<Option<(Vec<u8>, Metadata)>> {
+        let store = self.store.read().map_err(|_| KlineError::LockPoisoned)?;
+        Ok(store
+            .get_string(key)?
+            .cloned()
+            .zip(store.meta(key).cloned()))
+    }
+
+    /// Reads a slice of the string at `key`. `select` is given the value's
+    /// length and returns the inclusive byte range to read, or `None` if
+    /// none applies. The length, the slice and the metadata come from the
+    /// same snapshot of the key.
+    pub fn get_slice_with_meta(
+        &self,
+        key: &[u8],
+        select: impl FnOnce(usize) -> Option<(usize, usize)>,
+    ) -> Result<Option<Slice>> {
+        let store = self.store.read().map_err(|_| KlineError::LockPoisoned)?;
+        let (Some(value), Some(meta)) = (store.get_string(key)?, store.meta(key)) else {
+            return Ok(None);
+        };
+        let range = select(value.len()).filter(|(start, end)| start <= end && *end < value.len());
+        Ok(Some(Slice {
+            total: value.len(),
+            range: range.map(|(start, end)| (start, value[start..=end].to_vec())),
+            meta: meta.clone(),
+        }))
+    }
+
+    /// Returns the metadata of a key of any type.
+    pub fn meta(&self, key: &[u8]) -> Result<Option<Metadata>> {
+        let store = self.store.read().map_err(|_| KlineError::LockPoisoned)?;
+        Ok(store.meta(key).cloned())
+    }
+}
@@ -1,3 +1,27 @@
 pub mod engine;
+mod bulk;
+mod changes;
+mod collections;
+mod expiry;
+mod glob;
+mod history;
+mod keys;
+mod lease;
+mod meta;
+mod pubsub;
+mod store;
+mod stream;
+mod value;
+mod waiters;
+mod wal;
+mod zset;
 
+pub use changes::{ChangeEvent, ChangeMessage, ChangeOp, KeyFilter, Subscription};
 pub use engine::Kline;
+pub use history::{At, Version};
+pub use keys::{KeyPage, KeyScan};
+pub use lease::Lease;
+pub use meta::{MetaUpdate, Metadata, Slice, Tags};
+pub use pubsub::{ChannelFilter, ChannelSubscription, Message};
+pub use stream::{Fields, PendingEntry, StreamId};
+pub use wal::{ChangesSince, WalEntry};
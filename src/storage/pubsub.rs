@@ -0,0 +1,96 @@
+use std::sync::Mutex;
+use tokio::sync::mpsc;
+use crate::constants::pubsub::SUBSCRIBER_BUFFER;
+use super::glob;
+
+/// A channel or glob pattern a pub/sub subscriber listens on.
+#[derive(Debug, Clone)]
+pub enum ChannelFilter {
+    Channel(String),
+    /// Glob pattern: `*` matches any run of characters, `?` exactly one.
+    Pattern(String),
+}
+
+impl ChannelFilter {
+    pub fn matches(&self, channel: &str) -> bool {
+        match self {
+            ChannelFilter::Channel(name) => name == channel,
+            ChannelFilter::Pattern(pattern) => glob::matches(pattern.as_bytes(), channel.as_bytes()),
+        }
+    }
+}
+
+/// A message published to a channel.
+#[derive(Debug, Clone)]
+pub struct Message {
+    pub channel: String,
+    /// The pattern that matched, if the subscriber was not subscribed to
+    /// `channel` by name.
+    pub pattern: Option<String>,
+    pub payload: Vec<u8>,
+}
+
+/// Receiving end of `Kline::subscribe_channels`. Dropping it unsubscribes.
+pub struct ChannelSubscription {
+    receiver: mpsc::Receiver<Message>,
+}
+
+impl ChannelSubscription {
+    /// Waits for the next message; `None` once the database is dropped.
+    pub async fn recv(&mut self) -> Option<Message> {
+        self.receiver.recv().await
+    }
+
+    pub fn try_recv(&mut self) -> Option<Message> {
+        self.receiver.try_recv().ok()
+    }
+}
+
+struct Subscriber {
+    filters: Vec<ChannelFilter>,
+    sender: mpsc::Sender<Message>,
+}
+
+/// Fire-and-forget message broker. Nothing is persisted: a message reaches
+/// only the subscribers connected when it is published, and is dropped for
+/// any subscriber whose buffer is full.
+#[derive(Default)]
+pub struct Broker {
+    subscribers: Mutex<Vec<Subscriber>>,
+}
+
+impl Broker {
+    pub fn subscribe(&self, filters: Vec<ChannelFilter>) -> ChannelSubscription {
+        let (sender, receiver) = mpsc::channel(SUBSCRIBER_BUFFER);
+        let mut subscribers = self.subscribers.lock().unwrap_or_else(|e| e.into_inner());
+        subscribers.push(Subscriber { filters, sender });
+        ChannelSubscription { receiver }
+    }
+
+    /// Delivers `payload` to every subscriber listening on `channel` and
+    /// returns how many received it. A subscriber matching the channel
+    /// through several filters gets the message once.
+    pub fn publish(&self, channel: &str, payload: Vec<u8>) -> usize {
+        let mut subscribers = self.subscribers.lock().unwrap_or_else(|e| e.into_inner());
+        subscribers.retain(|subscriber| !subscriber.sender.is_closed());
+
+        let mut received = 0;
+        for subscriber in subscribers.iter() {
+            let Some(filter) = subscriber.filters.iter().find(|f| f.matches(channel)) else {
+                continue;
+            };
+            let message = Message {
+                channel: channel.to_string(),
+                pattern: match filter {
+                    ChannelFilter::Pattern(pattern) => Some(pattern.clone()),
+                    ChannelFilter::Channel(_) => None,
+                },
+                payload: payload.clone(),
+            };
+            if subscriber.sender.try_send(message).is_ok() {
+                received += 1;
+            }
+        }
+        received
+    }
+}
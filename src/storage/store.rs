@@ -0,0 +1,656 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use crate::config::{HistoryConfig, LimitsConfig};
+use crate::constants::storage::INITIAL_HASHMAP_CAPACITY;
+use crate::error::{KlineError, Result};
+use super::engine::now_ms;
+use super::history::{History, Version};
+use super::lease::Leases;
+use super::meta::Metadata;
+use super::stream::Stream;
+use super::value::Value;
+use super::zset::SortedSet;
+
+struct Entry {
+    value: Value,
+    size: usize,
+    meta: Metadata,
+}
+
+/// In-memory key space plus the accounting needed to enforce `LimitsConfig`
+/// without rescanning the map on every write. Leases live here too so they
+/// are guarded by the same lock as every other logged mutation.
+pub struct Store {
+    data: HashMap<Vec<u8>, Entry>,
+    total_bytes: usize,
+    prefix_counts: HashMap<String, usize>,
+    leases: Leases,
+    history: History,
+    /// Time stamped on entries written next, set from each log record.
+    clock: u64,
+    /// Set while the log is replayed, so that reads judge expiry by the
+    /// record being replayed rather than by the wall clock.
+    replaying: bool,
+}
+
+impl Store {
+    pub fn new(limits: &LimitsConfig) -> Self {
+        Self {
+            data: HashMap::with_capacity(INITIAL_HASHMAP_CAPACITY),
+            total_bytes: 0,
+            prefix_counts: limits
+                .prefix_quotas
+                .keys()
+                .map(|prefix| (prefix.clone(), 0))
+                .collect(),
+            leases: Leases::default(),
+            history: History::default(),
+            clock: 0,
+            replaying: false,
+        }
+    }
+
+    /// Sets the time, in milliseconds since the epoch, that subsequent
+    /// writes record as their created/updated time.
+    pub fn set_clock(&mut self, now: u64) {
+        self.clock = now;
+    }
+
+    pub fn set_replaying(&mut self, replaying: bool) {
+        self.replaying = replaying;
+    }
+
+    /// Whether `entry` has expired as far as reads are concerned. Writes
+    /// instead drop expired entries as of the clock, in `purge_expired`, so
+    /// that replay reaches the same state.
+    fn is_expired(&self, entry: &Entry) -> bool {
+        let now = if self.replaying { self.clock } else { now_ms() };
+        entry.meta.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+
+    /// Drops the entry at `key` if it expired by the current clock. Called
+    /// before every write to a key, so a write never sees an expired value.
+    fn purge_expired(&mut self, key: &[u8]) {
+        let clock = self.clock;
+        if self
+            .data
+            .get(key)
+            .and_then(|entry| entry.meta.expires_at)
+            .is_some_and(|expires_at| expires_at <= clock)
+        {
+            self.drop_entry(key);
+        }
+    }
+
+    /// Keys whose TTL has passed but which are still stored, for the
+    /// expiry sweep.
+    pub fn expired_keys(&self, now: u64) -> Vec<Vec<u8>> {
+        self.data
+            .iter()
+            .filter(|(_, entry)| entry.meta.expires_at.is_some_and(|expires_at| expires_at <= now))
+            .map(|(key, _)| key.clone())
+            .collect()
+    }
+
+    pub fn meta(&self, key: &[u8]) -> Option<&Metadata> {
+        self.data.get(key).filter(|entry| !self.is_expired(entry)).map(|entry| &entry.meta)
+    }
+
+    pub fn meta_mut(&mut self, key: &[u8]) -> Option<&mut Metadata> {
+        self.data.get_mut(key).map(|entry| &mut entry.meta)
+    }
+
+    pub fn history(&self) -> &History {
+        &self.history
+    }
+
+    pub fn history_mut(&mut self) -> &mut History {
+        &mut self.history
+    }
+
+    /// Records the current string value of `key` (or its absence) as a
+    /// version, if `config` keeps history for it. Collections are skipped.
+    pub fn record_version(&mut self, config: &HistoryConfig, key: &[u8], version: u64, timestamp: u64) {
+        let value = match self.get(key) {
+            Some(Value::String(bytes)) => Some(bytes.clone()),
+            Some(_) => return,
+            None => None,
+        };
+        self.history.record(config, key, Version { version, timestamp, value });
+    }
+
+    pub fn leases(&self) -> &Leases {
+        &self.leases
+    }
+
+    pub fn leases_mut(&mut self) -> &mut Leases {
+        &mut self.leases
+    }
+
+    pub fn get(&self, key: &[u8]) -> Option<&Value> {
+        self.data.get(key).filter(|entry| !self.is_expired(entry)).map(|entry| &entry.value)
+    }
+
+    /// Returns the string value at `key`, or `WrongType` if the key holds a
+    /// collection.
+    pub fn get_string(&self, key: &[u8]) -> Result<Option<&Vec<u8>>> {
+        match self.get(key) {
+            Some(Value::String(bytes)) => Ok(Some(bytes)),
+            Some(other) => Err(wrong_type(key, "string", other)),
+            None => Ok(None),
+        }
+    }
+
+    /// Fails with `WrongType` if `key` exists and is not of `expected` type.
+    pub fn check_type(&self, key: &[u8], expected: &'static str) -> Result<()> {
+        match self.get(key) {
+            Some(value) if value.type_name() != expected => Err(wrong_type(key, expected, value)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Payload bytes currently stored under `key`.
+    pub fn size_of(&self, key: &[u8]) -> usize {
+        self.data.get(key).map_or(0, |entry| entry.size)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&Vec<u8>, &Value)> {
+        self.data
+            .iter()
+            .filter(|(_, entry)| !self.is_expired(entry))
+            .map(|(key, entry)| (key, &entry.value))
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &Vec<u8>> {
+        self.iter().map(|(key, _)| key)
+    }
+
+    /// Checks that storing `value_len` bytes under `key` would stay within
+    /// every configured limit. Overwriting an existing key never counts
+    /// against `max_keys` or prefix quotas.
+    pub fn check_limits(&self, limits: &LimitsConfig, key: &[u8], value_len: usize) -> Result<()> {
+        let existing = self.data.get(key).map(|entry| key.len() + entry.size);
+
+        if existing.is_none() {
+            if self.data.len() >= limits.max_keys {
+                return Err(KlineError::DatabaseFull {
+                    current: self.data.len(),
+                    max: limits.max_keys,
+                });
+            }
+
+            for (prefix, max) in &limits.prefix_quotas {
+                let current = self.prefix_counts.get(prefix).copied().unwrap_or(0);
+                if key.starts_with(prefix.as_bytes()) && current >= *max {
+                    return Err(KlineError::PrefixQuotaExceeded {
+                        prefix: prefix.clone(),
+                        current,
+                        max: *max,
+                    });
+                }
+            }
+        }
+
+        if let Some(max) = limits.max_total_bytes {
+            let projected = self.total_bytes - existing.unwrap_or(0) + key.len() + value_len;
+            if projected > max {
+                return Err(KlineError::StorageFull { size: projected, max });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// `check_limits` for a batch of `(key, value_len)` writes applied
+    /// together. A key written more than once counts with its last length.
+    pub fn check_puts(&self, limits: &LimitsConfig, puts: &[(&[u8], usize)]) -> Result<()> {
+        let mut sizes: HashMap<&[u8], usize> = HashMap::new();
+        for (key, value_len) in puts {
+            sizes.insert(key, *value_len);
+        }
+        let new_keys: Vec<&[u8]> = sizes.keys().copied().filter(|key| !self.data.contains_key(*key)).collect();
+
+        if !new_keys.is_empty() && self.data.len() + new_keys.len() > limits.max_keys {
+            return Err(KlineError::DatabaseFull {
+                current: self.data.len(),
+                max: limits.max_keys,
+            });
+        }
+
+        for (prefix, max) in &limits.prefix_quotas {
+            let current = self.prefix_counts.get(prefix).copied().unwrap_or(0);
+            let added = new_keys.iter().filter(|key| key.starts_with(prefix.as_bytes())).count();
+            if added > 0 && current + added > *max {
+                return Err(KlineError::PrefixQuotaExceeded {
+                    prefix: prefix.clone(),
+                    current,
+                    max: *max,
+                });
+            }
+        }
+
+        if let Some(max) = limits.max_total_bytes {
+            let added: usize = sizes.iter().map(|(key, value_len)| key.len() + value_len).sum();
+            let removed: usize = sizes
+                .keys()
+                .filter(|key| self.data.contains_key(**key))
+                .map(|key| key.len() + self.size_of(key))
+                .sum();
+            let projected = (self.total_bytes + added).saturating_sub(removed);
+            if projected > max {
+                return Err(KlineError::StorageFull { size: projected, max });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Inserts or replaces the value at `key`. A replaced entry keeps its
+    /// creation time, content type and tags but loses its TTL; see
+    /// `overwrite` for writes that keep it.
+    pub fn insert(&mut self, key: Vec<u8>, value: Value) -> Option<Value> {
+        self.purge_expired(&key);
+        let size = value.size();
+        self.total_bytes += key.len() + size;
+        let meta = match self.data.get(&key) {
+            Some(old_entry) => Metadata { updated_at: self.clock, expires_at: None, ..old_entry.meta.clone() },
+            None => Metadata::new(self.clock),
+        };
+        let old = self.data.insert(key.clone(), Entry { value, size, meta });
+        match &old {
+            Some(old_entry) => self.total_bytes -= key.len() + old_entry.size,
+            None => self.adjust_prefix_counts(&key, true),
+        }
+        old.map(|entry| entry.value)
+    }
+
+    /// Like `insert`, but a replaced entry keeps its TTL too. Used for
+    /// writes computed from the old value, such as counters.
+    pub fn overwrite(&mut self, key: Vec<u8>, value: Value) -> Option<Value> {
+        let expires_at = self.meta(&key).and_then(|meta| meta.expires_at);
+        let old = self.insert(key.clone(), value);
+        if let Some(meta) = self.meta_mut(&key) {
+            meta.expires_at = expires_at;
+        }
+        old
+    }
+
+    pub fn remove(&mut self, key: &[u8]) -> Option<Value> {
+        self.take(key).map(|entry| entry.value)
+    }
+
+    /// Checks that moving each `(from, to)` pair would stay within the
+    /// configured limits. Existing targets that are not themselves being
+    /// moved count as replaced.
+    pub fn check_renames(&self, limits: &LimitsConfig, renames: &[(Vec<u8>, Vec<u8>)]) -> Result<()> {
+        let sources: HashSet<&[u8]> = renames.iter().map(|(from, _)| from.as_slice()).collect();
+        let replaced: Vec<&[u8]> = renames
+            .iter()
+            .map(|(_, to)| to.as_slice())
+            .filter(|to| self.data.contains_key(*to) && !sources.contains(to))
+            .collect();
+
+        for (prefix, max) in &limits.prefix_quotas {
+            let matching = |key: &[u8]| key.starts_with(prefix.as_bytes()) as isize;
+            let delta: isize = renames.iter().map(|(from, to)| matching(to) - matching(from)).sum::<isize>()
+                - replaced.iter().map(|key| matching(key)).sum::<isize>();
+            let current = self.prefix_counts.get(prefix).copied().unwrap_or(0);
+            if delta > 0 && current as isize + delta > *max as isize {
+                return Err(KlineError::PrefixQuotaExceeded {
+                    prefix: prefix.clone(),
+                    current,
+                    max: *max,
+                });
+            }
+        }
+
+        if let Some(max) = limits.max_total_bytes {
+            let added: usize = renames.iter().map(|(_, to)| to.len()).sum();
+            let removed: usize = renames.iter().map(|(from, _)| from.len()).sum::<usize>()
+                + replaced.iter().map(|key| key.len() + self.size_of(key)).sum::<usize>();
+            let projected = (self.total_bytes + added).saturating_sub(removed);
+            if projected > max {
+                return Err(KlineError::StorageFull { size: projected, max });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Moves each `(from, to)` entry with its metadata, replacing whatever
+    /// is at `to`. All sources are taken out before any target is written,
+    /// so overlapping moves such as `a:` to `a:b:` work.
+    pub fn rename(&mut self, renames: &[(Vec<u8>, Vec<u8>)]) {
+        let moved: Vec<(Vec<u8>, Entry)> = renames
+            .iter()
+            .filter_map(|(from, to)| Some((to.clone(), self.take(from)?)))
+            .collect();
+        for (to, mut entry) in moved {
+            entry.meta.updated_at = self.clock;
+            self.take(&to);
+            self.put_entry(to, entry);
+        }
+    }
+
+    /// Copies the entry at `from` to `to`, replacing whatever is there. The
+    /// copy keeps the content type and tags but is stamped as new.
+    pub fn copy(&mut self, from: &[u8], to: &[u8]) -> bool {
+        self.purge_expired(from);
+        let Some(source) = self.data.get(from) else {
+            return false;
+        };
+        let entry = Entry {
+            value: source.value.clone(),
+            size: source.size,
+            meta: Metadata {
+                content_type: source.meta.content_type.clone(),
+                tags: source.meta.tags.clone(),
+                ..Metadata::new(self.clock)
+            },
+        };
+        self.take(to);
+        self.put_entry(to.to_vec(), entry);
+        true
+    }
+
+    fn take(&mut self, key: &[u8]) -> Option<Entry> {
+        self.purge_expired(key);
+        self.drop_entry(key)
+    }
+
+    fn drop_entry(&mut self, key: &[u8]) -> Option<Entry> {
+        let old = self.data.remove(key)?;
+        self.total_bytes -= key.len() + old.size;
+        self.adjust_prefix_counts(key, false);
+        Some(old)
+    }
+
+    /// Inserts an entry under a key that must not currently exist.
+    fn put_entry(&mut self, key: Vec<u8>, entry: Entry) {
+        self.total_bytes += key.len() + entry.size;
+        self.adjust_prefix_counts(&key, true);
+        self.data.insert(key, entry);
+    }
+
+    pub fn clear(&mut self) {
+        self.data.clear();
+        self.history.clear();
+        self.total_bytes = 0;
+        for count in self.prefix_counts.values_mut() {
+            *count = 0;
+        }
+    }
+
+    /// Applies `f` to the string value at `key` in place, creating an empty
+    /// value first if the key is missing. Returns `None` if the key holds a
+    /// collection.
+    pub fn modify_string<R>(&mut self, key: &[u8], f: impl FnOnce(&mut Vec<u8>) -> R) -> Option<R> {
+        self.update(key, || Value::String(Vec::new()), |value| match value {
+            Value::String(bytes) => {
+                let before = bytes.len();
+                let result = f(bytes);
+                Some((result, bytes.len() as isize - before as isize))
+            }
+            _ => None,
+        })
+    }
+
+    /// Pushes `items` onto the front or back of the list at `key` and returns
+    /// its new length.
+    pub fn push(&mut self, key: &[u8], items: Vec<Vec<u8>>, front: bool) -> Option<usize> {
+        self.update(key, || Value::List(VecDeque::new()), |value| match value {
+            Value::List(list) => {
+                let added: usize = items.iter().map(Vec::len).sum();
+                for item in items {
+                    if front {
+                        list.push_front(item);
+                    } else {
+                        list.push_back(item);
+                    }
+                }
+                Some((list.len(), added as isize))
+            }
+            _ => None,
+        })
+    }
+
+    /// Removes up to `count` items from the front or back of the list at
+    /// `key`.
+    pub fn pop(&mut self, key: &[u8], count: usize, front: bool) -> Option<Vec<Vec<u8>>> {
+        self.purge_expired(key);
+        if !self.data.contains_key(key) {
+            return Some(Vec::new());
+        }
+        self.update(key, || Value::List(VecDeque::new()), |value| match value {
+            Value::List(list) => {
+                let count = count.min(list.len());
+                let popped: Vec<Vec<u8>> = if front {
+                    list.drain(..count).collect()
+                } else {
+                    list.drain(list.len() - count..).rev().collect()
+                };
+                let removed: usize = popped.iter().map(Vec::len).sum();
+                Some((popped, -(removed as isize)))
+            }
+            _ => None,
+        })
+    }
+
+    /// Removes occurrences of `item` from the list at `key`, from the head if
+    /// `count` is positive, from the tail if negative, or all if zero.
+    pub fn lrem(&mut self, key: &[u8], count: i64, item: &[u8]) -> Option<usize> {
+        self.purge_expired(key);
+        if !self.data.contains_key(key) {
+            return Some(0);
+        }
+        self.update(key, || Value::List(VecDeque::new()), |value| match value {
+            Value::List(list) => {
+                let limit = if count == 0 { usize::MAX } else { count.unsigned_abs() as usize };
+                let mut positions: Vec<usize> = list
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, i)| i.as_slice() == item)
+                    .map(|(pos, _)| pos)
+                    .collect();
+                if count < 0 {
+                    positions.reverse();
+                }
+                positions.truncate(limit);
+                positions.sort_unstable_by(|a, b| b.cmp(a));
+                for &pos in &positions {
+                    list.remove(pos);
+                }
+                Some((positions.len(), -((positions.len() * item.len()) as isize)))
+            }
+            _ => None,
+        })
+    }
+
+    /// Sets hash fields and returns how many of them were new.
+    pub fn hset(&mut self, key: &[u8], fields: Vec<(Vec<u8>, Vec<u8>)>) -> Option<usize> {
+        self.update(key, || Value::Hash(HashMap::new()), |value| match value {
+            Value::Hash(hash) => {
+                let mut created = 0;
+                let mut delta = 0isize;
+                for (field, val) in fields {
+                    delta += (field.len() + val.len()) as isize;
+                    let field_len = field.len();
+                    match hash.insert(field, val) {
+                        Some(old) => delta -= (field_len + old.len()) as isize,
+                        None => created += 1,
+                    }
+                }
+                Some((created, delta))
+            }
+            _ => None,
+        })
+    }
+
+    /// Removes hash fields and returns how many existed.
+    pub fn hdel(&mut self, key: &[u8], fields: &[Vec<u8>]) -> Option<usize> {
+        if !self.data.contains_key(key) {
+            return Some(0);
+        }
+        self.update(key, || Value::Hash(HashMap::new()), |value| match value {
+            Value::Hash(hash) => {
+                let mut removed = 0;
+                let mut delta = 0isize;
+                for field in fields {
+                    if let Some(old) = hash.remove(field) {
+                        removed += 1;
+                        delta -= (field.len() + old.len()) as isize;
+                    }
+                }
+                Some((removed, delta))
+            }
+            _ => None,
+        })
+    }
+
+    /// Adds set members and returns how many were new.
+    pub fn sadd(&mut self, key: &[u8], members: Vec<Vec<u8>>) -> Option<usize> {
+        self.update(key, || Value::Set(HashSet::new()), |value| match value {
+            Value::Set(set) => {
+                let mut added = 0;
+                let mut delta = 0isize;
+                for member in members {
+                    let len = member.len();
+                    if set.insert(member) {
+                        added += 1;
+                        delta += len as isize;
+                    }
+                }
+                Some((added, delta))
+            }
+            _ => None,
+        })
+    }
+
+    /// Removes set members and returns how many existed.
+    pub fn srem(&mut self, key: &[u8], members: &[Vec<u8>]) -> Option<usize> {
+        if !self.data.contains_key(key) {
+            return Some(0);
+        }
+        self.update(key, || Value::Set(HashSet::new()), |value| match value {
+            Value::Set(set) => {
+                let mut removed = 0;
+                let mut delta = 0isize;
+                for member in members {
+                    if set.remove(member) {
+                        removed += 1;
+                        delta -= member.len() as isize;
+                    }
+                }
+                Some((removed, delta))
+            }
+            _ => None,
+        })
+    }
+
+    /// Adds or rescores sorted set members and returns how many were new.
+    pub fn zadd(&mut self, key: &[u8], members: Vec<(f64, Vec<u8>)>) -> Option<usize> {
+        self.update(key, || Value::SortedSet(SortedSet::default()), |value| match value {
+            Value::SortedSet(zset) => {
+                let mut added = 0;
+                let mut delta = 0isize;
+                for (score, member) in members {
+                    let (size, created) = zset.insert(member, score);
+                    delta += size;
+                    added += created as usize;
+                }
+                Some((added, delta))
+            }
+            _ => None,
+        })
+    }
+
+    /// Removes sorted set members and returns how many existed.
+    pub fn zrem(&mut self, key: &[u8], members: &[Vec<u8>]) -> Option<usize> {
+        if !self.data.contains_key(key) {
+            return Some(0);
+        }
+        self.update(key, || Value::SortedSet(SortedSet::default()), |value| match value {
+            Value::SortedSet(zset) => {
+                let mut removed = 0;
+                let mut delta = 0isize;
+                for member in members {
+                    if let Some(size) = zset.remove(member) {
+                        removed += 1;
+                        delta += size;
+                    }
+                }
+                Some((removed, delta))
+            }
+            _ => None,
+        })
+    }
+
+    /// Removes and returns up to `count` lowest-scored members.
+    pub fn zpopmin(&mut self, key: &[u8], count: usize) -> Option<Vec<(Vec<u8>, f64)>> {
+        if !self.data.contains_key(key) {
+            return Some(Vec::new());
+        }
+        self.update(key, || Value::SortedSet(SortedSet::default()), |value| match value {
+            Value::SortedSet(zset) => {
+                let before = zset.size();
+                let popped = zset.pop_min(count);
+                let delta = zset.size() as isize - before as isize;
+                Some((popped, delta))
+            }
+            _ => None,
+        })
+    }
+
+    /// Applies `f` to the stream at `key`, creating an empty stream if the
+    /// key is missing. `f` reports the change in stored bytes.
+    pub fn update_stream<R>(&mut self, key: &[u8], f: impl FnOnce(&mut Stream) -> (R, isize)) -> Option<R> {
+        self.update(key, || Value::Stream(Stream::default()), |value| match value {
+            Value::Stream(stream) => Some(f(stream)),
+            _ => None,
+        })
+    }
+
+    /// Runs `f` against the value at `key` (created with `empty` if missing),
+    /// applies the size delta it reports and drops collections left empty.
+    /// `f` returns `None` when the value has the wrong type.
+    fn update<R>(
+        &mut self,
+        key: &[u8],
+        empty: impl FnOnce() -> Value,
+        f: impl FnOnce(&mut Value) -> Option<(R, isize)>,
+    ) -> Option<R> {
+        self.purge_expired(key);
+        if !self.data.contains_key(key) {
+            self.insert(key.to_vec(), empty());
+        }
+        let entry = self.data.get_mut(key).expect("key inserted above");
+        let (result, delta) = f(&mut entry.value)?;
+        entry.size = (entry.size as isize + delta) as usize;
+        entry.meta.updated_at = self.clock;
+        self.total_bytes = (self.total_bytes as isize + delta) as usize;
+
+        if entry.value.is_empty_collection() {
+            self.remove(key);
+        }
+        Some(result)
+    }
+
+    fn adjust_prefix_counts(&mut self, key: &[u8], added: bool) {
+        for (prefix, count) in self.prefix_counts.iter_mut() {
+            if key.starts_with(prefix.as_bytes()) {
+                if added {
+                    *count += 1;
+                } else {
+                    *count -= 1;
+                }
+            }
+        }
+    }
+}
+
+fn wrong_type(key: &[u8], expected: &'static str, found: &Value) -> KlineError {
+    KlineError::WrongType {
+        key: String::from_utf8_lossy(key).to_string(),
+        expected,
+        found: found.type_name(),
+    }
+}
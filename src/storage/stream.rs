@@ -0,0 +1,162 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::str::FromStr;
+
+/// Stream entry ID: milliseconds since the epoch plus a sequence number for
+/// entries added within the same millisecond. Rendered as `ms-seq`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct StreamId {
+    pub ms: u64,
+    pub seq: u64,
+}
+
+impl StreamId {
+    pub const MIN: StreamId = StreamId { ms: 0, seq: 0 };
+    pub const MAX: StreamId = StreamId { ms: u64::MAX, seq: u64::MAX };
+
+    pub fn new(ms: u64, seq: u64) -> Self {
+        Self { ms, seq }
+    }
+
+    /// Parses a range bound, accepting `-` and `+` for the lowest and
+    /// highest IDs.
+    pub fn parse_bound(s: &str) -> Option<Self> {
+        match s {
+            "-" => Some(Self::MIN),
+            "+" => Some(Self::MAX),
+            _ => s.parse().ok(),
+        }
+    }
+
+    /// ID for an entry added at `now_ms` after `self`, kept strictly above
+    /// `self` even if the clock goes backwards. `None` after `MAX`.
+    pub fn generate_after(self, now_ms: u64) -> Option<Self> {
+        if now_ms > self.ms {
+            Some(Self::new(now_ms, 0))
+        } else {
+            self.next()
+        }
+    }
+
+    /// The smallest ID strictly greater than this one, if any.
+    pub fn next(self) -> Option<Self> {
+        match self.seq.checked_add(1) {
+            Some(seq) => Some(Self::new(self.ms, seq)),
+            None => self.ms.checked_add(1).map(|ms| Self::new(ms, 0)),
+        }
+    }
+}
+
+impl fmt::Display for StreamId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.ms, self.seq)
+    }
+}
+
+impl FromStr for StreamId {
+    type Err = ();
+
+    /// Accepts `ms-seq` or a bare `ms`, which means `ms-0`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once('-') {
+            Some((ms, seq)) => Ok(Self::new(ms.parse().map_err(|_| ())?, seq.parse().map_err(|_| ())?)),
+            None => Ok(Self::new(s.parse().map_err(|_| ())?, 0)),
+        }
+    }
+}
+
+pub type Fields = Vec<(Vec<u8>, Vec<u8>)>;
+
+/// Bytes charged per entry for its ID.
+pub const ID_SIZE: usize = 2 * std::mem::size_of::<u64>();
+
+/// An entry delivered to a consumer but not yet acknowledged.
+#[derive(Debug, Clone)]
+pub struct PendingEntry {
+    pub consumer: String,
+    pub delivered_ms: u64,
+    pub delivery_count: u32,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ConsumerGroup {
+    pub last_delivered: StreamId,
+    pub pending: BTreeMap<StreamId, PendingEntry>,
+}
+
+/// Append-only log of field/value entries ordered by ID, with consumer
+/// groups tracking delivery and acknowledgement.
+#[derive(Debug, Clone, Default)]
+pub struct Stream {
+    pub entries: BTreeMap<StreamId, Fields>,
+    pub last_id: StreamId,
+    pub groups: HashMap<String, ConsumerGroup>,
+}
+
+impl Stream {
+    pub fn size(&self) -> usize {
+        self.entries.values().map(entry_size).sum()
+    }
+
+    /// Appends an entry and returns the change in stored bytes. The caller
+    /// guarantees `id > last_id`.
+    pub fn add(&mut self, id: StreamId, fields: Fields) -> isize {
+        let size = entry_size(&fields) as isize;
+        self.entries.insert(id, fields);
+        self.last_id = self.last_id.max(id);
+        size
+    }
+
+    /// Drops entries with IDs below `min_id` and returns how many were
+    /// removed together with the change in stored bytes.
+    pub fn trim_before(&mut self, min_id: StreamId) -> (usize, isize) {
+        let kept = self.entries.split_off(&min_id);
+        let removed = std::mem::replace(&mut self.entries, kept);
+        let bytes: usize = removed.values().map(entry_size).sum();
+        (removed.len(), -(bytes as isize))
+    }
+
+    /// Entries with `start <= id <= end`, capped at `count`.
+    pub fn range(&self, start: StreamId, end: StreamId, count: usize) -> Vec<(StreamId, Fields)> {
+        if start > end {
+            return Vec::new();
+        }
+        self.entries
+            .range(start..=end)
+            .take(count)
+            .map(|(id, fields)| (*id, fields.clone()))
+            .collect()
+    }
+
+    /// Marks `ids` as delivered to `consumer` in `group`, advancing the
+    /// group's last delivered ID.
+    pub fn deliver(&mut self, group: &str, consumer: &str, now_ms: u64, ids: &[StreamId]) {
+        let Some(group) = self.groups.get_mut(group) else {
+            return;
+        };
+        for id in ids {
+            let pending = group.pending.entry(*id).or_insert_with(|| PendingEntry {
+                consumer: consumer.to_string(),
+                delivered_ms: now_ms,
+                delivery_count: 0,
+            });
+            pending.consumer = consumer.to_string();
+            pending.delivered_ms = now_ms;
+            pending.delivery_count += 1;
+            group.last_delivered = group.last_delivered.max(*id);
+        }
+    }
+
+    /// Removes `ids` from the group's pending list and returns how many were
+    /// pending.
+    pub fn ack(&mut self, group: &str, ids: &[StreamId]) -> usize {
+        match self.groups.get_mut(group) {
+            Some(group) => ids.iter().filter(|id| group.pending.remove(id).is_some()).count(),
+            None => 0,
+        }
+    }
+}
+
+fn entry_size(fields: &Fields) -> usize {
+    ID_SIZE + fields.iter().map(|(f, v)| f.len() + v.len()).sum::<usize>()
+}
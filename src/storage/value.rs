@@ -0,0 +1,53 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use super::stream::Stream;
+use super::zset::SortedSet;
+
+/// A value stored under a key. Plain `put`/`get` operate on `String`; the
+/// collection variants are created and mutated by their own commands.
+#[derive(Debug, Clone)]
+pub enum Value {
+    String(Vec<u8>),
+    List(VecDeque<Vec<u8>>),
+    Hash(HashMap<Vec<u8>, Vec<u8>>),
+    Set(HashSet<Vec<u8>>),
+    SortedSet(SortedSet),
+    Stream(Stream),
+}
+
+impl Value {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::String(_) => "string",
+            Value::List(_) => "list",
+            Value::Hash(_) => "hash",
+            Value::Set(_) => "set",
+            Value::SortedSet(_) => "zset",
+            Value::Stream(_) => "stream",
+        }
+    }
+
+    /// Number of payload bytes held by the value, as counted against
+    /// `LimitsConfig::max_total_bytes`.
+    pub fn size(&self) -> usize {
+        match self {
+            Value::String(bytes) => bytes.len(),
+            Value::List(items) => items.iter().map(Vec::len).sum(),
+            Value::Hash(fields) => fields.iter().map(|(f, v)| f.len() + v.len()).sum(),
+            Value::Set(members) => members.iter().map(Vec::len).sum(),
+            Value::SortedSet(zset) => zset.size(),
+            Value::Stream(stream) => stream.size(),
+        }
+    }
+
+    /// Collections are removed once their last element is gone. Streams
+    /// are kept so their last ID and consumer groups survive trimming.
+    pub fn is_empty_collection(&self) -> bool {
+        match self {
+            Value::String(_) | Value::Stream(_) => false,
+            Value::List(items) => items.is_empty(),
+            Value::Hash(fields) => fields.is_empty(),
+            Value::Set(members) => members.is_empty(),
+            Value::SortedSet(zset) => zset.is_empty(),
+        }
+    }
+}
@@ -0,0 +1,65 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, Weak};
+use tokio::sync::{futures::Notified, Notify};
+
+/// Wakes blocked readers when the keys they wait on change.
+///
+/// Each blocked call registers one `Notify` under every key it waits on;
+/// writers call `wake` after mutating a key. `notify_one` stores a permit, so
+/// a wake that lands between a reader's check and its await is not lost.
+#[derive(Default)]
+pub struct Waiters {
+    keys: Mutex<HashMap<Vec<u8>, Vec<Weak<Notify>>>>,
+}
+
+/// A blocked call's registration, removed from every key it waits on when
+/// dropped, whether the call returned or was cancelled.
+pub struct Registration<'a> {
+    waiters: &'a Waiters,
+    keys: Vec<Vec<u8>>,
+    notify: Arc<Notify>,
+}
+
+impl Registration<'_> {
+    pub fn notified(&self) -> Notified<'_> {
+        self.notify.notified()
+    }
+}
+
+impl Drop for Registration<'_> {
+    fn drop(&mut self) {
+        let mut map = self.waiters.keys.lock().unwrap_or_else(|e| e.into_inner());
+        for key in &self.keys {
+            if let Some(waiters) = map.get_mut(key) {
+                waiters.retain(|waiter| Weak::as_ptr(waiter) != Arc::as_ptr(&self.notify));
+                if waiters.is_empty() {
+                    map.remove(key);
+                }
+            }
+        }
+    }
+}
+
+impl Waiters {
+    pub fn register(&self, keys: &[&[u8]]) -> Registration<'_> {
+        let notify = Arc::new(Notify::new());
+        let mut map = self.keys.lock().unwrap_or_else(|e| e.into_inner());
+        for key in keys {
+            map.entry(key.to_vec()).or_default().push(Arc::downgrade(&notify));
+        }
+        Registration {
+            waiters: self,
+            keys: keys.iter().map(|key| key.to_vec()).collect(),
+            notify,
+        }
+    }
+
+    pub fn wake(&self, key: &[u8]) {
+        let map = self.keys.lock().unwrap_or_else(|e| e.into_inner());
+        for waiter in map.get(key).into_iter().flatten() {
+            if let Some(notify) = waiter.upgrade() {
+                notify.notify_one();
+            }
+        }
+    }
+}
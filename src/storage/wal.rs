@@ -0,0 +1,234 @@
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Lines, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use base64::{engine::general_purpose, Engine as _};
+use crate::constants::db::{SEGMENT_SUFFIX, TEMP_FILE_SUFFIX};
+use crate::error::{KlineError, Result};
+
+/// Write-ahead log with global sequence numbers.
+///
+/// Every record is written as `<seq> <timestamp> <op> <args...>`, with the
+/// timestamp in milliseconds since the Unix epoch. Compaction writes a
+/// new file that starts with `checkpoint <seq>` followed by unsequenced
+/// snapshot records, and keeps the replaced file as a read-only segment
+/// named `<path>.<checkpoint>.wal` so `changes_since` can serve history
+/// older than the live file. Only the newest `retained_segments` segments
+/// are kept.
+pub struct Wal {
+    path: String,
+    file: Mutex<File>,
+    seq: AtomicU64,
+    timestamp: AtomicU64,
+    checkpoint: AtomicU64,
+    retained_segments: usize,
+}
+
+/// A committed mutation as recorded in the log.
+#[derive(Debug, Clone)]
+pub struct WalEntry {
+    pub seq: u64,
+    /// Milliseconds since the Unix epoch; 0 for records written before
+    /// timestamps were logged.
+    pub timestamp: u64,
+    /// Record type, such as `put`, `delete`, `append` or `lpush`.
+    pub op: String,
+    /// Remaining fields in log encoding: keys and payloads are base64,
+    /// counts, offsets, scores and stream IDs are plain text.
+    pub args: Vec<String>,
+}
+
+impl WalEntry {
+    /// The key this record mutates, if the record has one.
+    pub fn key(&self) -> Option<Vec<u8>> {
+        general_purpose::STANDARD.decode(self.args.first()?).ok()
+    }
+}
+
+/// Splits a log line into its sequence number and timestamp (if present)
+/// and the record. Snapshot records carry neither.
+pub fn split_header(line: &str) -> (Option<u64>, Option<u64>, &str) {
+    let (seq, record) = split_number(line);
+    if seq.is_none() {
+        return (None, None, line);
+    }
+    let (timestamp, record) = split_number(record);
+    (seq, timestamp, record)
+}
+
+fn split_number(line: &str) -> (Option<u64>, &str) {
+    match line.split_once(' ') {
+        Some((number, rest)) if number.bytes().all(|b| b.is_ascii_digit()) => (number.parse().ok(), rest),
+        _ => (None, line),
+    }
+}
+
+/// Reads the checkpoint a log file starts from; files written before
+/// checkpoints existed start from 0.
+fn read_checkpoint(path: &Path) -> Result<u64> {
+    let mut first = String::new();
+    BufReader::new(File::open(path)?).read_line(&mut first)?;
+    Ok(first
+        .trim_end()
+        .strip_prefix("checkpoint ")
+        .and_then(|seq| seq.parse().ok())
+        .unwrap_or(0))
+}
+
+impl Wal {
+    /// Opens the log at `path` for appending. `last_seq` and `checkpoint`
+    /// come from replaying it.
+    pub fn open(path: &str, last_seq: u64, checkpoint: u64, retained_segments: usize) -> Result<Self> {
+        let file = OpenOptions::new().append(true).create(true).open(path)?;
+        Ok(Self {
+            path: path.to_string(),
+            file: Mutex::new(file),
+            seq: AtomicU64::new(last_seq),
+            timestamp: AtomicU64::new(0),
+            checkpoint: AtomicU64::new(checkpoint),
+            retained_segments,
+        })
+    }
+
+    /// Sequence number of the last record written.
+    pub fn last_seq(&self) -> u64 {
+        self.seq.load(Ordering::SeqCst)
+    }
+
+    /// Timestamp of the last record written in this session.
+    pub fn last_timestamp(&self) -> u64 {
+        self.timestamp.load(Ordering::SeqCst)
+    }
+
+    /// Appends a record stamped with `timestamp`, flushes it and returns its
+    /// sequence number.
+    pub fn append(&self, record: &str, timestamp: u64) -> Result<u64> {
+        let mut file = self.file.lock().map_err(|_| KlineError::LockPoisoned)?;
+        let seq = self.seq.load(Ordering::SeqCst) + 1;
+        writeln!(file, "{} {} {}", seq, timestamp, record)?;
+        file.flush()?;
+        self.seq.store(seq, Ordering::SeqCst);
+        self.timestamp.store(timestamp, Ordering::SeqCst);
+        Ok(seq)
+    }
+
+    /// Whether any record has been written since the last checkpoint.
+    pub fn has_changes(&self) -> bool {
+        self.last_seq() > self.checkpoint.load(Ordering::SeqCst)
+    }
+
+    /// Replaces the live file with a checkpoint at the current sequence
+    /// number followed by whatever `write_snapshot` writes. The caller must
+    /// keep writers out (by holding the store lock) for the duration.
+    pub fn rotate(&self, write_snapshot: impl FnOnce(&mut File) -> Result<()>) -> Result<()> {
+        let mut file = self.file.lock().map_err(|_| KlineError::LockPoisoned)?;
+        let checkpoint = self.last_seq();
+        let temp_path = format!("{}{}", self.path, TEMP_FILE_SUFFIX);
+
+        let mut temp_file = File::create(&temp_path)?;
+        writeln!(temp_file, "checkpoint {}", checkpoint)?;
+        write_snapshot(&mut temp_file)?;
+        temp_file.flush()?;
+
+        // Keep the old log as a segment, then atomically swap in the new one.
+        if self.retained_segments > 0 {
+            let segment = format!("{}.{:020}{}", self.path, checkpoint, SEGMENT_SUFFIX);
+            if !Path::new(&segment).exists() {
+                std::fs::hard_link(&self.path, &segment)?;
+            }
+        }
+        std::fs::rename(&temp_path, &self.path)?;
+        *file = OpenOptions::new().append(true).open(&self.path)?;
+        self.checkpoint.store(checkpoint, Ordering::SeqCst);
+
+        let segments = self.segments()?;
+        let excess = segments.len().saturating_sub(self.retained_segments);
+        for segment in &segments[..excess] {
+            std::fs::remove_file(segment)?;
+        }
+        Ok(())
+    }
+
+    /// Retained segments, oldest first.
+    fn segments(&self) -> Result<Vec<PathBuf>> {
+        let path = Path::new(&self.path);
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let prefix = format!("{}.", path.file_name().and_then(|n| n.to_str()).unwrap_or_default());
+
+        let mut segments: Vec<PathBuf> = std::fs::read_dir(dir)?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|p| {
+                p.file_name()
+                    .and_then(|n| n.to_str())
+                    .is_some_and(|n| n.starts_with(&prefix) && n.ends_with(SEGMENT_SUFFIX))
+            })
+            .collect();
+        segments.sort();
+        Ok(segments)
+    }
+
+    /// Iterates over committed records with sequence numbers greater than
+    /// `since`, oldest first. Fails with `ChangesCompacted` if records after
+    /// `since` are no longer retained.
+    pub fn changes_since(&self, since: u64) -> Result<ChangesSince> {
+        // Hold the file lock so the set of files cannot rotate underneath us.
+        let _file = self.file.lock().map_err(|_| KlineError::LockPoisoned)?;
+        let mut paths = self.segments()?;
+        paths.push(PathBuf::from(&self.path));
+
+        let earliest = read_checkpoint(&paths[0])?;
+        if since < earliest {
+            return Err(KlineError::ChangesCompacted { requested: since, earliest });
+        }
+
+        let files = paths
+            .iter()
+            .map(File::open)
+            .collect::<std::io::Result<VecDeque<File>>>()?;
+        Ok(ChangesSince { files, lines: None, since })
+    }
+}
+
+/// Iterator returned by `Kline::changes_since`.
+pub struct ChangesSince {
+    files: VecDeque<File>,
+    lines: Option<Lines<BufReader<File>>>,
+    since: u64,
+}
+
+impl Iterator for ChangesSince {
+    type Item = Result<WalEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let lines = match &mut self.lines {
+                Some(lines) => lines,
+                None => self.lines.insert(BufReader::new(self.files.pop_front()?).lines()),
+            };
+            let line = match lines.next() {
+                Some(Ok(line)) => line,
+                Some(Err(err)) => return Some(Err(err.into())),
+                None => {
+                    self.lines = None;
+                    continue;
+                }
+            };
+
+            let (Some(seq), timestamp, record) = split_header(&line) else {
+                continue;
+            };
+            if seq <= self.since {
+                continue;
+            }
+            let mut parts = record.split(' ').map(str::to_string);
+            let op = parts.next().unwrap_or_default();
+            return Some(Ok(WalEntry { seq, timestamp: timestamp.unwrap_or(0), op, args: parts.collect() }));
+        }
+    }
+}
@@ -0,0 +1,116 @@
+use std::cmp::Ordering;
+use std::collections::{BTreeSet, HashMap};
+
+/// Score wrapper giving `f64` the total order needed for the score index.
+/// NaN scores are rejected before they reach the set.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Score(pub f64);
+
+impl Eq for Score {}
+
+impl PartialOrd for Score {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Score {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+/// Sorted set indexed both by member (for score lookups) and by
+/// `(score, member)` (for ordered range reads).
+#[derive(Debug, Clone, Default)]
+pub struct SortedSet {
+    scores: HashMap<Vec<u8>, f64>,
+    ordered: BTreeSet<(Score, Vec<u8>)>,
+}
+
+/// Bytes charged per member for its score.
+pub const SCORE_SIZE: usize = std::mem::size_of::<f64>();
+
+impl SortedSet {
+    pub fn len(&self) -> usize {
+        self.scores.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.scores.is_empty()
+    }
+
+    pub fn size(&self) -> usize {
+        self.scores.keys().map(|member| member.len() + SCORE_SIZE).sum()
+    }
+
+    pub fn score(&self, member: &[u8]) -> Option<f64> {
+        self.scores.get(member).copied()
+    }
+
+    /// Zero-based position of `member` in ascending score order.
+    pub fn rank(&self, member: &[u8]) -> Option<usize> {
+        let score = self.score(member)?;
+        Some(self.ordered.range(..(Score(score), member.to_vec())).count())
+    }
+
+    /// Inserts or rescores `member`. Returns the change in stored bytes and
+    /// whether the member is new.
+    pub fn insert(&mut self, member: Vec<u8>, score: f64) -> (isize, bool) {
+        match self.scores.insert(member.clone(), score) {
+            Some(old) => {
+                self.ordered.remove(&(Score(old), member.clone()));
+                self.ordered.insert((Score(score), member));
+                (0, false)
+            }
+            None => {
+                let size = (member.len() + SCORE_SIZE) as isize;
+                self.ordered.insert((Score(score), member));
+                (size, true)
+            }
+        }
+    }
+
+    /// Removes `member`, returning the change in stored bytes if it existed.
+    pub fn remove(&mut self, member: &[u8]) -> Option<isize> {
+        let score = self.scores.remove(member)?;
+        self.ordered.remove(&(Score(score), member.to_vec()));
+        Some(-((member.len() + SCORE_SIZE) as isize))
+    }
+
+    /// Removes and returns up to `count` members with the lowest scores.
+    pub fn pop_min(&mut self, count: usize) -> Vec<(Vec<u8>, f64)> {
+        let mut popped = Vec::with_capacity(count.min(self.len()));
+        while popped.len() < count {
+            let Some((Score(score), member)) = self.ordered.pop_first() else {
+                break;
+            };
+            self.scores.remove(&member);
+            popped.push((member, score));
+        }
+        popped
+    }
+
+    /// Members with ranks between `start` and `stop`, both inclusive, in
+    /// ascending score order.
+    pub fn range_by_rank(&self, start: usize, stop: usize) -> Vec<(Vec<u8>, f64)> {
+        self.ordered
+            .iter()
+            .skip(start)
+            .take(stop.saturating_sub(start) + 1)
+            .map(|(Score(score), member)| (member.clone(), *score))
+            .collect()
+    }
+
+    /// Members with `min <= score <= max` in ascending score order.
+    pub fn range_by_score(&self, min: f64, max: f64) -> impl Iterator<Item = (&Vec<u8>, f64)> {
+        self.ordered
+            .range((Score(min), Vec::new())..)
+            .take_while(move |(Score(score), _)| *score <= max)
+            .map(|(Score(score), member)| (member, *score))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&Vec<u8>, f64)> {
+        self.ordered.iter().map(|(Score(score), member)| (member, *score))
+    }
+}
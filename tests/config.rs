@@ -0,0 +1,47 @@
+use kline::KlineConfig;
+
+const BASE: &str = r#"
+[server]
+port = 3000
+bind_address = "127.0.0.1"
+max_connections = 1000
+
+[storage]
+data_dir = "./data"
+compaction_interval_secs = 60
+max_log_size_mb = 100
+
+[limits]
+max_key_size = 1024
+max_value_size = 10485760
+max_keys = 1000000
+
+[ttl]
+cleanup_interval_secs = 30
+max_ttl_secs = 31536000
+"#;
+
+fn parse(extra: &str) -> KlineConfig {
+    let path = std::env::temp_dir().join(format!("kline-config-{}-{}.conf", std::process::id(), extra.len()));
+    std::fs::write(&path, format!("{}\n{}", BASE, extra)).unwrap();
+    let config = KlineConfig::from_file(&path.to_string_lossy());
+    let _ = std::fs::remove_file(&path);
+    config.unwrap()
+}
+
+#[test]
+fn a_partial_rate_limit_section_keeps_the_other_defaults() {
+    let config = parse("[server.rate_limit]\nrequests_per_sec = 50\n");
+    let defaults = KlineConfig::default().server.rate_limit;
+    assert_eq!(config.server.rate_limit.requests_per_sec, 50.0);
+    assert_eq!(config.server.rate_limit.burst, defaults.burst);
+    assert_eq!(config.server.rate_limit.max_concurrent_requests, defaults.max_concurrent_requests);
+}
+
+#[test]
+fn optional_sections_can_be_left_out() {
+    let config = parse("");
+    assert!(!config.auth.enabled);
+    assert!(config.server.tls.is_none());
+    assert!(config.history.retention.is_empty());
+}
@@ -0,0 +1,629 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+use axum::body::{to_bytes, Body};
+use axum::extract::ConnectInfo;
+use axum::http::{Method, Request, StatusCode};
+use axum::Router;
+use kline::config::{Role, TokenConfig};
+use kline::http::auth::hash_token;
+use kline::http::server::ClientAddr;
+use kline::{http, Kline, KlineConfig};
+use serde_json::{json, Value};
+use tower::ServiceExt;
+
+/// A scratch directory removed when dropped.
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("kline-http-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+        Self(path)
+    }
+
+    fn file(&self, name: &str) -> String {
+        self.0.join(name).to_string_lossy().into_owned()
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+fn open(dir: &TempDir) -> (Arc<Kline>, Router) {
+    open_with(dir, KlineConfig::default())
+}
+
+fn open_with(dir: &TempDir, config: KlineConfig) -> (Arc<Kline>, Router) {
+    let db = Arc::new(Kline::open_with_config(&dir.file("kline.db"), config).unwrap());
+    let app = http::create_router(db.clone());
+    (db, app)
+}
+
+/// Sends one request through the router and returns its status and body.
+async fn call(app: &Router, method: Method, uri: &str, body: impl Into<Body>) -> (StatusCode, Vec<u8>) {
+    let request = Request::builder().method(method).uri(uri).body(body.into()).unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    (status, to_bytes(response.into_body(), usize::MAX).await.unwrap().to_vec())
+}
+
+async fn call_json(app: &Router, method: Method, uri: &str, body: impl Into<Body>) -> (StatusCode, Value) {
+    let (status, body) = call(app, method, uri, body).await;
+    (status, serde_json::from_slice(&body).unwrap())
+}
+
+/// POSTs `body` as JSON, as the bulk routes expect.
+async fn post_json(app: &Router, uri: &str, body: Value) -> (StatusCode, Value) {
+    let request = Request::builder()
+        .method(Method::POST)
+        .uri(uri)
+        .header("content-type", "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    (status, serde_json::from_slice(&to_bytes(response.into_body(), usize::MAX).await.unwrap()).unwrap())
+}
+
+#[tokio::test]
+async fn queue_items_that_are_not_utf8_can_be_acknowledged() {
+    let dir = TempDir::new("queue-binary");
+    let (db, app) = open(&dir);
+    let item = vec![0xff, 0x00, 0x41];
+    db.rpush(b"jobs", vec![item.clone()]).unwrap();
+
+    let (status, popped) = call_json(&app, Method::POST, "/queue/jobs/pop?in_flight=jobs:wip", Body::empty()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(popped["encoding"], "base64");
+    assert_eq!(db.lrange(b"jobs:wip", 0, -1).unwrap(), vec![item]);
+
+    let uri = "/queue/jobs:wip/ack?encoding=base64";
+    let (status, acked) = call_json(&app, Method::POST, uri, popped["value"].as_str().unwrap().to_string()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(acked["count"], 1);
+    assert!(db.lrange(b"jobs:wip", 0, -1).unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn waits_too_long_to_represent_are_rejected() {
+    let dir = TempDir::new("huge-timeout");
+    let (_db, app) = open(&dir);
+    let max = u64::MAX;
+
+    for (method, uri) in [
+        (Method::POST, format!("/queue/jobs/pop?timeout={}", max)),
+        (Method::POST, format!("/lock/job?owner=a&ttl=10&wait={}", max)),
+    ] {
+        let (status, body) = call_json(&app, method, &uri, Body::empty()).await;
+        assert_eq!((status, body["error"].as_str()), (StatusCode::BAD_REQUEST, Some("invalid_timeout")), "{}", uri);
+    }
+}
+
+#[tokio::test]
+async fn missing_keys_get_the_key_not_found_error() {
+    let dir = TempDir::new("missing");
+    let (_db, app) = open(&dir);
+
+    for (uri, range) in [("/key/missing", None), ("/key/missing", Some("bytes=0-3")), ("/raw/missing", None)] {
+        let mut request = Request::builder().uri(uri);
+        if let Some(range) = range {
+            request = request.header("range", range);
+        }
+        let response = app.clone().oneshot(request.body(Body::empty()).unwrap()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND, "{} {:?}", uri, range);
+        let body: Value = serde_json::from_slice(&to_bytes(response.into_body(), usize::MAX).await.unwrap()).unwrap();
+        assert_eq!(body["error"], "key_not_found", "{} {:?}", uri, range);
+        assert_eq!(body["message"], "Key not found: missing");
+    }
+}
+
+#[tokio::test]
+async fn extractor_rejections_use_the_error_body() {
+    let dir = TempDir::new("rejections");
+    let (_db, app) = open(&dir);
+
+    let (status, body) = call_json(&app, Method::GET, "/keys?limit=x", Body::empty()).await;
+    assert_eq!((status, body["error"].as_str()), (StatusCode::BAD_REQUEST, Some("bad_request")));
+    assert!(body["message"].as_str().unwrap().contains("limit"));
+
+    let request = Request::builder()
+        .method(Method::POST)
+        .uri("/mput")
+        .header("content-type", "application/json")
+        .body(Body::from("{not json"))
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let body: Value = serde_json::from_slice(&to_bytes(response.into_body(), usize::MAX).await.unwrap()).unwrap();
+    assert_eq!((status, body["error"].as_str()), (StatusCode::BAD_REQUEST, Some("bad_request")));
+
+    let (status, body) = call_json(&app, Method::POST, "/mput", "{}").await;
+    assert_eq!((status, body["error"].as_str()), (StatusCode::UNSUPPORTED_MEDIA_TYPE, Some("unsupported_media_type")));
+
+    let (status, body) = call_json(&app, Method::PATCH, "/keys", Body::empty()).await;
+    assert_eq!((status, body["error"].as_str()), (StatusCode::METHOD_NOT_ALLOWED, Some("method_not_allowed")));
+}
+
+#[tokio::test]
+async fn missing_hash_fields_and_empty_queues() {
+    let dir = TempDir::new("hash-queue-missing");
+    let (db, app) = open(&dir);
+    db.hset(b"user:1", vec![(b"name".to_vec(), b"ada".to_vec())]).unwrap();
+
+    for uri in ["/hash/user:1/email", "/hash/user:2/name"] {
+        let (status, body) = call_json(&app, Method::GET, uri, Body::empty()).await;
+        assert_eq!((status, body["error"].as_str()), (StatusCode::NOT_FOUND, Some("key_not_found")), "{}", uri);
+    }
+
+    let (status, body) = call(&app, Method::POST, "/queue/jobs/pop", Body::empty()).await;
+    assert_eq!((status, body.is_empty()), (StatusCode::NO_CONTENT, true));
+}
+
+#[tokio::test]
+async fn malformed_ttls_are_invalid_ttl_wherever_they_are_given() {
+    let dir = TempDir::new("ttl-parse");
+    let (db, app) = open(&dir);
+    db.put(b"session".to_vec(), b"token".to_vec()).unwrap();
+
+    for (method, uri, header) in [
+        (Method::PUT, "/key/session?ttl=abc", None),
+        (Method::PUT, "/key/session", Some("abc")),
+        (Method::PUT, "/key/session", Some("-5")),
+        (Method::POST, "/key/session/expire?ttl=1.5", None),
+    ] {
+        let mut request = Request::builder().method(method).uri(uri);
+        if let Some(ttl) = header {
+            request = request.header("x-kline-ttl", ttl);
+        }
+        let response = app.clone().oneshot(request.body(Body::from("v")).unwrap()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{} {:?}", uri, header);
+        let body: Value = serde_json::from_slice(&to_bytes(response.into_body(), usize::MAX).await.unwrap()).unwrap();
+        assert_eq!(body["error"], "invalid_ttl", "{} {:?}", uri, header);
+    }
+    assert_eq!(db.ttl(b"session").unwrap(), None);
+}
+
+#[tokio::test]
+async fn range_reads_carry_the_ttl_header() {
+    let dir = TempDir::new("range-ttl");
+    let (_db, app) = open(&dir);
+    let (status, _) = call(&app, Method::PUT, "/key/session?ttl=60", "token").await;
+    assert_eq!(status, StatusCode::OK);
+
+    let request = Request::builder().uri("/key/session").header("range", "bytes=0-1").body(Body::empty()).unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+    let ttl: u64 = response.headers()["x-kline-ttl"].to_str().unwrap().parse().unwrap();
+    assert!(ttl > 0 && ttl <= 60);
+}
+
+#[tokio::test]
+async fn hash_fields_are_read_in_the_requested_encoding() {
+    let dir = TempDir::new("hget-encoding");
+    let (db, app) = open(&dir);
+    db.hset(b"h", vec![(b"bin".to_vec(), vec![0xff, 0x10]), (b"text".to_vec(), b"hi".to_vec())]).unwrap();
+
+    let (_, body) = call_json(&app, Method::GET, "/hash/h/bin", Body::empty()).await;
+    assert_eq!((body["value"].as_str(), body["encoding"].as_str()), (Some("/xA="), Some("base64")));
+    let (_, body) = call_json(&app, Method::GET, "/hash/h/text?encoding=hex", Body::empty()).await;
+    assert_eq!((body["value"].as_str(), body["encoding"].as_str()), (Some("6869"), Some("hex")));
+    let (status, body) = call_json(&app, Method::GET, "/hash/h/bin?encoding=utf8", Body::empty()).await;
+    assert_eq!((status, body["error"].as_str()), (StatusCode::BAD_REQUEST, Some("bad_request")));
+}
+
+#[tokio::test]
+async fn hex_bodies_must_be_only_hex_digits() {
+    let dir = TempDir::new("hex-digits");
+    let (db, app) = open(&dir);
+
+    for body in ["+f", "0x", "-1", " f"] {
+        let (status, _) = call(&app, Method::PUT, "/key/k?encoding=hex", body).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{:?}", body);
+    }
+    let (status, _) = call(&app, Method::PUT, "/key/k?encoding=hex", "0aFf\n").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(db.get(b"k").unwrap(), Some(vec![0x0a, 0xff]));
+}
+
+#[tokio::test]
+async fn prefix_deletes_refuse_an_empty_prefix() {
+    let dir = TempDir::new("delete-prefix");
+    let (db, app) = open(&dir);
+    for key in ["tmp:1", "tmp:2", "keep"] {
+        db.put(key.as_bytes().to_vec(), b"v".to_vec()).unwrap();
+    }
+    db.put(vec![0xff, b':', b'1'], b"v".to_vec()).unwrap();
+
+    for uri in ["/keys?prefix=&confirm=", "/keys?key_encoding=hex&prefix=&confirm=", "/keys?prefix=tmp:", "/keys?confirm=tmp:"] {
+        let (status, body) = call_json(&app, Method::DELETE, uri, Body::empty()).await;
+        assert_eq!((status, body["error"].as_str()), (StatusCode::BAD_REQUEST, Some("bad_request")), "{}", uri);
+    }
+    assert_eq!(db.count_keys(&Default::default()).unwrap(), 4);
+
+    let (_, body) = call_json(&app, Method::DELETE, "/keys?prefix=tmp:&confirm=tmp:", Body::empty()).await;
+    assert_eq!(body["count"], 2);
+    let (_, body) = call_json(&app, Method::DELETE, "/keys?key_encoding=hex&prefix=ff3a&confirm=ff3a", Body::empty()).await;
+    assert_eq!(body["count"], 1);
+    assert_eq!(db.get(b"keep").unwrap(), Some(b"v".to_vec()));
+}
+
+#[tokio::test]
+async fn unsupported_ranges_are_ignored_and_unsatisfiable_ones_get_416() {
+    let dir = TempDir::new("range-parse");
+    let (db, app) = open(&dir);
+    db.put(b"word".to_vec(), b"hello".to_vec()).unwrap();
+    let get = |range: &'static str| {
+        let request = Request::builder().uri("/key/word").header("range", range).body(Body::empty()).unwrap();
+        let app = app.clone();
+        async move {
+            let response = app.oneshot(request).await.unwrap();
+            let status = response.status();
+            (status, to_bytes(response.into_body(), usize::MAX).await.unwrap().to_vec())
+        }
+    };
+
+    for range in ["items=0-1", "bytes=0-1,3-4", "bytes=3-1", "bytes=+1-2", "bytes=-", "bytes=a-"] {
+        let (status, body) = get(range).await;
+        assert_eq!(status, StatusCode::OK, "{}", range);
+        assert_eq!(serde_json::from_slice::<Value>(&body).unwrap()["value"], "hello", "{}", range);
+    }
+    for (range, expected) in [("bytes=1-100", "ello"), ("bytes=-2", "lo"), ("bytes=4-", "o"), ("bytes=-9", "hello")] {
+        assert_eq!(get(range).await, (StatusCode::PARTIAL_CONTENT, expected.as_bytes().to_vec()), "{}", range);
+    }
+    for range in ["bytes=5-", "bytes=5-9", "bytes=-0"] {
+        assert_eq!(get(range).await.0, StatusCode::RANGE_NOT_SATISFIABLE, "{}", range);
+    }
+}
+
+#[tokio::test]
+async fn content_type_and_tags_change_only_with_their_own_header() {
+    let dir = TempDir::new("put-meta");
+    let (db, app) = open(&dir);
+    let put = |headers: Vec<(&'static str, &'static str)>| {
+        let mut request = Request::builder().method(Method::PUT).uri("/key/doc");
+        for (name, value) in headers {
+            request = request.header(name, value);
+        }
+        app.clone().oneshot(request.body(Body::from("v")).unwrap())
+    };
+
+    put(vec![("content-type", "text/plain"), ("x-kline-tags", "env=prod")]).await.unwrap();
+    put(vec![("content-type", "application/json")]).await.unwrap();
+    let meta = db.meta(b"doc").unwrap().unwrap();
+    assert_eq!(meta.content_type.as_deref(), Some("application/json"));
+    assert_eq!(meta.tags.get("env").map(String::as_str), Some("prod"));
+
+    put(vec![("x-kline-tags", "owner=bob"), ("x-kline-ttl", "60")]).await.unwrap();
+    put(vec![]).await.unwrap();
+    let meta = db.meta(b"doc").unwrap().unwrap();
+    assert_eq!(meta.content_type.as_deref(), Some("application/json"));
+    assert_eq!(meta.tags.into_iter().collect::<Vec<_>>(), vec![("owner".to_string(), "bob".to_string())]);
+}
+
+#[tokio::test]
+async fn queue_pops_wait_for_an_item_on_any_of_their_keys() {
+    let dir = TempDir::new("queue-wait");
+    let (db, app) = open(&dir);
+    db.rpush(b"low", vec![b"old".to_vec()]).unwrap();
+
+    let (_, body) = call_json(&app, Method::POST, "/queue/high/pop?keys=low", Body::empty()).await;
+    assert_eq!((body["key"].as_str(), body["value"].as_str()), (Some("low"), Some("old")));
+
+    let waiting = tokio::spawn({
+        let app = app.clone();
+        async move { call_json(&app, Method::POST, "/queue/high/pop?timeout=5&keys=low", Body::empty()).await }
+    });
+    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    assert!(!waiting.is_finished());
+    db.rpush(b"high", vec![b"new".to_vec()]).unwrap();
+    let (status, body) = waiting.await.unwrap();
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!((body["key"].as_str(), body["value"].as_str()), (Some("high"), Some("new")));
+}
+
+#[tokio::test]
+async fn type_conflicts_are_409_with_their_own_error_codes() {
+    let dir = TempDir::new("type-conflicts");
+    let (db, app) = open(&dir);
+    db.rpush(b"list", vec![b"a".to_vec()]).unwrap();
+    db.put(b"word".to_vec(), b"hello".to_vec()).unwrap();
+    db.put(b"max".to_vec(), i64::MAX.to_string().into_bytes()).unwrap();
+
+    for (method, uri, error) in [
+        (Method::PUT, "/key/list", "wrong_type"),
+        (Method::POST, "/key/list/append", "wrong_type"),
+        (Method::POST, "/key/list/incr", "wrong_type"),
+        (Method::PUT, "/hash/word/field", "wrong_type"),
+        (Method::POST, "/key/word/incr", "not_a_number"),
+        (Method::POST, "/key/max/incr", "numeric_overflow"),
+    ] {
+        let (status, body) = call_json(&app, method, uri, "x").await;
+        assert_eq!((status, body["error"].as_str()), (StatusCode::CONFLICT, Some(error)), "{}", uri);
+    }
+    let (status, body) = call_json(&app, Method::POST, "/key/word/incr?by=one", Body::empty()).await;
+    assert_eq!((status, body["error"].as_str()), (StatusCode::BAD_REQUEST, Some("bad_request")));
+    assert_eq!(db.get(b"max").unwrap(), Some(i64::MAX.to_string().into_bytes()));
+}
+
+#[tokio::test]
+async fn keys_with_slashes_or_binary_bytes_can_be_addressed() {
+    let dir = TempDir::new("key-paths");
+    let (db, app) = open(&dir);
+
+    let (status, _) = call(&app, Method::PUT, "/key/tenant/a/b", "nested").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(db.get(b"tenant/a/b").unwrap(), Some(b"nested".to_vec()));
+    let (_, body) = call_json(&app, Method::GET, "/key/tenant/a/b", Body::empty()).await;
+    assert_eq!((body["key"].as_str(), body["value"].as_str()), (Some("tenant/a/b"), Some("nested")));
+    let (_, raw) = call(&app, Method::GET, "/raw/tenant/a/b", Body::empty()).await;
+    assert_eq!(raw, b"nested");
+
+    // PUT has no `/meta` sub-route, so this writes the key `doc/meta`; GET
+    // reaches it only through `key_encoding`.
+    call(&app, Method::PUT, "/key/doc/meta", "m").await;
+    assert_eq!(db.get(b"doc/meta").unwrap(), Some(b"m".to_vec()));
+    let (status, body) = call_json(&app, Method::GET, "/key/doc/meta", Body::empty()).await;
+    assert_eq!((status, body["error"].as_str()), (StatusCode::NOT_FOUND, Some("key_not_found")));
+    let (_, body) = call_json(&app, Method::GET, "/key/646f632f6d657461?key_encoding=hex", Body::empty()).await;
+    assert_eq!(body["value"], "m");
+
+    call(&app, Method::PUT, "/key/ff00?key_encoding=hex", "binary").await;
+    assert_eq!(db.get(&[0xff, 0x00]).unwrap(), Some(b"binary".to_vec()));
+    let (_, body) = call_json(&app, Method::GET, "/key/%2FwA%3D?key_encoding=base64", Body::empty()).await;
+    assert_eq!(body["value"], "binary");
+    let (status, body) = call_json(&app, Method::GET, "/key/zz?key_encoding=hex", Body::empty()).await;
+    assert_eq!((status, body["error"].as_str()), (StatusCode::BAD_REQUEST, Some("bad_request")));
+}
+
+#[tokio::test]
+async fn key_listings_page_through_filtered_keys_with_cursors() {
+    let dir = TempDir::new("list-keys");
+    let (db, app) = open(&dir);
+    for key in ["user:1", "user:2", "user:10", "user:3", "users", "other"] {
+        db.put(key.as_bytes().to_vec(), key.as_bytes().to_vec()).unwrap();
+    }
+
+    let mut seen = Vec::new();
+    let mut uri = "/keys?prefix=user:&limit=2".to_string();
+    loop {
+        let (status, body) = call_json(&app, Method::GET, &uri, Body::empty()).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(body["count"].as_u64().unwrap() <= 2);
+        seen.extend(body["keys"].as_array().unwrap().iter().map(|key| key.as_str().unwrap().to_string()));
+        match body["next_cursor"].as_str() {
+            Some(cursor) => uri = format!("/keys?prefix=user:&limit=2&cursor={}", cursor),
+            None => break,
+        }
+    }
+    assert_eq!(seen, vec!["user:1", "user:10", "user:2", "user:3"]);
+
+    let (_, body) = call_json(&app, Method::GET, "/keys?pattern=user:?&values=true", Body::empty()).await;
+    assert_eq!(body["keys"], serde_json::json!(["user:1", "user:2", "user:3"]));
+    assert_eq!(body["values"], body["keys"]);
+    assert_eq!(body["next_cursor"], Value::Null);
+
+    let (_, body) = call_json(&app, Method::GET, "/keys?prefix=user&count_only=true", Body::empty()).await;
+    assert_eq!(body, serde_json::json!({ "count": 5 }));
+
+    for uri in ["/keys?cursor=!!", "/keys?limit=0"] {
+        let (status, body) = call_json(&app, Method::GET, uri, Body::empty()).await;
+        assert_eq!((status, body["error"].as_str()), (StatusCode::BAD_REQUEST, Some("bad_request")), "{}", uri);
+    }
+}
+
+#[tokio::test]
+async fn bulk_writes_report_a_status_per_key() {
+    let dir = TempDir::new("bulk");
+    let (db, app) = open(&dir);
+    db.rpush(b"list", vec![b"x".to_vec()]).unwrap();
+
+    let entries = json!({ "entries": { "a": "1", "b": "2", "list": "3" } });
+    let (status, body) = post_json(&app, "/mput", entries.clone()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!((body["succeeded"].as_u64(), body["failed"].as_u64()), (Some(2), Some(1)));
+    let item = &body["items"][2];
+    assert_eq!((item["key"].as_str(), item["status"].as_u64(), item["error"].as_str()), (Some("list"), Some(409), Some("wrong_type")));
+
+    db.delete(b"a").unwrap();
+    let (status, body) = post_json(&app, "/mput", json!({ "entries": entries["entries"], "atomic": true })).await;
+    assert_eq!((status, body["error"].as_str()), (StatusCode::CONFLICT, Some("wrong_type")));
+    assert_eq!(db.get(b"a").unwrap(), None);
+
+    let (_, body) = post_json(&app, "/mget", json!({ "keys": ["b", "a"] })).await;
+    assert_eq!(body["items"][0]["value"], "2");
+    assert_eq!(body["items"][1]["found"], false);
+
+    let (_, body) = post_json(&app, "/mdelete?key_encoding=hex", json!({ "keys": ["62", "61"] })).await;
+    let statuses: Vec<_> = body["items"].as_array().unwrap().iter().map(|item| item["status"].clone()).collect();
+    assert_eq!(statuses, vec![json!(200), json!(404)]);
+    assert_eq!(db.get(b"b").unwrap(), None);
+
+    let (status, body) = post_json(&app, "/mget?key_encoding=hex", json!({ "keys": ["zz"] })).await;
+    assert_eq!((status, body["error"].as_str()), (StatusCode::BAD_REQUEST, Some("bad_request")));
+}
+
+#[tokio::test]
+async fn ttl_routes_set_report_and_clear_expiry() {
+    let dir = TempDir::new("ttl-routes");
+    let (db, app) = open(&dir);
+
+    let (status, _) = call(&app, Method::PUT, "/key/session?ttl=60", "token").await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, body) = call_json(&app, Method::GET, "/key/session/ttl", Body::empty()).await;
+    assert!(body["ttl"].as_u64().is_some_and(|ttl| (59..=60).contains(&ttl)), "{}", body);
+    assert!(body["expires_at"].is_u64());
+
+    let (_, body) = call_json(&app, Method::POST, "/key/session/expire?ttl=600", Body::empty()).await;
+    assert!(body["ttl"].as_u64().is_some_and(|ttl| ttl > 590), "{}", body);
+    let (_, body) = call_json(&app, Method::POST, "/key/session/persist", Body::empty()).await;
+    assert_eq!(body["persisted"], true);
+    let (_, body) = call_json(&app, Method::GET, "/key/session/ttl", Body::empty()).await;
+    assert_eq!((body["ttl"].clone(), body["expires_at"].clone()), (Value::Null, Value::Null));
+
+    for (method, uri) in [
+        (Method::GET, "/key/missing/ttl"),
+        (Method::POST, "/key/missing/expire?ttl=5"),
+        (Method::POST, "/key/missing/persist"),
+    ] {
+        let (status, body) = call_json(&app, method, uri, Body::empty()).await;
+        assert_eq!((status, body["error"].as_str()), (StatusCode::NOT_FOUND, Some("key_not_found")), "{}", uri);
+    }
+
+    db.expire(b"session", std::time::Duration::from_millis(20)).unwrap();
+    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    let (status, _) = call(&app, Method::GET, "/key/session", Body::empty()).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn oversized_bodies_are_413_whether_or_not_their_length_is_declared() {
+    let dir = TempDir::new("body-limits");
+    let mut config = KlineConfig::default();
+    config.limits.max_value_size = 16;
+    let (db, app) = open_with(&dir, config);
+    let put = |body: Body, length: Option<usize>, uri: &'static str| {
+        let mut request = Request::builder().method(Method::PUT).uri(uri);
+        if let Some(length) = length {
+            request = request.header("content-length", length);
+        }
+        app.clone().oneshot(request.body(body).unwrap())
+    };
+
+    let declared = put(Body::from("x".repeat(17)), Some(17), "/key/k").await.unwrap();
+    assert_eq!(declared.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    let chunks = futures_util::stream::iter(["x".repeat(10), "x".repeat(10)].map(Ok::<_, std::io::Error>));
+    let streamed = put(Body::from_stream(chunks), None, "/key/k").await.unwrap();
+    assert_eq!(streamed.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    let body: Value = serde_json::from_slice(&to_bytes(streamed.into_body(), usize::MAX).await.unwrap()).unwrap();
+    assert_eq!(body["error"], "payload_too_large");
+    assert_eq!(db.get(b"k").unwrap(), None);
+
+    // The limit is on the decoded value, so hex may take twice the bytes.
+    let hex = put(Body::from("ab".repeat(16)), Some(32), "/key/k?encoding=hex").await.unwrap();
+    assert_eq!(hex.status(), StatusCode::OK);
+    assert_eq!(db.get(b"k").unwrap(), Some(vec![0xab; 16]));
+
+    let entries: serde_json::Map<_, _> = (0..10_000).map(|i| (format!("key{}", i), json!("v"))).collect();
+    let (status, body) = post_json(&app, "/mput", json!({ "entries": entries })).await;
+    assert_eq!((status, body["error"].as_str()), (StatusCode::PAYLOAD_TOO_LARGE, Some("payload_too_large")));
+    assert_eq!(db.get(b"key0").unwrap(), None);
+}
+
+#[tokio::test]
+async fn rate_limited_clients_get_429_with_retry_after() {
+    let dir = TempDir::new("rate-limit");
+    let mut config = KlineConfig::default();
+    config.server.rate_limit.requests_per_sec = 0.5;
+    config.server.rate_limit.burst = 2;
+    let (_db, app) = open_with(&dir, config);
+    let from = |ip: [u8; 4]| {
+        let request = Request::builder()
+            .uri("/keys")
+            .extension(ConnectInfo(ClientAddr((ip, 4000).into())))
+            .body(Body::empty())
+            .unwrap();
+        app.clone().oneshot(request)
+    };
+
+    for _ in 0..2 {
+        assert_eq!(from([10, 0, 0, 1]).await.unwrap().status(), StatusCode::OK);
+    }
+    let limited = from([10, 0, 0, 1]).await.unwrap();
+    assert_eq!(limited.status(), StatusCode::TOO_MANY_REQUESTS);
+    let retry_after: u64 = limited.headers()["retry-after"].to_str().unwrap().parse().unwrap();
+    assert!((1..=2).contains(&retry_after), "{}", retry_after);
+    let body: Value = serde_json::from_slice(&to_bytes(limited.into_body(), usize::MAX).await.unwrap()).unwrap();
+    assert_eq!(body["error"], "rate_limited");
+    assert_eq!(from([10, 0, 0, 2]).await.unwrap().status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn requests_past_max_concurrent_requests_get_429() {
+    let dir = TempDir::new("concurrency-limit");
+    let mut config = KlineConfig::default();
+    config.server.rate_limit.max_concurrent_requests = 1;
+    let (db, app) = open_with(&dir, config);
+
+    let waiting = tokio::spawn({
+        let app = app.clone();
+        async move { call(&app, Method::POST, "/queue/jobs/pop?timeout=5", Body::empty()).await }
+    });
+    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    let request = Request::builder().uri("/keys").body(Body::empty()).unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(response.headers()["retry-after"], "1");
+
+    db.rpush(b"jobs", vec![b"job".to_vec()]).unwrap();
+    assert_eq!(waiting.await.unwrap().0, StatusCode::OK);
+    let (status, _) = call(&app, Method::GET, "/keys", Body::empty()).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn tokens_are_held_to_their_role_and_prefix() {
+    let dir = TempDir::new("auth");
+    let mut config = KlineConfig::default();
+    config.auth.enabled = true;
+    for (name, role, prefix) in [("reader", Role::ReadOnly, None), ("tenant", Role::ReadWrite, Some("tenant:")), ("admin", Role::Admin, None)] {
+        let prefix = prefix.map(str::to_string);
+        config.auth.tokens.push(TokenConfig { name: name.to_string(), hash: hash_token(name), role, prefix });
+    }
+    let (db, app) = open_with(&dir, config);
+    let send = |method: Method, uri: &str, token: Option<&str>, body: &'static str| {
+        let mut request = Request::builder().method(method).uri(uri).header("content-type", "application/json");
+        if let Some(token) = token {
+            request = request.header("authorization", format!("Bearer {}", token));
+        }
+        let app = app.clone();
+        let request = request.body(Body::from(body)).unwrap();
+        async move {
+            let response = app.oneshot(request).await.unwrap();
+            let status = response.status();
+            let challenge = response.headers().contains_key("www-authenticate");
+            let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            (status, challenge, serde_json::from_slice::<Value>(&body).ok())
+        }
+    };
+
+    for token in [None, Some("guess")] {
+        let (status, challenge, body) = send(Method::GET, "/keys", token, "").await;
+        assert_eq!((status, challenge), (StatusCode::UNAUTHORIZED, true), "{:?}", token);
+        assert_eq!(body.unwrap()["error"], "unauthorized");
+    }
+
+    let allowed = [
+        (Method::PUT, "/key/tenant:1", "tenant", ""),
+        (Method::GET, "/key/tenant:1", "reader", ""),
+        (Method::GET, "/keys?prefix=tenant:", "tenant", ""),
+        (Method::POST, "/mput", "tenant", r#"{"entries": {"tenant:2": "v"}}"#),
+    ];
+    for (method, uri, token, body) in allowed {
+        let (status, _, _) = send(method, uri, Some(token), body).await;
+        assert_eq!(status, StatusCode::OK, "{} as {}", uri, token);
+    }
+
+    let denied = [
+        (Method::PUT, "/key/tenant:1", "reader", ""),
+        (Method::PUT, "/key/other", "tenant", ""),
+        (Method::POST, "/key/tenant:1/rename?to=other", "tenant", ""),
+        (Method::GET, "/keys", "tenant", ""),
+        (Method::POST, "/mput", "tenant", r#"{"entries": {"tenant:3": "v", "other": "v"}}"#),
+        (Method::POST, "/list/other/rpush", "tenant", r#"{"values": ["v"]}"#),
+        (Method::DELETE, "/keys?prefix=tenant:&confirm=tenant:", "tenant", ""),
+        (Method::GET, "/metrics", "reader", ""),
+    ];
+    for (method, uri, token, body) in denied {
+        let (status, _, body) = send(method, uri, Some(token), body).await;
+        assert_eq!((status, body.unwrap()["error"].as_str()), (StatusCode::FORBIDDEN, Some("forbidden")), "{} as {}", uri, token);
+    }
+    assert_eq!(db.get(b"other").unwrap(), None);
+    assert_eq!(db.get(b"tenant:3").unwrap(), None);
+
+    let (status, _, body) = send(Method::DELETE, "/keys?prefix=tenant:&confirm=tenant:", Some("admin"), "").await;
+    assert_eq!((status, body.unwrap()["count"].as_u64()), (StatusCode::OK, Some(2)));
+}
@@ -0,0 +1,672 @@
+use std::path::PathBuf;
+use std::time::Duration;
+use kline::config::RetentionPolicy;
+use kline::storage::{At, ChangeMessage, ChangeOp, ChannelFilter, KeyFilter, MetaUpdate, StreamId};
+use kline::{Kline, KlineConfig, KlineError};
+
+/// A scratch directory removed when dropped.
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("kline-storage-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+        Self(path)
+    }
+
+    fn file(&self, name: &str) -> String {
+        self.0.join(name).to_string_lossy().into_owned()
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+fn open(dir: &TempDir, config: KlineConfig) -> Kline {
+    Kline::open_with_config(&dir.file("kline.db"), config).unwrap()
+}
+
+#[test]
+fn counters_enforce_the_key_size_limit() {
+    let dir = TempDir::new("counter-key-size");
+    let mut config = KlineConfig::default();
+    config.limits.max_key_size = 8;
+    let db = open(&dir, config);
+
+    assert!(matches!(db.incr_by(b"counter:long", 1), Err(KlineError::KeyTooLarge { size: 12, max: 8 })));
+    assert!(matches!(db.incr_by_float(b"counter:long", 1.5), Err(KlineError::KeyTooLarge { .. })));
+    assert!(matches!(db.xgroup_create(b"stream:long", "workers", None), Err(KlineError::KeyTooLarge { .. })));
+    assert_eq!(db.get(b"counter:long").unwrap(), None);
+    assert_eq!(db.incr_by(b"counter", 2).unwrap(), 2);
+}
+
+#[test]
+fn slices_tell_an_empty_value_from_a_missing_key() {
+    let dir = TempDir::new("slice");
+    let db = open(&dir, KlineConfig::default());
+    db.put(b"empty".to_vec(), Vec::new()).unwrap();
+    db.put(b"word".to_vec(), b"hello".to_vec()).unwrap();
+
+    let empty = db.get_slice_with_meta(b"empty", |_| Some((0, 0))).unwrap().unwrap();
+    assert_eq!((empty.total, empty.range), (0, None));
+    assert!(db.get_slice_with_meta(b"missing", |_| Some((0, 0))).unwrap().is_none());
+
+    let word = db.get_slice_with_meta(b"word", |total| Some((1, total - 2))).unwrap().unwrap();
+    assert_eq!((word.total, word.range), (5, Some((1, b"ell".to_vec()))));
+}
+
+#[test]
+fn stream_ids_stop_short_of_the_maximum() {
+    let dir = TempDir::new("stream-max");
+    let db = open(&dir, KlineConfig::default());
+    let fields = || vec![(b"n".to_vec(), b"1".to_vec())];
+
+    assert!(matches!(db.xadd(b"events", Some(StreamId::MAX), fields()), Err(KlineError::InvalidStreamId { .. })));
+    let last = StreamId::new(u64::MAX, u64::MAX - 1);
+    assert_eq!(db.xadd(b"events", Some(last), fields()).unwrap(), last);
+    assert!(matches!(db.xadd(b"events", None, fields()), Err(KlineError::InvalidStreamId { .. })));
+    assert_eq!(StreamId::new(u64::MAX, 0).next(), Some(StreamId::new(u64::MAX, 1)));
+    assert_eq!(StreamId::MAX.next(), None);
+}
+
+#[tokio::test]
+async fn blocking_reads_reject_timeouts_too_long_to_represent() {
+    let dir = TempDir::new("huge-timeout");
+    let db = open(&dir, KlineConfig::default());
+    db.xgroup_create(b"events", "workers", None).unwrap();
+
+    assert!(matches!(db.blpop(&[b"jobs"], Duration::MAX).await, Err(KlineError::InvalidTimeout { .. })));
+    assert!(matches!(
+        db.xread_blocking(b"events", StreamId::MIN, None, Duration::MAX).await,
+        Err(KlineError::InvalidTimeout { .. })
+    ));
+    assert!(matches!(
+        db.xreadgroup_blocking(b"events", "workers", "c", None, Duration::MAX).await,
+        Err(KlineError::InvalidTimeout { .. })
+    ));
+    assert!(matches!(
+        db.acquire_lease_blocking("job", "a", Duration::from_secs(10), Duration::MAX).await,
+        Err(KlineError::InvalidTimeout { .. })
+    ));
+}
+
+#[test]
+fn prefix_and_range_deletes_and_moves_are_recorded_in_history() {
+    let dir = TempDir::new("history-prefix");
+    let mut config = KlineConfig::default();
+    config.history.retention.insert(String::new(), RetentionPolicy { versions: Some(10), max_age_secs: None });
+    let db = open(&dir, config.clone());
+    for key in ["a:1", "a:2", "b:1", "c:1"] {
+        db.put(key.as_bytes().to_vec(), b"v".to_vec()).unwrap();
+    }
+
+    assert_eq!(db.delete_prefix(b"a:").unwrap(), 2);
+    assert_eq!(db.delete_range(b"b:", b"b;").unwrap(), 1);
+    assert_eq!(db.rename_prefix(b"c:", b"d:").unwrap(), 1);
+    let now = At::Version(db.last_seq());
+    let current = |db: &Kline, key: &[u8]| db.get_at(key, now).unwrap().and_then(|version| version.value);
+    for db in [&db, &open(&dir, config)] {
+        assert_eq!(current(db, b"a:1"), None);
+        assert_eq!(current(db, b"b:1"), None);
+        assert_eq!(current(db, b"c:1"), None);
+        assert_eq!(current(db, b"d:1"), Some(b"v".to_vec()));
+        assert_eq!(db.history(b"a:2").unwrap().len(), 2);
+    }
+}
+
+#[test]
+fn read_modify_writes_keep_the_ttl_and_puts_clear_it() {
+    let dir = TempDir::new("ttl-rmw");
+    let db = open(&dir, KlineConfig::default());
+    let minute = Duration::from_secs(60);
+
+    assert_eq!(db.incr_by(b"hits", 1).unwrap(), 1);
+    assert!(db.expire(b"hits", minute).unwrap());
+    db.incr_by(b"hits", 1).unwrap();
+    db.decr_by(b"hits", 1).unwrap();
+    db.incr_by_float(b"hits", 0.5).unwrap();
+    db.put_with_ttl(b"session".to_vec(), b"a".to_vec(), MetaUpdate::default(), minute).unwrap();
+    db.append(b"session", b"b").unwrap();
+    db.set_range(b"session", 0, b"c").unwrap();
+    assert_eq!(db.get_set(b"session".to_vec(), b"d".to_vec()).unwrap(), Some(b"cb".to_vec()));
+    db.put_with_ttl(b"plain".to_vec(), b"a".to_vec(), MetaUpdate::default(), minute).unwrap();
+    db.put(b"plain".to_vec(), b"b".to_vec()).unwrap();
+
+    for db in [&db, &open(&dir, KlineConfig::default())] {
+        assert!(db.ttl(b"hits").unwrap().is_some_and(|ttl| ttl <= minute));
+        assert_eq!(db.get(b"hits").unwrap(), Some(b"1.5".to_vec()));
+        assert!(db.ttl(b"session").unwrap().is_some());
+        assert_eq!(db.ttl(b"plain").unwrap(), None);
+    }
+}
+
+#[test]
+fn lists_hashes_and_sets_are_held_to_the_value_size_limit() {
+    let dir = TempDir::new("collection-size");
+    let mut config = KlineConfig::default();
+    config.limits.max_value_size = 8;
+    let db = open(&dir, config);
+    let too_large = |result: kline::Result<usize>| matches!(result, Err(KlineError::ValueTooLarge { size: 10, max: 8 }));
+
+    assert_eq!(db.rpush(b"list", vec![b"abcde".to_vec()]).unwrap(), 1);
+    assert!(too_large(db.lpush(b"list", vec![b"fghij".to_vec()])));
+    assert_eq!(db.hset(b"hash", vec![(b"ab".to_vec(), b"cde".to_vec())]).unwrap(), 1);
+    assert!(too_large(db.hset(b"hash", vec![(b"fg".to_vec(), b"hij".to_vec())])));
+    assert_eq!(db.hset(b"hash", vec![(b"ab".to_vec(), b"fgh".to_vec()), (b"ab".to_vec(), b"ijklmn".to_vec())]).unwrap(), 0);
+    assert_eq!(db.sadd(b"set", vec![b"abcd".to_vec(), b"abcd".to_vec()]).unwrap(), 1);
+    assert_eq!(db.sadd(b"set", vec![b"e".to_vec()]).unwrap(), 1);
+    assert!(too_large(db.sadd(b"set", vec![b"e".to_vec(), b"fghij".to_vec()])));
+
+    db.rpush(b"source", vec![b"fghij".to_vec()]).unwrap();
+    assert!(matches!(db.lmove(b"source", b"list", true, false), Err(KlineError::ValueTooLarge { .. })));
+    assert_eq!(db.llen(b"source").unwrap(), 1);
+    assert_eq!(db.lrange(b"list", 0, -1).unwrap(), vec![b"abcde".to_vec()]);
+}
+
+#[test]
+fn sorted_sets_are_held_to_the_value_size_limit() {
+    let dir = TempDir::new("zset-size");
+    let mut config = KlineConfig::default();
+    config.limits.max_value_size = 20;
+    let db = open(&dir, config);
+
+    // Each member costs its length plus eight bytes for the score.
+    assert_eq!(db.zadd(b"board", vec![(1.0, b"ab".to_vec()), (2.0, b"ab".to_vec())]).unwrap(), 1);
+    assert_eq!(db.zadd(b"board", vec![(3.0, b"ab".to_vec()), (1.0, b"c".to_vec())]).unwrap(), 1);
+    assert!(matches!(
+        db.zadd(b"board", vec![(1.0, b"d".to_vec())]),
+        Err(KlineError::ValueTooLarge { size: 28, max: 20 })
+    ));
+    assert_eq!(db.zrange(b"board", 0, -1).unwrap(), vec![(b"c".to_vec(), 1.0), (b"ab".to_vec(), 3.0)]);
+}
+
+#[test]
+fn streams_are_held_to_the_value_size_limit_until_trimmed() {
+    let dir = TempDir::new("stream-size");
+    let mut config = KlineConfig::default();
+    config.limits.max_value_size = 40;
+    let db = open(&dir, config);
+    // Each entry costs its fields plus sixteen bytes for the ID.
+    let fields = || vec![(b"n".to_vec(), b"1".to_vec())];
+
+    db.xadd(b"events", None, fields()).unwrap();
+    db.xadd(b"events", None, fields()).unwrap();
+    assert!(matches!(db.xadd(b"events", None, fields()), Err(KlineError::ValueTooLarge { size: 54, max: 40 })));
+    assert_eq!(db.xtrim_max_len(b"events", 1).unwrap(), 1);
+    db.xadd(b"events", None, fields()).unwrap();
+    assert_eq!(db.xlen(b"events").unwrap(), 2);
+}
+
+#[test]
+fn string_writes_refuse_keys_of_other_types() {
+    let dir = TempDir::new("put-type");
+    let db = open(&dir, KlineConfig::default());
+    db.rpush(b"list", vec![b"a".to_vec()]).unwrap();
+    let wrong_type = |result: kline::Result<()>| matches!(result, Err(KlineError::WrongType { .. }));
+
+    assert!(wrong_type(db.put(b"list".to_vec(), b"v".to_vec())));
+    assert!(wrong_type(db.put_with_meta(b"list".to_vec(), b"v".to_vec(), MetaUpdate::default())));
+    assert!(wrong_type(db.put_with_ttl(b"list".to_vec(), b"v".to_vec(), MetaUpdate::default(), Duration::from_secs(60))));
+    assert!(wrong_type(db.mput(vec![(b"other".to_vec(), b"v".to_vec()), (b"list".to_vec(), b"v".to_vec())])));
+    assert!(wrong_type(db.mput_each(vec![(b"list".to_vec(), b"v".to_vec())]).unwrap().remove(0)));
+    assert_eq!(db.get(b"other").unwrap(), None);
+    assert_eq!(db.lrange(b"list", 0, -1).unwrap(), vec![b"a".to_vec()]);
+}
+
+#[tokio::test]
+async fn a_cancelled_wait_does_not_keep_later_waiters_from_waking() {
+    let dir = TempDir::new("cancelled-wait");
+    let db = std::sync::Arc::new(open(&dir, KlineConfig::default()));
+
+    let cancelled = tokio::time::timeout(Duration::from_millis(20), db.blpop(&[b"jobs", b"other"], Duration::from_secs(60))).await;
+    assert!(cancelled.is_err());
+
+    let waiter = tokio::spawn({
+        let db = db.clone();
+        async move { db.blpop(&[b"jobs"], Duration::from_secs(5)).await }
+    });
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    db.rpush(b"jobs", vec![b"a".to_vec()]).unwrap();
+    assert_eq!(waiter.await.unwrap().unwrap(), Some((b"jobs".to_vec(), b"a".to_vec())));
+}
+
+#[test]
+fn sweeping_expired_leases_keeps_fencing_tokens_increasing() {
+    let dir = TempDir::new("lease-sweep");
+    let db = open(&dir, KlineConfig::default());
+    let first = db.acquire_lease("job", "a", Duration::from_secs(1)).unwrap().unwrap();
+    std::thread::sleep(Duration::from_millis(1100));
+
+    db.expire_due().unwrap();
+    assert!(db.lease("job").unwrap().is_none());
+    let second = db.acquire_lease("job", "b", Duration::from_secs(60)).unwrap().unwrap();
+    assert!(second.token > first.token);
+    drop(db);
+    let db = open(&dir, KlineConfig::default());
+    assert_eq!(db.lease("job").unwrap().map(|lease| lease.owner), Some("b".to_string()));
+}
+
+#[test]
+fn a_full_database_still_accepts_overwrites() {
+    let dir = TempDir::new("max-keys");
+    let mut config = KlineConfig::default();
+    config.limits.max_keys = 2;
+    let db = open(&dir, config.clone());
+    db.put(b"a".to_vec(), b"1".to_vec()).unwrap();
+    db.put(b"b".to_vec(), b"1".to_vec()).unwrap();
+
+    assert!(matches!(db.put(b"c".to_vec(), b"1".to_vec()), Err(KlineError::DatabaseFull { current: 2, max: 2 })));
+    db.put(b"a".to_vec(), b"2".to_vec()).unwrap();
+    db.mput(vec![(b"a".to_vec(), b"3".to_vec()), (b"b".to_vec(), b"3".to_vec())]).unwrap();
+    assert!(matches!(
+        db.mput(vec![(b"a".to_vec(), b"4".to_vec()), (b"c".to_vec(), b"4".to_vec())]),
+        Err(KlineError::DatabaseFull { .. })
+    ));
+
+    db.delete(b"b").unwrap();
+    db.put(b"c".to_vec(), b"1".to_vec()).unwrap();
+    let db = open(&dir, config);
+    assert_eq!(db.keys().unwrap().len(), 2);
+    assert_eq!(db.get(b"a").unwrap(), Some(b"3".to_vec()));
+}
+
+#[test]
+fn concurrent_puts_never_pass_max_keys() {
+    let dir = TempDir::new("max-keys-race");
+    let mut config = KlineConfig::default();
+    config.limits.max_keys = 50;
+    let db = std::sync::Arc::new(open(&dir, config));
+
+    let writers: Vec<_> = (0..8)
+        .map(|writer| {
+            let db = db.clone();
+            std::thread::spawn(move || {
+                (0..20).filter(|i| db.put(format!("{}:{}", writer, i).into_bytes(), b"v".to_vec()).is_ok()).count()
+            })
+        })
+        .collect();
+    let stored: usize = writers.into_iter().map(|writer| writer.join().unwrap()).sum();
+    assert_eq!(stored, 50);
+    assert_eq!(db.keys().unwrap().len(), 50);
+}
+
+#[test]
+fn counters_reject_overflow_and_non_numbers_and_survive_replay() {
+    let dir = TempDir::new("counters");
+    let db = open(&dir, KlineConfig::default());
+
+    assert_eq!(db.incr_by(b"n", 5).unwrap(), 5);
+    assert_eq!(db.decr_by(b"n", 7).unwrap(), -2);
+    db.put(b"max".to_vec(), i64::MAX.to_string().into_bytes()).unwrap();
+    assert!(matches!(db.incr_by(b"max", 1), Err(KlineError::NumericOverflow { .. })));
+    assert!(matches!(db.decr_by(b"n", i64::MIN), Err(KlineError::NumericOverflow { .. })));
+    assert!(matches!(db.incr_by_float(b"max", f64::INFINITY), Err(KlineError::NumericOverflow { .. })));
+    db.put(b"word".to_vec(), b"ten".to_vec()).unwrap();
+    assert!(matches!(db.incr_by(b"word", 1), Err(KlineError::NotANumber { .. })));
+    assert!(matches!(db.incr_by_float(b"word", 1.0), Err(KlineError::NotANumber { .. })));
+    assert_eq!(db.incr_by_float(b"f", 0.25).unwrap(), 0.25);
+
+    let db = open(&dir, KlineConfig::default());
+    assert_eq!(db.get(b"n").unwrap(), Some(b"-2".to_vec()));
+    assert_eq!(db.get(b"max").unwrap(), Some(i64::MAX.to_string().into_bytes()));
+    assert_eq!(db.incr_by_float(b"f", 0.5).unwrap(), 0.75);
+}
+
+#[test]
+fn byte_string_edits_replay_from_the_log_and_after_compaction() {
+    let dir = TempDir::new("byte-strings");
+    let mut db = open(&dir, KlineConfig::default());
+
+    assert_eq!(db.append(b"log", b"hello").unwrap(), 5);
+    assert_eq!(db.append(b"log", b" world").unwrap(), 11);
+    assert_eq!(db.set_range(b"log", 0, b"J").unwrap(), 11);
+    assert_eq!(db.set_range(b"pad", 3, b"x").unwrap(), 4);
+    assert_eq!(db.get_range(b"log", -5, -1).unwrap(), Some(b"world".to_vec()));
+    assert_eq!(db.get_range(b"log", 4, 2).unwrap(), Some(Vec::new()));
+    assert_eq!(db.get_range(b"missing", 0, -1).unwrap(), None);
+    assert_eq!(db.get_set(b"old".to_vec(), b"new".to_vec()).unwrap(), None);
+    assert_eq!(db.get_set(b"old".to_vec(), b"newer".to_vec()).unwrap(), Some(b"new".to_vec()));
+    db.rpush(b"list", vec![b"a".to_vec()]).unwrap();
+    assert!(matches!(db.append(b"list", b"x"), Err(KlineError::WrongType { .. })));
+
+    let check = |db: &Kline| {
+        assert_eq!(db.get(b"log").unwrap(), Some(b"Jello world".to_vec()));
+        assert_eq!(db.get(b"pad").unwrap(), Some(b"\0\0\0x".to_vec()));
+        assert_eq!(db.strlen(b"old").unwrap(), 5);
+    };
+    check(&db);
+    check(&open(&dir, KlineConfig::default()));
+    db.compact().unwrap();
+    check(&open(&dir, KlineConfig::default()));
+}
+
+#[test]
+fn lists_hashes_and_sets_replay_from_the_log_and_after_compaction() {
+    let dir = TempDir::new("collections-replay");
+    let mut db = open(&dir, KlineConfig::default());
+    let items = |items: &[&str]| items.iter().map(|item| item.as_bytes().to_vec()).collect::<Vec<_>>();
+
+    assert_eq!(db.rpush(b"list", items(&["b", "c", "b"])).unwrap(), 3);
+    assert_eq!(db.lpush(b"list", items(&["x", "a"])).unwrap(), 5);
+    assert_eq!(db.rpop(b"list", 1).unwrap(), items(&["b"]));
+    assert_eq!(db.lrem(b"list", 0, b"x").unwrap(), 1);
+    assert_eq!(db.lmove(b"list", b"done", true, false).unwrap(), Some(b"a".to_vec()));
+    assert_eq!(db.hset(b"hash", vec![(b"f".to_vec(), b"1".to_vec()), (b"g".to_vec(), b"2".to_vec())]).unwrap(), 2);
+    assert_eq!(db.hdel(b"hash", &items(&["g", "h"])).unwrap(), 1);
+    assert_eq!(db.sadd(b"set", items(&["a", "b", "c"])).unwrap(), 3);
+    assert_eq!(db.srem(b"set", &items(&["b"])).unwrap(), 1);
+    assert!(matches!(db.hset(b"list", vec![(b"f".to_vec(), b"1".to_vec())]), Err(KlineError::WrongType { .. })));
+    assert!(matches!(db.get(b"set"), Err(KlineError::WrongType { .. })));
+
+    let check = |db: &Kline| {
+        assert_eq!(db.lrange(b"list", 0, -1).unwrap(), items(&["b", "c"]));
+        assert_eq!(db.lrange(b"done", 0, -1).unwrap(), items(&["a"]));
+        assert_eq!(db.hgetall(b"hash").unwrap(), vec![(b"f".to_vec(), b"1".to_vec())]);
+        let mut members = db.smembers(b"set").unwrap();
+        members.sort();
+        assert_eq!(members, items(&["a", "c"]));
+        assert_eq!(db.key_type(b"set").unwrap(), Some("set"));
+    };
+    check(&db);
+    check(&open(&dir, KlineConfig::default()));
+    db.compact().unwrap();
+    check(&open(&dir, KlineConfig::default()));
+}
+
+#[test]
+fn sorted_sets_replay_from_the_log_and_after_compaction() {
+    let dir = TempDir::new("zset-replay");
+    let mut db = open(&dir, KlineConfig::default());
+    let members = |members: &[(f64, &str)]| members.iter().map(|(score, m)| (*score, m.as_bytes().to_vec())).collect::<Vec<_>>();
+
+    assert_eq!(db.zadd(b"board", members(&[(3.0, "c"), (1.0, "a"), (2.0, "b"), (5.0, "e")])).unwrap(), 4);
+    assert_eq!(db.zadd(b"board", members(&[(4.0, "a")])).unwrap(), 0);
+    assert_eq!(db.zrem(b"board", &[b"e".to_vec()]).unwrap(), 1);
+    assert_eq!(db.zpopmin(b"board", 1).unwrap(), vec![(b"b".to_vec(), 2.0)]);
+    assert!(matches!(db.zadd(b"board", members(&[(f64::NAN, "x")])), Err(KlineError::NotANumber { .. })));
+
+    let check = |db: &Kline| {
+        assert_eq!(db.zrange(b"board", 0, -1).unwrap(), vec![(b"c".to_vec(), 3.0), (b"a".to_vec(), 4.0)]);
+        assert_eq!(db.zrank(b"board", b"a").unwrap(), Some(1));
+        assert_eq!(db.zscore(b"board", b"b").unwrap(), None);
+        assert_eq!(db.zrange_by_score(b"board", 3.5, f64::INFINITY, None).unwrap(), vec![(b"a".to_vec(), 4.0)]);
+    };
+    check(&db);
+    check(&open(&dir, KlineConfig::default()));
+    db.compact().unwrap();
+    check(&open(&dir, KlineConfig::default()));
+}
+
+#[test]
+fn streams_and_consumer_groups_replay_from_the_log_and_after_compaction() {
+    let dir = TempDir::new("stream-replay");
+    let mut db = open(&dir, KlineConfig::default());
+    let fields = |n: &str| vec![(b"n".to_vec(), n.as_bytes().to_vec())];
+    let id = |ms| StreamId::new(ms, 0);
+
+    for ms in 1..=4 {
+        db.xadd(b"events", Some(id(ms)), fields(&ms.to_string())).unwrap();
+    }
+    assert!(matches!(db.xadd(b"events", Some(id(4)), fields("dup")), Err(KlineError::InvalidStreamId { .. })));
+    assert!(db.xadd(b"events", None, fields("5")).unwrap() > id(4));
+    assert_eq!(db.xtrim_max_len(b"events", 4).unwrap(), 1);
+    assert!(db.xgroup_create(b"events", "workers", Some(StreamId::MIN)).unwrap());
+    assert!(!db.xgroup_create(b"events", "workers", None).unwrap());
+    let delivered: Vec<StreamId> = db.xreadgroup(b"events", "workers", "w1", Some(2)).unwrap().into_iter().map(|(id, _)| id).collect();
+    assert_eq!(delivered, vec![id(2), id(3)]);
+    assert_eq!(db.xack(b"events", "workers", &[id(2), id(9)]).unwrap(), 1);
+    assert!(matches!(db.xreadgroup(b"events", "nobody", "w1", None), Err(KlineError::NoSuchGroup { .. })));
+
+    let check = |db: &Kline| {
+        let ids: Vec<StreamId> = db.xrange(b"events", StreamId::MIN, StreamId::MAX, None).unwrap().into_iter().map(|(id, _)| id).collect();
+        assert_eq!((ids.len(), ids[0]), (4, id(2)));
+        assert_eq!(db.xread(b"events", id(3), Some(1)).unwrap(), vec![(id(4), fields("4"))]);
+        let pending = db.xpending(b"events", "workers").unwrap();
+        assert_eq!(pending.len(), 1);
+        assert_eq!((pending[0].0, pending[0].1.consumer.as_str()), (id(3), "w1"));
+        // The group carries on after the entries it already delivered.
+        assert_eq!(db.xreadgroup(b"events", "workers", "w2", Some(1)).unwrap(), vec![(id(4), fields("4"))]);
+    };
+    check(&open(&dir, KlineConfig::default()));
+    db.compact().unwrap();
+    check(&open(&dir, KlineConfig::default()));
+}
+
+#[test]
+fn subscribers_see_matching_changes_in_commit_order() {
+    let dir = TempDir::new("subscribe");
+    let db = open(&dir, KlineConfig::default());
+    let mut users = db.subscribe(KeyFilter::Prefix(b"user:".to_vec()));
+    let mut exact = db.subscribe(KeyFilter::Exact(b"user:1".to_vec()));
+
+    db.put(b"user:1".to_vec(), b"ada".to_vec()).unwrap();
+    db.put(b"other".to_vec(), b"x".to_vec()).unwrap();
+    db.rpush(b"user:list", vec![b"a".to_vec()]).unwrap();
+    db.delete(b"user:1").unwrap();
+
+    let mut events = Vec::new();
+    while let Some(ChangeMessage::Event(event)) = users.try_recv() {
+        events.push(event);
+    }
+    let ops: Vec<(ChangeOp, String)> = events.iter().map(|e| (e.op, String::from_utf8_lossy(&e.key).into_owned())).collect();
+    assert_eq!(ops, vec![
+        (ChangeOp::Put, "user:1".to_string()),
+        (ChangeOp::Update, "user:list".to_string()),
+        (ChangeOp::Delete, "user:1".to_string()),
+    ]);
+    assert_eq!(events[0].value, Some(b"ada".to_vec()));
+    assert_eq!((events[1].value.as_ref(), events[2].value.as_ref()), (None, None));
+    assert!(events.windows(2).all(|pair| pair[0].seq < pair[1].seq));
+    assert_eq!(events[2].seq, db.last_seq());
+
+    let mut exact_ops = Vec::new();
+    while let Some(ChangeMessage::Event(event)) = exact.try_recv() {
+        exact_ops.push(event.op);
+    }
+    assert_eq!(exact_ops, vec![ChangeOp::Put, ChangeOp::Delete]);
+}
+
+#[test]
+fn changes_since_resumes_across_restarts_until_segments_are_dropped() {
+    let dir = TempDir::new("changes-since");
+    let mut config = KlineConfig::default();
+    config.storage.retained_segments = 1;
+    let changes = |db: &Kline, seq: u64| -> kline::Result<Vec<(u64, String)>> {
+        db.changes_since(seq)?.map(|entry| entry.map(|entry| (entry.seq, entry.op))).collect()
+    };
+
+    let mut db = open(&dir, config.clone());
+    db.put(b"a".to_vec(), b"1".to_vec()).unwrap();
+    let after_a = db.last_seq();
+    db.rpush(b"list", vec![b"x".to_vec()]).unwrap();
+    db.delete(b"a").unwrap();
+    let expected = vec![(after_a + 1, "rpush".to_string()), (after_a + 2, "delete".to_string())];
+    assert_eq!(changes(&db, after_a).unwrap(), expected);
+
+    db.compact().unwrap();
+    drop(db);
+    let mut db = open(&dir, config);
+    assert_eq!(db.last_seq(), after_a + 2);
+    assert_eq!(changes(&db, after_a).unwrap(), expected);
+
+    db.put(b"b".to_vec(), b"2".to_vec()).unwrap();
+    db.compact().unwrap();
+    assert!(matches!(changes(&db, after_a), Err(KlineError::ChangesCompacted { .. })));
+    assert_eq!(changes(&db, after_a + 2).unwrap(), vec![(after_a + 3, "put".to_string())]);
+}
+
+#[test]
+fn channel_messages_reach_name_and_pattern_subscribers_only() {
+    let dir = TempDir::new("pubsub");
+    let db = open(&dir, KlineConfig::default());
+    let mut named = db.subscribe_channels(vec![ChannelFilter::Channel("news".to_string())]);
+    let mut pattern = db.subscribe_channels(vec![ChannelFilter::Pattern("news.*".to_string())]);
+
+    assert_eq!(db.publish("news", b"a".to_vec()), 1);
+    assert_eq!(db.publish("news.sport", b"b".to_vec()), 1);
+    assert_eq!(db.publish("weather", b"c".to_vec()), 0);
+
+    let message = named.try_recv().unwrap();
+    assert_eq!((message.channel.as_str(), message.pattern, message.payload), ("news", None, b"a".to_vec()));
+    assert!(named.try_recv().is_none());
+    let message = pattern.try_recv().unwrap();
+    assert_eq!((message.channel.as_str(), message.pattern.as_deref()), ("news.sport", Some("news.*")));
+    assert!(pattern.try_recv().is_none());
+
+    drop(named);
+    assert_eq!(db.publish("news", b"d".to_vec()), 0);
+    assert_eq!(db.last_seq(), 0);
+}
+
+#[tokio::test]
+async fn leases_belong_to_one_owner_and_tokens_survive_restarts() {
+    let dir = TempDir::new("leases");
+    let db = std::sync::Arc::new(open(&dir, KlineConfig::default()));
+    let minute = Duration::from_secs(60);
+
+    let held = db.acquire_lease("job", "a", minute).unwrap().unwrap();
+    assert!(db.acquire_lease("job", "b", minute).unwrap().is_none());
+    assert_eq!(db.acquire_lease("job", "a", minute).unwrap().unwrap().token, held.token);
+    assert!(matches!(db.renew_lease("job", "b", minute), Err(KlineError::LeaseNotHeld { .. })));
+    assert!(matches!(db.release_lease("job", "b"), Err(KlineError::LeaseNotHeld { .. })));
+    assert!(db.renew_lease("job", "a", minute * 2).unwrap().expires_at > held.expires_at);
+
+    let waiter = tokio::spawn({
+        let db = db.clone();
+        async move { db.acquire_lease_blocking("job", "b", minute, Duration::from_secs(5)).await }
+    });
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    db.release_lease("job", "a").unwrap();
+    let taken = waiter.await.unwrap().unwrap().unwrap();
+    assert_eq!(taken.owner, "b");
+    assert!(taken.token > held.token);
+
+    drop(db);
+    let db = open(&dir, KlineConfig::default());
+    assert_eq!(db.lease("job").unwrap().map(|lease| lease.token), Some(taken.token));
+    assert!(db.acquire_lease("other", "c", minute).unwrap().unwrap().token > taken.token);
+}
+
+#[test]
+fn history_keeps_the_configured_versions_across_restarts_and_compaction() {
+    let dir = TempDir::new("history");
+    let mut config = KlineConfig::default();
+    config.history.retention.insert("audit:".to_string(), RetentionPolicy { versions: Some(3), max_age_secs: None });
+    let mut db = open(&dir, config.clone());
+
+    let mut seqs = Vec::new();
+    for value in ["v1", "v2", "v3", "v4"] {
+        db.put(b"audit:x".to_vec(), value.as_bytes().to_vec()).unwrap();
+        seqs.push(db.last_seq());
+    }
+    db.delete(b"audit:x").unwrap();
+    db.put(b"plain".to_vec(), b"v".to_vec()).unwrap();
+    db.delete(b"plain").unwrap();
+
+    let check = |db: &Kline| {
+        let values: Vec<_> = db.history(b"audit:x").unwrap().into_iter().map(|version| version.value).collect();
+        assert_eq!(values, vec![Some(b"v3".to_vec()), Some(b"v4".to_vec()), None]);
+        let at = |seq| db.get_at(b"audit:x", At::Version(seq)).unwrap().map(|version| version.value);
+        assert_eq!(at(seqs[1]), None);
+        assert_eq!(at(seqs[2]), Some(Some(b"v3".to_vec())));
+        assert_eq!(at(seqs[3] + 1), Some(None));
+        assert!(db.history(b"plain").unwrap().is_empty());
+    };
+    check(&db);
+    check(&open(&dir, config.clone()));
+    db.compact().unwrap();
+    check(&open(&dir, config));
+}
+
+#[test]
+fn renames_and_copies_replay_from_the_log_and_after_compaction() {
+    let dir = TempDir::new("renames");
+    let mut db = open(&dir, KlineConfig::default());
+    let meta = MetaUpdate { content_type: Some("text/plain".to_string()), tags: None };
+    db.put_with_meta(b"a".to_vec(), b"1".to_vec(), meta).unwrap();
+    db.put(b"b".to_vec(), b"2".to_vec()).unwrap();
+    for key in ["old:1", "old:2", "new:1"] {
+        db.put(key.as_bytes().to_vec(), key.as_bytes().to_vec()).unwrap();
+    }
+
+    assert!(matches!(db.rename(b"a", b"b", false), Err(KlineError::KeyExists { .. })));
+    assert!(matches!(db.rename(b"missing", b"c", false), Err(KlineError::KeyNotFound { .. })));
+    db.rename(b"a", b"c", false).unwrap();
+    db.copy(b"c", b"d").unwrap();
+    assert!(matches!(db.copy(b"c", b"b"), Err(KlineError::KeyExists { .. })));
+    assert!(matches!(db.rename_prefix(b"old:", b"new:"), Err(KlineError::KeyExists { .. })));
+    db.delete(b"new:1").unwrap();
+    assert_eq!(db.rename_prefix(b"old:", b"new:").unwrap(), 2);
+
+    let check = |db: &Kline| {
+        assert_eq!(db.get(b"a").unwrap(), None);
+        assert_eq!(db.get(b"c").unwrap(), Some(b"1".to_vec()));
+        assert_eq!(db.get(b"d").unwrap(), Some(b"1".to_vec()));
+        assert_eq!(db.meta(b"d").unwrap().unwrap().content_type.as_deref(), Some("text/plain"));
+        assert_eq!(db.get(b"old:1").unwrap(), None);
+        assert_eq!(db.get(b"new:2").unwrap(), Some(b"old:2".to_vec()));
+    };
+    check(&db);
+    check(&open(&dir, KlineConfig::default()));
+    db.compact().unwrap();
+    check(&open(&dir, KlineConfig::default()));
+}
+
+#[test]
+fn range_and_prefix_deletes_replay_from_the_log_and_after_compaction() {
+    let dir = TempDir::new("range-deletes");
+    let mut db = open(&dir, KlineConfig::default());
+    for key in ["a", "b", "c", "d", "e", "log:1", "log:2", "logs"] {
+        db.put(key.as_bytes().to_vec(), b"v".to_vec()).unwrap();
+    }
+
+    assert_eq!(db.delete_range(b"b", b"d").unwrap(), 2);
+    assert_eq!(db.delete_range(b"b", b"d").unwrap(), 0);
+    assert_eq!(db.delete_prefix(b"log:").unwrap(), 2);
+    assert_eq!(db.delete_range(b"lz", b"").unwrap(), 0);
+    assert_eq!(db.delete_range(b"e", b"").unwrap(), 2);
+
+    let check = |db: &Kline| {
+        let keys: Vec<_> = ["a", "b", "c", "d", "e", "log:1", "logs"]
+            .into_iter()
+            .filter(|key| db.get(key.as_bytes()).unwrap().is_some())
+            .collect();
+        assert_eq!(keys, vec!["a", "d"]);
+    };
+    check(&db);
+    check(&open(&dir, KlineConfig::default()));
+    db.compact().unwrap();
+    check(&open(&dir, KlineConfig::default()));
+}
+
+#[test]
+fn expired_keys_read_as_missing_and_are_swept_for_good() {
+    let dir = TempDir::new("expiry");
+    let db = open(&dir, KlineConfig::default());
+    let short = Duration::from_millis(50);
+    db.put_with_ttl(b"short".to_vec(), b"v".to_vec(), MetaUpdate::default(), short).unwrap();
+    db.put_with_ttl(b"long".to_vec(), b"v".to_vec(), MetaUpdate::default(), Duration::from_secs(60)).unwrap();
+    db.put(b"kept".to_vec(), b"v".to_vec()).unwrap();
+    assert!(db.expire(b"kept", short).unwrap());
+    assert!(db.persist(b"kept").unwrap());
+    assert!(!db.persist(b"kept").unwrap());
+    assert!(!db.expire(b"missing", short).unwrap());
+    assert!(matches!(db.expire(b"kept", Duration::ZERO), Err(KlineError::InvalidTtl { .. })));
+
+    std::thread::sleep(short * 2);
+    assert_eq!(db.get(b"short").unwrap(), None);
+    assert!(matches!(db.ttl(b"short"), Err(KlineError::KeyNotFound { .. })));
+    assert_eq!(db.expire_due().unwrap(), 1);
+    assert_eq!(db.expire_due().unwrap(), 0);
+
+    drop(db);
+    let db = open(&dir, KlineConfig::default());
+    assert_eq!(db.get(b"short").unwrap(), None);
+    assert!(db.ttl(b"long").unwrap().is_some_and(|ttl| ttl > Duration::from_secs(50)));
+    assert_eq!(db.ttl(b"kept").unwrap(), None);
+    assert_eq!(db.get(b"kept").unwrap(), Some(b"v".to_vec()));
+}
@@ -0,0 +1,205 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use kline::config::TlsConfig;
+use kline::{http, Kline, KlineConfig};
+use rcgen::{BasicConstraints, CertificateParams, CertifiedKey, ExtendedKeyUsagePurpose, IsCa, KeyPair};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer, ServerName};
+use rustls::{ClientConfig, RootCertStore};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_rustls::TlsConnector;
+
+/// A scratch directory removed when dropped.
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("kline-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+        Self(path)
+    }
+
+    fn file(&self, name: &str) -> String {
+        self.0.join(name).to_string_lossy().into_owned()
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+fn self_signed() -> CertifiedKey {
+    rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap()
+}
+
+fn write_cert(dir: &TempDir, cert: &CertifiedKey) -> TlsConfig {
+    let (cert_path, key_path) = (dir.file("server.pem"), dir.file("server.key"));
+    std::fs::write(&cert_path, cert.cert.pem()).unwrap();
+    std::fs::write(&key_path, cert.key_pair.serialize_pem()).unwrap();
+    TlsConfig { cert_path, key_path, client_ca: None }
+}
+
+/// Starts a server for `tls` on a free port and returns its address.
+async fn start(dir: &TempDir, tls: TlsConfig) -> std::net::SocketAddr {
+    start_with(dir, tls, KlineConfig::default()).await
+}
+
+async fn start_with(dir: &TempDir, tls: TlsConfig, mut config: KlineConfig) -> std::net::SocketAddr {
+    config.storage.data_dir = dir.file("data");
+    config.server.tls = Some(tls);
+    std::fs::create_dir_all(&config.storage.data_dir).unwrap();
+    let db = Arc::new(Kline::open_with_config(&dir.file("data/kline.db"), config.clone()).unwrap());
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let app = http::create_router(db);
+    tokio::spawn(async move { http::serve(listener, app, &config.server).await.unwrap() });
+    addr
+}
+
+fn client(trusted: &CertifiedKey, identity: Option<&CertifiedKey>) -> TlsConnector {
+    let mut roots = RootCertStore::empty();
+    roots.add(trusted.cert.der().clone()).unwrap();
+    let builder = ClientConfig::builder().with_root_certificates(roots);
+    let config = match identity {
+        Some(identity) => {
+            let key = PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(identity.key_pair.serialize_der()));
+            builder
+                .with_client_auth_cert(vec![CertificateDer::from(identity.cert.der().to_vec())], key)
+                .unwrap()
+        }
+        None => builder.with_no_client_auth(),
+    };
+    TlsConnector::from(Arc::new(config))
+}
+
+/// Sends one HTTP/1.1 request over TLS and returns the raw response.
+async fn request(connector: &TlsConnector, addr: std::net::SocketAddr, head: &str, body: &str) -> std::io::Result<String> {
+    let tcp = TcpStream::connect(addr).await?;
+    let mut tls = connector.connect(ServerName::try_from("localhost").unwrap(), tcp).await?;
+    let request = format!(
+        "{}\r\nHost: localhost\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        head,
+        body.len(),
+        body
+    );
+    tls.write_all(request.as_bytes()).await?;
+    let mut response = String::new();
+    tls.read_to_string(&mut response).await?;
+    Ok(response)
+}
+
+#[tokio::test]
+async fn serves_https_with_a_self_signed_certificate() {
+    let dir = TempDir::new("tls");
+    let cert = self_signed();
+    let addr = start(&dir, write_cert(&dir, &cert)).await;
+    let connector = client(&cert, None);
+
+    let put = request(&connector, addr, "PUT /key/greeting HTTP/1.1", "hello").await.unwrap();
+    assert!(put.starts_with("HTTP/1.1 200"), "{}", put);
+    let get = request(&connector, addr, "GET /key/greeting HTTP/1.1", "").await.unwrap();
+    assert!(get.contains(r#""value":"hello""#), "{}", get);
+
+    // Plain HTTP on the TLS port gets no HTTP response.
+    let mut plain = TcpStream::connect(addr).await.unwrap();
+    plain.write_all(b"GET /key/greeting HTTP/1.1\r\nHost: localhost\r\n\r\n").await.unwrap();
+    let mut response = Vec::new();
+    let _ = tokio::time::timeout(Duration::from_secs(5), plain.read_to_end(&mut response)).await;
+    assert!(!response.starts_with(b"HTTP/1.1"));
+}
+
+#[tokio::test]
+async fn requires_a_client_certificate_signed_by_client_ca() {
+    let dir = TempDir::new("mtls");
+    let server = self_signed();
+    let mut tls = write_cert(&dir, &server);
+
+    let ca_key = KeyPair::generate().unwrap();
+    let mut ca_params = CertificateParams::new(Vec::new()).unwrap();
+    ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    let ca = ca_params.self_signed(&ca_key).unwrap();
+    tls.client_ca = Some(dir.file("ca.pem"));
+    std::fs::write(dir.file("ca.pem"), ca.pem()).unwrap();
+
+    let client_key = KeyPair::generate().unwrap();
+    let mut client_params = CertificateParams::new(vec!["client".to_string()]).unwrap();
+    client_params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ClientAuth];
+    let client_cert = client_params.signed_by(&client_key, &ca, &ca_key).unwrap();
+    let identity = CertifiedKey { cert: client_cert, key_pair: client_key };
+
+    let addr = start(&dir, tls).await;
+
+    let anonymous = request(&client(&server, None), addr, "GET /keys HTTP/1.1", "").await;
+    assert!(anonymous.is_err() || anonymous.is_ok_and(|response| response.is_empty()));
+
+    let stranger = self_signed();
+    let untrusted = request(&client(&server, Some(&stranger)), addr, "GET /keys HTTP/1.1", "").await;
+    assert!(untrusted.is_err() || untrusted.is_ok_and(|response| response.is_empty()));
+
+    let response = request(&client(&server, Some(&identity)), addr, "GET /keys HTTP/1.1", "").await.unwrap();
+    assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn reloads_certificates_on_sighup() {
+    let dir = TempDir::new("reload");
+    let old = self_signed();
+    let tls = write_cert(&dir, &old);
+    let addr = start(&dir, tls).await;
+    assert!(request(&client(&old, None), addr, "GET /keys HTTP/1.1", "").await.is_ok());
+
+    let new = self_signed();
+    write_cert(&dir, &new);
+    hang_up();
+
+    let connector = client(&new, None);
+    let mut reloaded = false;
+    for _ in 0..50 {
+        if request(&connector, addr, "GET /keys HTTP/1.1", "").await.is_ok() {
+            reloaded = true;
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    assert!(reloaded, "server still presents the old certificate");
+    assert!(request(&client(&old, None), addr, "GET /keys HTTP/1.1", "").await.is_err());
+}
+
+#[cfg(unix)]
+fn hang_up() {
+    let status = std::process::Command::new("kill")
+        .args(["-HUP", &std::process::id().to_string()])
+        .status()
+        .unwrap();
+    assert!(status.success());
+}
+
+#[tokio::test]
+async fn handshakes_count_against_max_connections() {
+    let dir = TempDir::new("tls-limit");
+    let cert = self_signed();
+    let mut config = KlineConfig::default();
+    config.server.max_connections = 1;
+    let addr = start_with(&dir, write_cert(&dir, &cert), config).await;
+    let connector = client(&cert, None);
+
+    // A client that connects but never starts its handshake takes the only
+    // slot until it goes away.
+    let stalled = TcpStream::connect(addr).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let waiting = tokio::time::timeout(Duration::from_millis(500), request(&connector, addr, "GET /keys HTTP/1.1", "")).await;
+    assert!(waiting.is_err(), "a second connection was served past max_connections");
+
+    drop(stalled);
+    let response = tokio::time::timeout(Duration::from_secs(5), request(&connector, addr, "GET /keys HTTP/1.1", ""))
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+}